@@ -1,5 +1,45 @@
+#[cfg(feature = "alarm")]
+pub mod alarm;
+pub mod baseline;
+pub mod batch;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod breakdown;
+pub mod byte_budget;
+pub mod circuit_breaker;
+pub mod dns;
+pub mod env;
+pub mod error_class;
+pub mod fail_fast;
+pub mod fake;
+pub mod feedback_ramp;
+pub mod histogram;
+pub mod hook;
+pub mod iteration;
 pub mod metrics;
+pub mod negotiation;
+pub mod predicate;
+pub mod progress;
+pub mod ramp_fidelity;
+pub mod raw_samples;
+pub mod recycle;
+pub mod report;
+pub mod replay;
+pub mod reporter;
+pub mod request;
+pub mod retry;
 pub mod rps_summary;
+#[cfg(feature = "sigv4")]
+pub mod sigv4;
+pub mod slowest;
+pub mod steps;
 pub mod summary;
+pub mod tdigest;
+pub mod think_time;
+pub mod threshold;
+#[cfg(feature = "rustls")]
+pub mod tls;
 pub mod virtual_user;
 pub mod virtual_user_manager;
+pub mod vu_rate;
+pub mod weighted;
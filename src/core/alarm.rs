@@ -0,0 +1,131 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+struct AlarmState {
+    samples: VecDeque<(Instant, f64)>,
+    last_alarm: Option<Instant>,
+    alarms: usize,
+}
+
+#[derive(Debug)]
+pub struct LatencyAlarm {
+    budget: Duration,
+    window: Duration,
+    min_interval: Duration,
+    state: Mutex<AlarmState>,
+}
+
+impl LatencyAlarm {
+    pub fn new(budget: Duration, window: Duration) -> Self {
+        if window.is_zero() {
+            panic!("latency alarm window must not be zero");
+        }
+
+        Self {
+            budget,
+            window,
+            min_interval: window,
+            state: Mutex::new(AlarmState {
+                samples: VecDeque::new(),
+                last_alarm: None,
+                alarms: 0,
+            }),
+        }
+    }
+
+    pub fn rate_limit(mut self, min_interval: Duration) -> Self {
+        self.min_interval = min_interval;
+        self
+    }
+
+    pub fn record(&self, latency: f64) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.samples.push_back((now, latency));
+        self.prune(&mut state, now);
+    }
+
+    pub fn window_p99(&self) -> Option<f64> {
+        let mut state = self.state.lock().unwrap();
+        self.prune(&mut state, Instant::now());
+        p99(&state.samples)
+    }
+
+    pub fn alarms(&self) -> usize {
+        self.state.lock().unwrap().alarms
+    }
+
+    // Logs at most once per `min_interval` however long p99 stays over budget.
+    pub fn check(&self) -> bool {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        self.prune(&mut state, now);
+        let Some(p99) = p99(&state.samples) else {
+            return false;
+        };
+        if p99 <= self.budget.as_secs_f64()
+            || state
+                .last_alarm
+                .is_some_and(|at| now.duration_since(at) < self.min_interval)
+        {
+            return false;
+        }
+
+        state.last_alarm = Some(now);
+        state.alarms += 1;
+        tracing::warn!(
+            p99_ms = p99 * 1000.0,
+            budget_ms = self.budget.as_secs_f64() * 1000.0,
+            window_ms = self.window.as_millis() as u64,
+            samples = state.samples.len(),
+            "p99 latency over budget"
+        );
+        true
+    }
+
+    fn prune(&self, state: &mut AlarmState, now: Instant) {
+        while state
+            .samples
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > self.window)
+        {
+            state.samples.pop_front();
+        }
+    }
+}
+
+fn p99(samples: &VecDeque<(Instant, f64)>) -> Option<f64> {
+    if samples.is_empty() {
+        return None;
+    }
+    let mut latencies: Vec<f64> = samples.iter().map(|(_, latency)| *latency).collect();
+    let rank = ((latencies.len() as f64 * 0.99).ceil() as usize).clamp(1, latencies.len()) - 1;
+    let (_, value, _) = latencies.select_nth_unstable_by(rank, f64::total_cmp);
+    Some(*value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alarm_is_rate_limited_and_windowed() {
+        let alarm = LatencyAlarm::new(Duration::from_millis(10), Duration::from_millis(50))
+            .rate_limit(Duration::from_secs(60));
+        for _ in 0..99 {
+            alarm.record(0.001);
+        }
+        assert!(!alarm.check());
+
+        alarm.record(0.5);
+        alarm.record(0.5);
+        assert!(alarm.check());
+        assert!(!alarm.check());
+        assert_eq!(alarm.alarms(), 1);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(alarm.window_p99(), None);
+    }
+}
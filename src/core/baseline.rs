@@ -0,0 +1,161 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::metrics::Metrics;
+
+#[derive(Debug, Error)]
+pub enum BaselineError {
+    #[error("failed to access baseline file: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid baseline file: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    pub p95: Option<f64>,
+    pub throughput: Option<f64>,
+    pub error_rate: Option<f64>,
+}
+
+impl Baseline {
+    pub fn from_metrics(metrics: &Metrics) -> Self {
+        Self {
+            p95: metrics.latency_percentile(0.95),
+            throughput: metrics.throughput(),
+            error_rate: metrics.error_rate(),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BaselineError> {
+        let contents = fs::read(path)?;
+        Ok(serde_json::from_slice(&contents)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), BaselineError> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn compare(&self, baseline: &Baseline) -> MetricsDiff {
+        MetricsDiff {
+            p95_change: percent_change(self.p95, baseline.p95),
+            throughput_change: percent_change(self.throughput, baseline.throughput),
+            error_rate_change: percent_change(self.error_rate, baseline.error_rate),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Regression {
+    P95,
+    Throughput,
+    ErrorRate,
+}
+
+// All changes are in percent relative to the baseline, so +10.0 means 10% higher.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MetricsDiff {
+    pub p95_change: Option<f64>,
+    pub throughput_change: Option<f64>,
+    pub error_rate_change: Option<f64>,
+}
+
+impl MetricsDiff {
+    pub fn regressions(&self, tolerance: f64) -> Vec<Regression> {
+        let mut regressions = Vec::new();
+        if self.p95_change.is_some_and(|change| change > tolerance) {
+            regressions.push(Regression::P95);
+        }
+        if self
+            .throughput_change
+            .is_some_and(|change| change < -tolerance)
+        {
+            regressions.push(Regression::Throughput);
+        }
+        if self
+            .error_rate_change
+            .is_some_and(|change| change > tolerance)
+        {
+            regressions.push(Regression::ErrorRate);
+        }
+        regressions
+    }
+
+    pub fn is_regression(&self, tolerance: f64) -> bool {
+        !self.regressions(tolerance).is_empty()
+    }
+}
+
+fn percent_change(current: Option<f64>, baseline: Option<f64>) -> Option<f64> {
+    let (current, baseline) = (current?, baseline?);
+    if baseline == 0.0 {
+        return Some(if current == 0.0 { 0.0 } else { f64::INFINITY });
+    }
+    Some((current - baseline) / baseline * 100.0)
+}
+
+impl Metrics {
+    pub fn compare(&self, baseline: &Metrics) -> MetricsDiff {
+        Baseline::from_metrics(self).compare(&Baseline::from_metrics(baseline))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn metrics_with(latency: f64, requests: usize, errors: usize, seconds: u64) -> Metrics {
+        let mut metrics = Metrics::default();
+        for _ in 0..requests {
+            metrics.record_latency(latency);
+            metrics.http_request_time.update(latency);
+        }
+        metrics
+            .counters
+            .errors
+            .store(errors, std::sync::atomic::Ordering::Relaxed);
+        metrics.run_duration = Some(Duration::from_secs(seconds));
+        metrics
+    }
+
+    #[test]
+    fn test_compare_computes_percent_changes() {
+        let baseline = metrics_with(0.1, 1000, 10, 10);
+        let current = metrics_with(0.12, 800, 16, 10);
+
+        let diff = current.compare(&baseline);
+        let p95_change = diff.p95_change.unwrap();
+        assert!((p95_change - 20.0).abs() < 3.0, "p95 change {p95_change}");
+        assert!((diff.throughput_change.unwrap() + 20.0).abs() < 1e-9);
+        assert!((diff.error_rate_change.unwrap() - 100.0).abs() < 1e-9);
+
+        assert_eq!(
+            diff.regressions(10.0),
+            vec![
+                Regression::P95,
+                Regression::Throughput,
+                Regression::ErrorRate
+            ]
+        );
+        assert!(!diff.is_regression(150.0));
+    }
+
+    #[test]
+    fn test_baseline_round_trip() {
+        let path = std::env::temp_dir().join(format!("rperf-baseline-{}.json", std::process::id()));
+        let baseline = Baseline::from_metrics(&metrics_with(0.05, 100, 0, 2));
+        baseline.save(&path).unwrap();
+
+        let loaded = Baseline::load(&path).unwrap();
+        assert_eq!(loaded, baseline);
+        assert_eq!(loaded.compare(&baseline).error_rate_change, Some(0.0));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
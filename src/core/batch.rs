@@ -0,0 +1,111 @@
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetricsBatching {
+    pub max_samples: usize,
+    pub max_delay: Duration,
+}
+
+impl MetricsBatching {
+    pub fn new(max_samples: usize, max_delay: Duration) -> Self {
+        if max_samples == 0 {
+            panic!("metrics batching needs room for at least one sample");
+        }
+
+        Self {
+            max_samples,
+            max_delay,
+        }
+    }
+
+    // Past this a VU waits for the lock instead of letting its backlog grow without bound.
+    fn hard_limit(&self) -> usize {
+        self.max_samples.saturating_mul(4)
+    }
+}
+
+// Samples a VU has collected but not yet written to its shared metrics. Without a batching
+// policy every sample is due immediately, which is the per-request locking behavior.
+#[derive(Debug)]
+pub(crate) struct SampleBuffer<T> {
+    policy: Option<MetricsBatching>,
+    samples: Vec<T>,
+    oldest: Option<Instant>,
+}
+
+impl<T> SampleBuffer<T> {
+    pub(crate) fn new(policy: Option<MetricsBatching>) -> Self {
+        Self {
+            policy,
+            samples: Vec::new(),
+            oldest: None,
+        }
+    }
+
+    pub(crate) fn push(&mut self, sample: T) {
+        self.oldest.get_or_insert_with(Instant::now);
+        self.samples.push(sample);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub(crate) fn is_due(&self) -> bool {
+        match self.policy {
+            _ if self.samples.is_empty() => false,
+            Some(policy) => {
+                self.samples.len() >= policy.max_samples
+                    || self
+                        .oldest
+                        .is_some_and(|oldest| oldest.elapsed() >= policy.max_delay)
+            }
+            None => true,
+        }
+    }
+
+    // Whether a contended lock should be waited for rather than retried on the next sample.
+    pub(crate) fn must_block(&self) -> bool {
+        match self.policy {
+            Some(policy) => self.samples.len() >= policy.hard_limit(),
+            None => !self.samples.is_empty(),
+        }
+    }
+
+    pub(crate) fn take(&mut self) -> Vec<T> {
+        self.oldest = None;
+        std::mem::take(&mut self.samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_is_due_by_size_or_age() {
+        let mut buffer =
+            SampleBuffer::new(Some(MetricsBatching::new(3, Duration::from_millis(30))));
+        buffer.push(1);
+        buffer.push(2);
+        assert!(!buffer.is_due());
+        buffer.push(3);
+        assert!(buffer.is_due());
+        assert!(!buffer.must_block());
+        assert_eq!(buffer.take(), vec![1, 2, 3]);
+        assert!(buffer.is_empty());
+
+        buffer.push(4);
+        std::thread::sleep(Duration::from_millis(40));
+        assert!(buffer.is_due());
+        for sample in 5..=15 {
+            buffer.push(sample);
+        }
+        assert!(buffer.must_block());
+
+        let mut unbatched = SampleBuffer::new(None);
+        assert!(!unbatched.is_due());
+        unbatched.push(1);
+        assert!(unbatched.is_due() && unbatched.must_block());
+    }
+}
@@ -0,0 +1,123 @@
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use tokio::runtime::Runtime;
+
+use super::metrics::Metrics;
+use super::replay::ReplayPacing;
+use super::threshold::RunResult;
+use super::virtual_user_manager::{PlanSegment, RunError, VirtualUserConfig, VirtualUserManager};
+
+// Owns a private multi-threaded runtime so scripts can drive a run without async code.
+pub struct BlockingManager {
+    runtime: Runtime,
+    manager: VirtualUserManager,
+}
+
+impl BlockingManager {
+    pub fn new(config: VirtualUserConfig) -> io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        Ok(Self {
+            runtime,
+            manager: VirtualUserManager::new(config),
+        })
+    }
+
+    pub fn add_plan(&mut self, duration: Duration, target: usize) {
+        self.manager.add_plan(duration, target);
+    }
+
+    pub fn add_segment(&mut self, segment: PlanSegment) {
+        self.manager.add_segment(segment);
+    }
+
+    pub fn add_rps_plan(&mut self, duration: Duration, target_rps: f64) {
+        self.manager.add_rps_plan(duration, target_rps);
+    }
+
+    pub fn run(&mut self) -> Result<RunResult, RunError> {
+        self.runtime.block_on(self.manager.run())
+    }
+
+    pub fn run_rps(&mut self) -> Result<RunResult, RunError> {
+        self.runtime.block_on(self.manager.run_rps())
+    }
+
+    pub fn run_constant(&mut self, vus: usize, duration: Duration) -> Result<RunResult, RunError> {
+        self.runtime
+            .block_on(self.manager.run_constant(vus, duration))
+    }
+
+    pub fn run_iterations(
+        &mut self,
+        iterations: usize,
+        workers: usize,
+    ) -> Result<RunResult, RunError> {
+        self.runtime
+            .block_on(self.manager.run_iterations(iterations, workers))
+    }
+
+    pub fn replay(
+        &mut self,
+        log_path: impl AsRef<Path>,
+        pacing: ReplayPacing,
+    ) -> Result<RunResult, RunError> {
+        self.runtime.block_on(self.manager.replay(log_path, pacing))
+    }
+
+    pub fn get_overall_metrics(&self) -> &Metrics {
+        self.manager.get_overall_metrics()
+    }
+
+    pub fn manager(&mut self) -> &mut VirtualUserManager {
+        &mut self.manager
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    // A std-only server, so the test itself never touches an async runtime.
+    fn start_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { return };
+                std::thread::spawn(move || {
+                    let mut buf = [0u8; 1024];
+                    while stream.read(&mut buf).unwrap_or(0) > 0 {
+                        let response = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok";
+                        if stream.write_all(response.as_bytes()).is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[test]
+    fn test_blocking_run_returns_metrics() {
+        let url = start_server();
+        let mut manager = BlockingManager::new(VirtualUserConfig::new(&url)).unwrap();
+        manager.add_plan(Duration::from_millis(50), 1);
+        manager.add_plan(Duration::from_millis(200), 1);
+
+        let result = manager.run().unwrap();
+        assert!(result.passed());
+        let metrics = manager.get_overall_metrics();
+        assert!(metrics.total_requests() > 0);
+        assert_eq!(
+            metrics.status_code_counts.get(&200),
+            Some(&metrics.total_requests())
+        );
+    }
+}
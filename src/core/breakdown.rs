@@ -0,0 +1,161 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use super::dns::Lookup;
+use super::summary::Summary;
+
+// One request split into phases that add up to the time spent inside the client. reqwest
+// runs the TLS handshake inside its connector, so for HTTPS it is folded into `connect`
+// and `tls` stays zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LatencyBreakdown {
+    pub dns: f64,
+    pub connect: f64,
+    pub tls: f64,
+    pub ttfb: f64,
+    pub transfer: f64,
+}
+
+impl LatencyBreakdown {
+    pub fn total(&self) -> f64 {
+        self.dns + self.connect + self.tls + self.ttfb + self.transfer
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BreakdownSummary {
+    pub dns: Summary,
+    pub connect: Summary,
+    pub tls: Summary,
+    pub ttfb: Summary,
+    pub transfer: Summary,
+}
+
+impl BreakdownSummary {
+    pub fn record(&mut self, breakdown: &LatencyBreakdown) {
+        self.dns.update(breakdown.dns);
+        self.connect.update(breakdown.connect);
+        self.tls.update(breakdown.tls);
+        self.ttfb.update(breakdown.ttfb);
+        self.transfer.update(breakdown.transfer);
+    }
+}
+
+#[derive(Debug, Default)]
+struct PhaseTimes {
+    dns: Duration,
+    connect: Duration,
+}
+
+// Filled in by the resolver and connector below while a request runs in `track`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ConnectTimings(Arc<Mutex<PhaseTimes>>);
+
+tokio::task_local! {
+    static CURRENT: ConnectTimings;
+}
+
+impl ConnectTimings {
+    // Phases are counted from `sent_at`, when the request was handed to the client.
+    pub(crate) fn breakdown(
+        &self,
+        sent_at: Instant,
+        headers_at: Instant,
+        done_at: Instant,
+    ) -> LatencyBreakdown {
+        let phases = self.0.lock().unwrap();
+        let dns = phases.dns.as_secs_f64();
+        let connect = phases.connect.saturating_sub(phases.dns).as_secs_f64();
+        let waiting = headers_at.duration_since(sent_at).as_secs_f64();
+        LatencyBreakdown {
+            dns,
+            connect,
+            tls: 0.0,
+            ttfb: (waiting - dns - connect).max(0.0),
+            transfer: done_at.duration_since(headers_at).as_secs_f64(),
+        }
+    }
+
+    fn current() -> Option<Self> {
+        CURRENT.try_with(Clone::clone).ok()
+    }
+}
+
+pub(crate) async fn track<F: Future>(timings: ConnectTimings, future: F) -> F::Output {
+    CURRENT.scope(timings, future).await
+}
+
+// Times the system resolver, or the configured one when there is one.
+#[derive(Debug)]
+pub(crate) struct TimedResolver(pub(crate) Option<Lookup>);
+
+impl Resolve for TimedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let timings = ConnectTimings::current();
+        let host = name.as_str().to_string();
+        let lookup = self.0.clone();
+        Box::pin(async move {
+            let start = Instant::now();
+            let addrs: Vec<SocketAddr> = match lookup {
+                Some(lookup) => lookup.addrs(&host).await?,
+                None => tokio::net::lookup_host((host.as_str(), 0)).await?.collect(),
+            };
+            if let Some(timings) = timings {
+                timings.0.lock().unwrap().dns = start.elapsed();
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct TimedConnectLayer;
+
+impl<S> Layer<S> for TimedConnectLayer {
+    type Service = TimedConnect<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TimedConnect(inner)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct TimedConnect<S>(S);
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+impl<S, R> Service<R> for TimedConnect<S>
+where
+    S: Service<R>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<S::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        // Captured here because the pool may finish the connection on a background task.
+        let timings = ConnectTimings::current();
+        let connecting = self.0.call(request);
+        Box::pin(async move {
+            let start = Instant::now();
+            let result = connecting.await;
+            if let Some(timings) = timings {
+                timings.0.lock().unwrap().connect = start.elapsed();
+            }
+            result
+        })
+    }
+}
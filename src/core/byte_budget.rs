@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// A cap on request and response body bytes, shared by every VU in a run. VUs check it before
+// each request, so in-flight requests can carry the total past the limit.
+#[derive(Debug)]
+pub struct ByteBudget {
+    limit: u64,
+    transferred: AtomicU64,
+}
+
+impl ByteBudget {
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            transferred: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, bytes: u64) {
+        self.transferred.fetch_add(bytes, Ordering::AcqRel);
+    }
+
+    pub fn is_spent(&self) -> bool {
+        self.transferred() >= self.limit
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    pub fn transferred(&self) -> u64 {
+        self.transferred.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spent_once_limit_is_reached() {
+        let budget = ByteBudget::new(100);
+        budget.record(60);
+        assert!(!budget.is_spent());
+        budget.record(40);
+        assert!(budget.is_spent());
+        assert_eq!(budget.transferred(), 100);
+    }
+}
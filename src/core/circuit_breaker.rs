@@ -0,0 +1,184 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakerTransition {
+    pub from: BreakerState,
+    pub to: BreakerState,
+    pub at: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permit {
+    Allow,
+    Probe,
+    Wait(Duration),
+}
+
+#[derive(Debug)]
+struct BreakerInner {
+    state: BreakerState,
+    outcomes: VecDeque<(Instant, bool)>,
+    opened_at: Option<Instant>,
+    probe_in_flight: bool,
+    transitions: Vec<BreakerTransition>,
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    window: Duration,
+    failure_rate: f64,
+    min_requests: usize,
+    cooldown: Duration,
+    created_at: Instant,
+    inner: Mutex<BreakerInner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(
+        window: Duration,
+        failure_rate: f64,
+        min_requests: usize,
+        cooldown: Duration,
+    ) -> Self {
+        if !(0.0..=1.0).contains(&failure_rate) {
+            panic!("circuit breaker failure rate must be between 0 and 1");
+        }
+
+        Self {
+            window,
+            failure_rate,
+            min_requests: min_requests.max(1),
+            cooldown,
+            created_at: Instant::now(),
+            inner: Mutex::new(BreakerInner {
+                state: BreakerState::Closed,
+                outcomes: VecDeque::new(),
+                opened_at: None,
+                probe_in_flight: false,
+                transitions: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        self.inner.lock().unwrap().state
+    }
+
+    pub fn transitions(&self) -> Vec<BreakerTransition> {
+        self.inner.lock().unwrap().transitions.clone()
+    }
+
+    pub fn permit(&self) -> Permit {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            BreakerState::Closed => Permit::Allow,
+            BreakerState::Open => {
+                let opened_at = inner.opened_at.unwrap_or(self.created_at);
+                let elapsed = opened_at.elapsed();
+                if elapsed < self.cooldown {
+                    return Permit::Wait(self.cooldown - elapsed);
+                }
+                self.transition(&mut inner, BreakerState::HalfOpen);
+                inner.probe_in_flight = true;
+                Permit::Probe
+            }
+            BreakerState::HalfOpen if !inner.probe_in_flight => {
+                inner.probe_in_flight = true;
+                Permit::Probe
+            }
+            BreakerState::HalfOpen => Permit::Wait(self.cooldown.min(Duration::from_millis(10))),
+        }
+    }
+
+    pub fn record(&self, permit: Permit, failure: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        match (inner.state, permit) {
+            (BreakerState::HalfOpen, Permit::Probe) => {
+                inner.probe_in_flight = false;
+                if failure {
+                    self.open(&mut inner);
+                } else {
+                    inner.outcomes.clear();
+                    self.transition(&mut inner, BreakerState::Closed);
+                }
+            }
+            (BreakerState::Closed, _) => {
+                let now = Instant::now();
+                inner.outcomes.push_back((now, failure));
+                while inner
+                    .outcomes
+                    .front()
+                    .is_some_and(|(at, _)| now.duration_since(*at) > self.window)
+                {
+                    inner.outcomes.pop_front();
+                }
+
+                let total = inner.outcomes.len();
+                let failures = inner.outcomes.iter().filter(|(_, failed)| *failed).count();
+                if total >= self.min_requests && failures as f64 / total as f64 > self.failure_rate
+                {
+                    self.open(&mut inner);
+                }
+            }
+            // Results of requests sent before the breaker opened carry no signal.
+            _ => {}
+        }
+    }
+
+    fn open(&self, inner: &mut BreakerInner) {
+        inner.opened_at = Some(Instant::now());
+        inner.outcomes.clear();
+        self.transition(inner, BreakerState::Open);
+    }
+
+    fn transition(&self, inner: &mut BreakerInner, to: BreakerState) {
+        inner.transitions.push(BreakerTransition {
+            from: inner.state,
+            to,
+            at: self.created_at.elapsed(),
+        });
+        inner.state = to;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opens_probes_and_closes() {
+        let breaker =
+            CircuitBreaker::new(Duration::from_secs(1), 0.5, 4, Duration::from_millis(50));
+        for _ in 0..4 {
+            assert_eq!(breaker.permit(), Permit::Allow);
+            breaker.record(Permit::Allow, true);
+        }
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert!(matches!(breaker.permit(), Permit::Wait(_)));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(breaker.permit(), Permit::Probe);
+        assert!(matches!(breaker.permit(), Permit::Wait(_)));
+        breaker.record(Permit::Probe, false);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+
+        let states: Vec<_> = breaker.transitions().iter().map(|t| t.to).collect();
+        assert_eq!(
+            states,
+            vec![
+                BreakerState::Open,
+                BreakerState::HalfOpen,
+                BreakerState::Closed
+            ]
+        );
+    }
+}
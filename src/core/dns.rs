@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use serde::Deserialize;
+
+#[derive(Debug, Clone)]
+pub enum DnsResolver {
+    // Hostnames missing from the map fall back to the system resolver.
+    Hosts(HashMap<String, IpAddr>),
+    // A DNS-over-HTTPS endpoint speaking the JSON API, e.g.
+    // `https://cloudflare-dns.com/dns-query`. The endpoint itself is resolved by the system.
+    DnsOverHttps(String),
+}
+
+impl DnsResolver {
+    pub fn hosts<'a>(hosts: impl IntoIterator<Item = (&'a str, IpAddr)>) -> Self {
+        Self::Hosts(
+            hosts
+                .into_iter()
+                .map(|(host, ip)| (host.to_ascii_lowercase(), ip))
+                .collect(),
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DohResponse {
+    #[serde(rename = "Status")]
+    status: u32,
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DohAnswer>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DohAnswer {
+    #[serde(rename = "type")]
+    kind: u16,
+    data: String,
+}
+
+// The resolver the client is built with. Ports are left at zero; the client fills in the
+// one from the URL.
+#[derive(Debug, Clone)]
+pub(crate) struct Lookup {
+    resolver: Arc<DnsResolver>,
+    doh_client: reqwest::Client,
+}
+
+impl Lookup {
+    pub(crate) fn new(resolver: &DnsResolver) -> Self {
+        Self {
+            resolver: Arc::new(resolver.clone()),
+            doh_client: reqwest::Client::new(),
+        }
+    }
+
+    pub(crate) async fn addrs(&self, host: &str) -> io::Result<Vec<SocketAddr>> {
+        match &*self.resolver {
+            DnsResolver::Hosts(hosts) => match hosts.get(&host.to_ascii_lowercase()) {
+                Some(ip) => Ok(vec![SocketAddr::new(*ip, 0)]),
+                None => Ok(tokio::net::lookup_host((host, 0)).await?.collect()),
+            },
+            DnsResolver::DnsOverHttps(endpoint) => {
+                // AAAA records are only asked for when there are no A records.
+                for kind in [1, 28] {
+                    let ips = self.query(endpoint, host, kind).await?;
+                    if !ips.is_empty() {
+                        return Ok(ips.into_iter().map(|ip| SocketAddr::new(ip, 0)).collect());
+                    }
+                }
+                Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no addresses found for {host}"),
+                ))
+            }
+        }
+    }
+
+    async fn query(&self, endpoint: &str, host: &str, kind: u16) -> io::Result<Vec<IpAddr>> {
+        let body = self
+            .doh_client
+            .get(endpoint)
+            .query(&[("name", host), ("type", &kind.to_string())])
+            .header("accept", "application/dns-json")
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(io::Error::other)?
+            .bytes()
+            .await
+            .map_err(io::Error::other)?;
+        let response: DohResponse = serde_json::from_slice(&body)?;
+        if response.status != 0 {
+            return Err(io::Error::other(format!(
+                "DNS-over-HTTPS lookup of {host} failed with rcode {}",
+                response.status
+            )));
+        }
+        Ok(response
+            .answer
+            .iter()
+            .filter(|answer| answer.kind == kind)
+            .filter_map(|answer| answer.data.parse().ok())
+            .collect())
+    }
+}
+
+impl Resolve for Lookup {
+    fn resolve(&self, name: Name) -> Resolving {
+        let lookup = self.clone();
+        Box::pin(async move {
+            let addrs = lookup.addrs(name.as_str()).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{header, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_dns_over_https_falls_back_to_aaaa() {
+        let doh = MockServer::start().await;
+        Mock::given(query_param("name", "rperf.test"))
+            .and(query_param("type", "1"))
+            .and(header("accept", "application/dns-json"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"{"Status": 0, "Answer": [{"type": 5, "data": "alias.rperf.test."}]}"#,
+                "application/dns-json",
+            ))
+            .mount(&doh)
+            .await;
+        Mock::given(query_param("name", "rperf.test"))
+            .and(query_param("type", "28"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"{"Status": 0, "Answer": [{"type": 28, "data": "::1"}]}"#,
+                "application/dns-json",
+            ))
+            .mount(&doh)
+            .await;
+        Mock::given(query_param("name", "missing.test"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw(r#"{"Status": 3}"#, "application/dns-json"),
+            )
+            .mount(&doh)
+            .await;
+
+        let lookup = Lookup::new(&DnsResolver::DnsOverHttps(format!(
+            "{}/dns-query",
+            doh.uri()
+        )));
+        assert_eq!(
+            lookup.addrs("rperf.test").await.unwrap(),
+            vec!["[::1]:0".parse().unwrap()]
+        );
+        assert!(lookup.addrs("missing.test").await.is_err());
+    }
+}
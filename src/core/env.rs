@@ -0,0 +1,82 @@
+use super::request::{RequestBody, RequestSpec};
+
+// Replaces every `${NAME}` with the environment variable `NAME`, failing with the name of the
+// first one that is unset. A `$` without braces, `${}` and an unclosed `${` are left as they are.
+pub fn interpolate(template: &str) -> Result<String, String> {
+    interpolate_with(template, |name| std::env::var(name).ok())
+}
+
+fn interpolate_with(
+    template: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+        match &rest[start + 2..start + end] {
+            "" => rendered.push_str("${}"),
+            name => rendered.push_str(&lookup(name).ok_or_else(|| name.to_string())?),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+pub(crate) fn resolve(value: &mut String) -> Result<(), String> {
+    *value = interpolate(value)?;
+    Ok(())
+}
+
+pub(crate) fn resolve_headers(headers: &mut [(String, String)]) -> Result<(), String> {
+    headers.iter_mut().try_for_each(|(_, value)| resolve(value))
+}
+
+// Only text bodies and form values; binary and multipart bodies are sent as given.
+pub(crate) fn resolve_body(body: &mut RequestBody) -> Result<(), String> {
+    match body {
+        RequestBody::Bytes(bytes) => {
+            if let Ok(text) = std::str::from_utf8(bytes) {
+                *bytes = interpolate(text)?.into();
+            }
+        }
+        RequestBody::Form(fields) => {
+            for (_, value) in fields {
+                resolve(value)?;
+            }
+        }
+        RequestBody::Empty | RequestBody::Multipart(_) => {}
+    }
+    Ok(())
+}
+
+pub(crate) fn resolve_request(request: &mut RequestSpec) -> Result<(), String> {
+    resolve(&mut request.url)?;
+    if let Some(path) = &mut request.path {
+        resolve(path)?;
+    }
+    resolve_headers(&mut request.headers)?;
+    resolve_body(&mut request.body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpolate_leaves_other_dollars_alone() {
+        let lookup = |name: &str| (name == "PORT").then(|| "8080".to_string());
+        assert_eq!(
+            interpolate_with("$HOME:${PORT}/${}/${PORT", lookup),
+            Ok("$HOME:8080/${}/${PORT".to_string())
+        );
+        assert_eq!(
+            interpolate_with("${PORT}${HOST}", lookup),
+            Err("HOST".to_string())
+        );
+    }
+}
@@ -0,0 +1,57 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ErrorClass {
+    Connect,
+    Timeout,
+    Request,
+    Body,
+    Decode,
+    Redirect,
+    Other,
+}
+
+impl ErrorClass {
+    pub fn of(error: &reqwest::Error) -> Self {
+        if error.is_timeout() {
+            ErrorClass::Timeout
+        } else if error.is_connect() {
+            ErrorClass::Connect
+        } else if error.is_redirect() {
+            ErrorClass::Redirect
+        } else if error.is_body() {
+            ErrorClass::Body
+        } else if error.is_decode() {
+            ErrorClass::Decode
+        } else if error.is_request() {
+            ErrorClass::Request
+        } else {
+            ErrorClass::Other
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ErrorClass::Connect => "connect",
+            ErrorClass::Timeout => "timeout",
+            ErrorClass::Request => "request",
+            ErrorClass::Body => "body",
+            ErrorClass::Decode => "decode",
+            ErrorClass::Redirect => "redirect",
+            ErrorClass::Other => "other",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_refused_connection_is_connect_error() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let error = reqwest::get(format!("http://{}", addr)).await.unwrap_err();
+        assert_eq!(ErrorClass::of(&error), ErrorClass::Connect);
+    }
+}
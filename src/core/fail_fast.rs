@@ -0,0 +1,55 @@
+use tokio::sync::watch;
+
+// Shared by every VU in a run; the first failure reported is kept and wakes the manager.
+#[derive(Debug)]
+pub struct FailFast {
+    failure: watch::Sender<Option<String>>,
+}
+
+impl Default for FailFast {
+    fn default() -> Self {
+        Self {
+            failure: watch::Sender::new(None),
+        }
+    }
+}
+
+impl FailFast {
+    // Returns whether this was the first failure.
+    pub fn trip(&self, failure: String) -> bool {
+        self.failure.send_if_modified(|first| {
+            if first.is_some() {
+                return false;
+            }
+            *first = Some(failure);
+            true
+        })
+    }
+
+    pub fn failure(&self) -> Option<String> {
+        self.failure.borrow().clone()
+    }
+
+    pub fn reset(&self) {
+        self.failure.send_replace(None);
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Option<String>> {
+        self.failure.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keeps_first_failure() {
+        let fail_fast = FailFast::default();
+        assert!(fail_fast.trip("first".to_string()));
+        assert!(!fail_fast.trip("second".to_string()));
+        assert_eq!(fail_fast.failure().as_deref(), Some("first"));
+        fail_fast.reset();
+        assert_eq!(fail_fast.failure(), None);
+    }
+}
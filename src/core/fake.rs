@@ -0,0 +1,105 @@
+use rand::Rng;
+
+use super::request::{RequestBody, RequestSpec};
+
+const FIRST_NAMES: &[&str] = &[
+    "Alice", "Bruno", "Chen", "Dana", "Emil", "Fatima", "Goran", "Hana", "Ivan", "Julia", "Kenji",
+    "Lena", "Marco", "Nadia", "Omar", "Priya",
+];
+const LAST_NAMES: &[&str] = &[
+    "Novak", "Garcia", "Kim", "Okafor", "Silva", "Schmidt", "Tanaka", "Rossi", "Haddad", "Larsen",
+    "Moreau", "Kowalski",
+];
+
+fn pick<'a>(names: &[&'a str], rng: &mut impl Rng) -> &'a str {
+    names[rng.gen_range(0..names.len())]
+}
+
+fn generate(token: &str, rng: &mut impl Rng) -> Option<String> {
+    match token {
+        "random_name" => Some(format!(
+            "{} {}",
+            pick(FIRST_NAMES, rng),
+            pick(LAST_NAMES, rng)
+        )),
+        "random_email" => Some(format!(
+            "{}.{}{}@example.com",
+            pick(FIRST_NAMES, rng).to_ascii_lowercase(),
+            pick(LAST_NAMES, rng).to_ascii_lowercase(),
+            rng.gen_range(1..10_000)
+        )),
+        _ => {
+            let (min, max) = token.strip_prefix("random_int:")?.split_once(':')?;
+            let (min, max): (i64, i64) = (min.trim().parse().ok()?, max.trim().parse().ok()?);
+            (min <= max).then(|| rng.gen_range(min..=max).to_string())
+        }
+    }
+}
+
+// Replaces `{random_name}`, `{random_email}` and `{random_int:MIN:MAX}` (both ends inclusive)
+// with fresh values. Anything else in braces, JSON included, is left as it is.
+pub fn render(template: &str, rng: &mut impl Rng) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{random_") {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+        match generate(&rest[start + 1..start + end], rng) {
+            Some(value) => rendered.push_str(&value),
+            None => rendered.push_str(&rest[start..start + end + 1]),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+// A copy of `request` with its text body or form values rendered.
+pub(crate) fn fill(request: &RequestSpec, rng: &mut impl Rng) -> RequestSpec {
+    let mut request = request.clone();
+    match &mut request.body {
+        RequestBody::Bytes(bytes) => {
+            if let Ok(text) = std::str::from_utf8(bytes) {
+                *bytes = render(text, rng).into();
+            }
+        }
+        RequestBody::Form(fields) => {
+            for (_, value) in fields {
+                *value = render(value, rng);
+            }
+        }
+        RequestBody::Empty | RequestBody::Multipart(_) => {}
+    }
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_render_leaves_other_braces_alone() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let rendered = render(
+            r#"{"id": {random_int:7:7}, "x": "{random_nope}", "n": "{random_name}"}"#,
+            &mut rng,
+        );
+        let json: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(json["id"], 7);
+        assert_eq!(json["x"], "{random_nope}");
+        let name = json["n"].as_str().unwrap();
+        let (first, last) = name.split_once(' ').unwrap();
+        assert!(
+            FIRST_NAMES.contains(&first) && LAST_NAMES.contains(&last),
+            "{name}"
+        );
+        assert_eq!(
+            render("{random_int:9:1} {random_", &mut rng),
+            "{random_int:9:1} {random_"
+        );
+    }
+}
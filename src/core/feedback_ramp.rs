@@ -0,0 +1,122 @@
+use std::time::{Duration, Instant};
+
+// Slows ramp-ups while latency climbs. Every `window` the mean latency is compared with the
+// previous window's, and the ramp advances at `1 - growth / max_growth` of its planned pace,
+// never slower than `min_pace`. A slowed ramp runs past its planned duration until it reaches
+// its target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeedbackRamp {
+    pub max_growth: f64,
+    pub window: Duration,
+    pub min_pace: f64,
+}
+
+impl FeedbackRamp {
+    // `max_growth` is the relative growth per window at which the ramp slows to `min_pace`,
+    // e.g. 0.5 for latency rising by half.
+    pub fn new(max_growth: f64) -> Self {
+        if max_growth <= 0.0 {
+            panic!("max latency growth must be greater than 0");
+        }
+
+        Self {
+            max_growth,
+            window: Duration::from_secs(1),
+            min_pace: 0.1,
+        }
+    }
+
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    // Above zero, or a ramp could stall for good.
+    pub fn min_pace(mut self, min_pace: f64) -> Self {
+        if !(min_pace > 0.0 && min_pace <= 1.0) {
+            panic!("min pace must be in (0, 1], got {min_pace}");
+        }
+
+        self.min_pace = min_pace;
+        self
+    }
+}
+
+// Tracks one ramp segment. Latency comes in as the run's running `(sum, count)` so the
+// caller only has to add up its summaries.
+#[derive(Debug)]
+pub(crate) struct RampPacer {
+    feedback: FeedbackRamp,
+    window_start: Instant,
+    window_totals: Option<(f64, usize)>,
+    previous_mean: Option<f64>,
+    pace: f64,
+}
+
+impl RampPacer {
+    pub(crate) fn new(feedback: FeedbackRamp, now: Instant) -> Self {
+        Self {
+            feedback,
+            window_start: now,
+            window_totals: None,
+            previous_mean: None,
+            pace: 1.0,
+        }
+    }
+
+    // The first call starts the first window whenever it comes.
+    pub(crate) fn is_due(&self, now: Instant) -> bool {
+        self.window_totals.is_none()
+            || now.duration_since(self.window_start) >= self.feedback.window
+    }
+
+    pub(crate) fn update(&mut self, now: Instant, totals: (f64, usize)) {
+        if let Some((sum, count)) = self.window_totals {
+            let count = totals.1.saturating_sub(count);
+            if count > 0 {
+                let mean = (totals.0 - sum) / count as f64;
+                if let Some(previous) = self.previous_mean.filter(|previous| *previous > 0.0) {
+                    let growth = (mean - previous) / previous;
+                    self.pace = (1.0 - growth / self.feedback.max_growth)
+                        .clamp(self.feedback.min_pace, 1.0);
+                }
+                self.previous_mean = Some(mean);
+            }
+        }
+        self.window_start = now;
+        self.window_totals = Some(totals);
+    }
+
+    pub(crate) fn pace(&self) -> f64 {
+        self.pace
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pace_follows_latency_growth() {
+        let window = Duration::from_secs(1);
+        let start = Instant::now();
+        let mut pacer = RampPacer::new(FeedbackRamp::new(0.5).window(window).min_pace(0.2), start);
+        let mut at = |secs: u64, totals: (f64, usize)| {
+            let now = start + window * secs as u32;
+            assert!(pacer.is_due(now));
+            pacer.update(now, totals);
+            pacer.pace()
+        };
+
+        assert_eq!(at(0, (0.0, 0)), 1.0);
+        // 10ms, then 12.5ms: a quarter up, half of `max_growth`.
+        assert_eq!(at(1, (1.0, 100)), 1.0);
+        assert!((at(2, (2.25, 200)) - 0.5).abs() < 1e-9);
+        // Doubling is past `max_growth`; a window without requests keeps the pace.
+        assert_eq!(at(3, (4.75, 300)), 0.2);
+        assert_eq!(at(4, (4.75, 300)), 0.2);
+        // Flat or falling latency restores the planned pace.
+        assert_eq!(at(5, (7.25, 400)), 1.0);
+        assert!(!pacer.is_due(start + window * 5 + window / 2));
+    }
+}
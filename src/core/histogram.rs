@@ -0,0 +1,230 @@
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    lowest: f64,
+    growth: f64,
+    counts: Vec<u64>,
+    total: u64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new(1e-6, 100.0, 1.02)
+    }
+}
+
+impl Histogram {
+    pub fn new(lowest: f64, highest: f64, growth: f64) -> Self {
+        if lowest <= 0.0 || highest <= lowest || growth <= 1.0 {
+            panic!("histogram requires 0 < lowest < highest and growth > 1");
+        }
+
+        let bucket_count = ((highest / lowest).ln() / growth.ln()).ceil() as usize + 2;
+
+        Self {
+            lowest,
+            growth,
+            counts: vec![0; bucket_count],
+            total: 0,
+            min: f64::MAX,
+            max: f64::MIN,
+        }
+    }
+
+    pub fn record(&mut self, value: f64) {
+        self.record_n(value, 1);
+    }
+
+    pub fn record_n(&mut self, value: f64, count: u64) {
+        if count == 0 {
+            return;
+        }
+
+        let index = self.bucket_index(value);
+        self.counts[index] += count;
+        self.total += count;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    // Back-fills the samples a stalled closed-loop client never got to send, as in
+    // HdrHistogram's `recordValueWithExpectedInterval`.
+    pub fn record_corrected(&mut self, value: f64, expected_interval: f64) {
+        self.record(value);
+        if expected_interval <= 0.0 {
+            return;
+        }
+
+        let mut missing = value - expected_interval;
+        while missing >= expected_interval {
+            self.record(missing);
+            missing -= expected_interval;
+        }
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        if other.total == 0 {
+            return;
+        }
+
+        if self.lowest == other.lowest
+            && self.growth == other.growth
+            && self.counts.len() == other.counts.len()
+        {
+            for (dest, src) in self.counts.iter_mut().zip(&other.counts) {
+                *dest += src;
+            }
+            self.total += other.total;
+            self.min = self.min.min(other.min);
+            self.max = self.max.max(other.max);
+        } else {
+            for (index, &count) in other.counts.iter().enumerate() {
+                if count > 0 {
+                    let value = other.representative_value(index);
+                    self.record_n(value, count);
+                }
+            }
+            self.min = self.min.min(other.min);
+            self.max = self.max.max(other.max);
+        }
+    }
+
+    pub fn percentile(&self, q: f64) -> Option<f64> {
+        if self.total == 0 {
+            return None;
+        }
+
+        if q <= 0.0 {
+            return Some(self.min);
+        }
+        if q >= 1.0 {
+            return Some(self.max);
+        }
+
+        let rank = ((q * self.total as f64).ceil() as u64).max(1);
+
+        let mut cumulative = 0;
+        for (index, &count) in self.counts.iter().enumerate() {
+            cumulative += count;
+            if cumulative >= rank {
+                let value = self.representative_value(index);
+                return Some(value.clamp(self.min, self.max));
+            }
+        }
+
+        Some(self.max)
+    }
+
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    pub fn buckets(&self) -> Vec<(f64, u64)> {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(index, &count)| (self.upper_bound(index), count))
+            .collect()
+    }
+
+    pub fn reset(&mut self) {
+        self.counts.iter_mut().for_each(|count| *count = 0);
+        self.total = 0;
+        self.min = f64::MAX;
+        self.max = f64::MIN;
+    }
+
+    fn bucket_index(&self, value: f64) -> usize {
+        if value <= self.lowest {
+            return 0;
+        }
+
+        let index = ((value / self.lowest).ln() / self.growth.ln()).ceil() as usize;
+        index.min(self.counts.len() - 1)
+    }
+
+    fn upper_bound(&self, index: usize) -> f64 {
+        self.lowest * self.growth.powi(index as i32)
+    }
+
+    fn representative_value(&self, index: usize) -> f64 {
+        if index == 0 {
+            return self.lowest;
+        }
+        self.upper_bound(index) / self.growth.sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_histogram() {
+        let histogram = Histogram::default();
+        assert_eq!(histogram.count(), 0);
+        assert_eq!(histogram.percentile(0.5), None);
+    }
+
+    #[test]
+    fn test_percentile_within_bucket_precision() {
+        let mut histogram = Histogram::default();
+        for i in 1..=1000 {
+            histogram.record(i as f64 / 1000.0);
+        }
+
+        assert_eq!(histogram.count(), 1000);
+        let p50 = histogram.percentile(0.5).unwrap();
+        let p99 = histogram.percentile(0.99).unwrap();
+        assert!((p50 - 0.5).abs() / 0.5 < 0.02);
+        assert!((p99 - 0.99).abs() / 0.99 < 0.02);
+        assert_eq!(histogram.percentile(1.0).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_buckets() {
+        let mut histogram = Histogram::default();
+        for value in [0.001, 0.001, 0.01, 0.25, 3.0] {
+            histogram.record(value);
+        }
+
+        let buckets = histogram.buckets();
+        assert_eq!(buckets.len(), 4);
+        assert_eq!(buckets[0].1, 2);
+        assert!(buckets.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(buckets.iter().all(|&(bound, _)| bound > 0.0));
+        assert!(buckets[3].0 >= 3.0);
+    }
+
+    #[test]
+    fn test_record_corrected_backfills_stalls() {
+        let mut histogram = Histogram::default();
+        histogram.record_corrected(0.001, 0.01);
+        assert_eq!(histogram.count(), 1);
+
+        histogram.record_corrected(0.1, 0.01);
+        assert_eq!(histogram.count(), 11);
+        assert!((histogram.percentile(0.5).unwrap() - 0.05).abs() / 0.05 < 0.02);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut first = Histogram::default();
+        let mut second = Histogram::default();
+        let mut combined = Histogram::default();
+        for i in 1..=500 {
+            first.record(i as f64 / 1000.0);
+            combined.record(i as f64 / 1000.0);
+        }
+        for i in 501..=1000 {
+            second.record(i as f64 / 1000.0);
+            combined.record(i as f64 / 1000.0);
+        }
+
+        first.merge(&second);
+        assert_eq!(first.count(), combined.count());
+        assert_eq!(first.percentile(0.9), combined.percentile(0.9));
+    }
+}
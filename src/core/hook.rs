@@ -0,0 +1,31 @@
+use std::fmt::Debug;
+
+use reqwest::Request;
+
+pub trait RequestHook: Debug + Send + Sync {
+    fn before_send(&self, request: &mut Request);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Client;
+
+    #[derive(Debug)]
+    struct AddHeader;
+
+    impl RequestHook for AddHeader {
+        fn before_send(&self, request: &mut Request) {
+            request
+                .headers_mut()
+                .insert("x-hooked", "1".parse().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_hook_mutates_request() {
+        let mut request = Client::new().get("http://test.com/").build().unwrap();
+        AddHeader.before_send(&mut request);
+        assert_eq!(request.headers()["x-hooked"], "1");
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// A fixed number of iterations shared by every VU; each VU claims one before starting it.
+#[derive(Debug)]
+pub struct IterationBudget {
+    total: usize,
+    claimed: AtomicUsize,
+    completed: AtomicUsize,
+}
+
+impl IterationBudget {
+    pub fn new(total: usize) -> Self {
+        Self {
+            total,
+            claimed: AtomicUsize::new(0),
+            completed: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn try_claim(&self) -> bool {
+        self.claimed
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |claimed| {
+                (claimed < self.total).then_some(claimed + 1)
+            })
+            .is_ok()
+    }
+
+    pub fn complete(&self) {
+        self.completed.fetch_add(1, Ordering::AcqRel);
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn completed(&self) -> usize {
+        self.completed.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_claims_never_exceed_total_across_threads() {
+        let budget = Arc::new(IterationBudget::new(1000));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let budget = budget.clone();
+                std::thread::spawn(move || {
+                    let mut claimed = 0;
+                    while budget.try_claim() {
+                        budget.complete();
+                        claimed += 1;
+                    }
+                    claimed
+                })
+            })
+            .collect();
+
+        let claimed: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(claimed, 1000);
+        assert_eq!(budget.completed(), 1000);
+        assert!(!budget.try_claim());
+    }
+}
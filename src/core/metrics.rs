@@ -1,35 +1,225 @@
 // metrics.rs
 use std::collections::HashMap;
-use std::time::Duration;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use super::breakdown::BreakdownSummary;
+use super::error_class::ErrorClass;
+use super::histogram::Histogram;
+use super::raw_samples::RawSamples;
 use super::rps_summary::RpsSummary;
-use super::summary::Summary;
+use super::slowest::{SlowRequest, SlowestRequests};
+use super::summary::{Summary, TimedExtremes};
+use super::tdigest::TDigest;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PercentileBackend {
+    #[default]
+    Histogram,
+    TDigest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LatencyResolution {
+    Coarse,
+    #[default]
+    Normal,
+    Fine,
+}
+
+impl LatencyResolution {
+    pub fn histogram(self) -> Histogram {
+        match self {
+            LatencyResolution::Coarse => Histogram::new(1e-4, 60.0, 1.1),
+            LatencyResolution::Normal => Histogram::new(1e-6, 100.0, 1.02),
+            LatencyResolution::Fine => Histogram::new(1e-7, 600.0, 1.002),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub active_vus: usize,
+    pub requests: usize,
+    pub errors: usize,
+    pub timeouts: usize,
+    pub assertion_failures: usize,
+    pub retries: usize,
+    pub retries_dropped_by_budget: usize,
+    pub retries_skipped_non_idempotent: usize,
+}
+
+impl MetricsSnapshot {
+    pub fn merge(&mut self, other: &MetricsSnapshot) {
+        self.active_vus += other.active_vus;
+        self.requests += other.requests;
+        self.errors += other.errors;
+        self.timeouts += other.timeouts;
+        self.assertion_failures += other.assertion_failures;
+        self.retries += other.retries;
+        self.retries_dropped_by_budget += other.retries_dropped_by_budget;
+        self.retries_skipped_non_idempotent += other.retries_skipped_non_idempotent;
+    }
+}
+
+// Kept outside the metrics mutex so snapshots never contend with a VU mid-request.
+#[derive(Debug, Default)]
+pub struct Counters {
+    pub requests: AtomicUsize,
+    pub errors: AtomicUsize,
+    pub timeouts: AtomicUsize,
+    pub assertion_failures: AtomicUsize,
+    pub retries: AtomicUsize,
+    pub retries_dropped_by_budget: AtomicUsize,
+    pub retries_skipped_non_idempotent: AtomicUsize,
+}
+
+impl Counters {
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            active_vus: 0,
+            requests: self.requests.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            assertion_failures: self.assertion_failures.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            retries_dropped_by_budget: self.retries_dropped_by_budget.load(Ordering::Relaxed),
+            retries_skipped_non_idempotent: self
+                .retries_skipped_non_idempotent
+                .load(Ordering::Relaxed),
+        }
+    }
+
+    // Resets every counter and returns what it held; increments racing with this are kept.
+    pub fn take(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            active_vus: 0,
+            requests: self.requests.swap(0, Ordering::Relaxed),
+            errors: self.errors.swap(0, Ordering::Relaxed),
+            timeouts: self.timeouts.swap(0, Ordering::Relaxed),
+            assertion_failures: self.assertion_failures.swap(0, Ordering::Relaxed),
+            retries: self.retries.swap(0, Ordering::Relaxed),
+            retries_dropped_by_budget: self.retries_dropped_by_budget.swap(0, Ordering::Relaxed),
+            retries_skipped_non_idempotent: self
+                .retries_skipped_non_idempotent
+                .swap(0, Ordering::Relaxed),
+        }
+    }
+
+    pub fn add(&self, snapshot: &MetricsSnapshot) {
+        self.requests.fetch_add(snapshot.requests, Ordering::Relaxed);
+        self.errors.fetch_add(snapshot.errors, Ordering::Relaxed);
+        self.timeouts.fetch_add(snapshot.timeouts, Ordering::Relaxed);
+        self.assertion_failures
+            .fetch_add(snapshot.assertion_failures, Ordering::Relaxed);
+        self.retries.fetch_add(snapshot.retries, Ordering::Relaxed);
+        self.retries_dropped_by_budget
+            .fetch_add(snapshot.retries_dropped_by_budget, Ordering::Relaxed);
+        self.retries_skipped_non_idempotent
+            .fetch_add(snapshot.retries_skipped_non_idempotent, Ordering::Relaxed);
+    }
+}
 
 #[derive(Debug)]
 pub struct Metrics {
     pub total_latency: Summary,
+    pub latency_extremes: Option<TimedExtremes>,
+    pub raw_samples: Option<RawSamples>,
+    pub latency_histogram: Histogram,
+    pub latency_digest: Option<TDigest>,
+    pub corrected_latency_histogram: Option<Histogram>,
+    pub expected_interval: Option<Duration>,
     pub tcp_connect_time: Summary,
     pub tls_handshake_time: Summary,
     pub http_request_time: Summary,
+    pub ttfb: Summary,
+    pub latency_breakdown: BreakdownSummary,
+    pub injected_delay: Summary,
+    pub queue_wait: Summary,
     pub rps_summary: RpsSummary,
-    pub total_errors: usize,
+    pub counters: Arc<Counters>,
     pub error_rates_per_sec: Summary,
     pub status_code_counts: HashMap<u16, usize>,
+    pub remote_addr_counts: HashMap<SocketAddr, usize>,
+    pub error_classes: HashMap<ErrorClass, usize>,
+    // Responses under 400 that passed the success predicate, if one is set. 304s count.
+    pub successful_responses: usize,
+    // Transport errors split by whether a connection was ever established; request deadlines
+    // are counted in `timeouts` instead.
+    pub connect_failures: usize,
+    pub request_failures: usize,
+    pub connection_close_count: usize,
+    pub connection_recycles: usize,
+    pub expect_continue_rejected: usize,
+    pub upload_bytes: usize,
+    pub uncompressed_upload_bytes: usize,
+    pub download_bytes: usize,
+    // Bytes read per response body.
+    pub response_size: Summary,
+    // Latency per `RequestSpec::tag`; untagged requests are left out.
+    pub per_tag: HashMap<String, Summary>,
+    pub not_modified: usize,
+    pub repeated_responses: usize,
+    pub negotiation_failures: usize,
+    pub connection_probes: usize,
+    pub failed_connection_probes: usize,
+    pub slowest_requests: SlowestRequests,
+    pub tls_resumed_handshakes: usize,
+    pub tls_full_handshakes: usize,
     pub other_errors: Vec<String>,
+    pub run_duration: Option<Duration>,
+    pub started_at: Option<Instant>,
+    pub tags: HashMap<String, String>,
 }
 
 impl Default for Metrics {
     fn default() -> Self {
         Self {
             total_latency: Summary::new(),
+            latency_extremes: None,
+            raw_samples: None,
+            latency_histogram: Histogram::default(),
+            latency_digest: None,
+            corrected_latency_histogram: None,
+            expected_interval: None,
             tcp_connect_time: Summary { min: 0.0, max: 0.0, sum: 0.0, count: 0 },
             tls_handshake_time: Summary { min: 0.0, max: 0.0, sum: 0.0, count: 0 },
             http_request_time: Summary::new(),
+            ttfb: Summary::new(),
+            latency_breakdown: BreakdownSummary::default(),
+            injected_delay: Summary::new(),
+            queue_wait: Summary::new(),
             rps_summary: RpsSummary::default(),
-            total_errors: 0,
+            counters: Arc::default(),
             error_rates_per_sec: Summary::new(),
             status_code_counts: HashMap::new(),
+            remote_addr_counts: HashMap::new(),
+            error_classes: HashMap::new(),
+            successful_responses: 0,
+            connect_failures: 0,
+            request_failures: 0,
+            connection_close_count: 0,
+            connection_recycles: 0,
+            expect_continue_rejected: 0,
+            upload_bytes: 0,
+            uncompressed_upload_bytes: 0,
+            download_bytes: 0,
+            response_size: Summary::new(),
+            per_tag: HashMap::new(),
+            not_modified: 0,
+            repeated_responses: 0,
+            negotiation_failures: 0,
+            connection_probes: 0,
+            failed_connection_probes: 0,
+            slowest_requests: SlowestRequests::default(),
+            tls_resumed_handshakes: 0,
+            tls_full_handshakes: 0,
             other_errors: Vec::new(),
+            run_duration: None,
+            started_at: None,
+            tags: HashMap::new(),
         }
     }
 }
@@ -38,15 +228,256 @@ impl Metrics {
     pub fn new(rps_window_size: Duration) -> Self {
         Self {
             total_latency: Summary::new(),
+            latency_extremes: None,
+            raw_samples: None,
+            latency_histogram: Histogram::default(),
+            latency_digest: None,
+            corrected_latency_histogram: None,
+            expected_interval: None,
             tcp_connect_time: Summary { min: 0.0, max: 0.0, sum: 0.0, count: 0 },
             tls_handshake_time: Summary { min: 0.0, max: 0.0, sum: 0.0, count: 0 },
             http_request_time: Summary::new(),
+            ttfb: Summary::new(),
+            latency_breakdown: BreakdownSummary::default(),
+            injected_delay: Summary::new(),
+            queue_wait: Summary::new(),
             rps_summary: RpsSummary::new(rps_window_size),
-            total_errors: 0,
+            counters: Arc::default(),
             error_rates_per_sec: Summary::new(),
             status_code_counts: HashMap::new(),
+            remote_addr_counts: HashMap::new(),
+            error_classes: HashMap::new(),
+            successful_responses: 0,
+            connect_failures: 0,
+            request_failures: 0,
+            connection_close_count: 0,
+            connection_recycles: 0,
+            expect_continue_rejected: 0,
+            upload_bytes: 0,
+            uncompressed_upload_bytes: 0,
+            download_bytes: 0,
+            response_size: Summary::new(),
+            per_tag: HashMap::new(),
+            not_modified: 0,
+            repeated_responses: 0,
+            negotiation_failures: 0,
+            connection_probes: 0,
+            failed_connection_probes: 0,
+            slowest_requests: SlowestRequests::default(),
+            tls_resumed_handshakes: 0,
+            tls_full_handshakes: 0,
             other_errors: Vec::new(),
+            run_duration: None,
+            started_at: None,
+            tags: HashMap::new(),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn with_percentile_backend(mut self, backend: PercentileBackend) -> Self {
+        self.latency_digest = match backend {
+            PercentileBackend::Histogram => None,
+            PercentileBackend::TDigest => Some(TDigest::default()),
+        };
+        self
+    }
+
+    pub fn with_latency_timestamps(mut self) -> Self {
+        self.latency_extremes = Some(TimedExtremes::default());
+        self
+    }
+
+    pub fn with_raw_samples(mut self, capacity: usize) -> Self {
+        self.raw_samples = Some(RawSamples::new(capacity));
+        self
+    }
+
+    pub fn with_tags(mut self, tags: HashMap<String, String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub fn with_slowest_capacity(mut self, capacity: usize) -> Self {
+        self.slowest_requests = SlowestRequests::new(capacity);
+        self
+    }
+
+    pub fn with_latency_resolution(mut self, resolution: LatencyResolution) -> Self {
+        self.latency_histogram = resolution.histogram();
+        self
+    }
+
+    pub fn with_coordinated_omission_correction(mut self, expected_interval: Duration) -> Self {
+        let mut histogram = self.latency_histogram.clone();
+        histogram.reset();
+        self.corrected_latency_histogram = Some(histogram);
+        self.expected_interval = Some(expected_interval);
+        self
+    }
+
+    pub fn record_latency(&mut self, latency: f64) {
+        self.total_latency.update(latency);
+        self.latency_histogram.record(latency);
+        if let (Some(histogram), Some(interval)) =
+            (self.corrected_latency_histogram.as_mut(), self.expected_interval)
+        {
+            histogram.record_corrected(latency, interval.as_secs_f64());
+        }
+        if let Some(digest) = self.latency_digest.as_mut() {
+            digest.add(latency);
+        }
+        if let Some(raw) = self.raw_samples.as_mut() {
+            raw.record(latency);
+        }
+    }
+
+    pub fn total_requests(&self) -> usize {
+        self.http_request_time.count()
+    }
+
+    pub fn total_errors(&self) -> usize {
+        self.counters.errors.load(Ordering::Relaxed)
+    }
+
+    pub fn timeouts(&self) -> usize {
+        self.counters.timeouts.load(Ordering::Relaxed)
+    }
+
+    pub fn assertion_failures(&self) -> usize {
+        self.counters.assertion_failures.load(Ordering::Relaxed)
+    }
+
+    pub fn retries(&self) -> usize {
+        self.counters.retries.load(Ordering::Relaxed)
+    }
+
+    pub fn retries_dropped_by_budget(&self) -> usize {
+        self.counters.retries_dropped_by_budget.load(Ordering::Relaxed)
+    }
+
+    pub fn retries_skipped_non_idempotent(&self) -> usize {
+        self.counters.retries_skipped_non_idempotent.load(Ordering::Relaxed)
+    }
+
+    pub fn throughput(&self) -> Option<f64> {
+        match self.run_duration {
+            Some(duration) if !duration.is_zero() => {
+                Some(self.total_requests() as f64 / duration.as_secs_f64())
+            }
+            _ => self.rps_summary.get_average_rps().ok().flatten(),
+        }
+    }
+
+    // Offset from the start of the run at which the fastest request finished.
+    pub fn latency_min_at(&self) -> Option<Duration> {
+        let (_, at) = self.latency_extremes?.min?;
+        Some(at.saturating_duration_since(self.started_at?))
+    }
+
+    pub fn latency_max_at(&self) -> Option<Duration> {
+        let (_, at) = self.latency_extremes?.max?;
+        Some(at.saturating_duration_since(self.started_at?))
+    }
+
+    pub fn effective_concurrency(&self) -> Option<f64> {
+        Some(self.throughput()? * self.total_latency.average()?)
+    }
+
+    pub fn error_rate(&self) -> Option<f64> {
+        let total_requests = self.total_requests();
+        (total_requests > 0).then(|| self.total_errors() as f64 / total_requests as f64)
+    }
+
+    pub fn status_class_counts(&self) -> (u64, u64, u64, u64, u64) {
+        let mut classes = [0u64; 5];
+        for (&code, &count) in &self.status_code_counts {
+            if (100..600).contains(&code) {
+                classes[(code / 100 - 1) as usize] += count as u64;
+            }
+        }
+        (classes[0], classes[1], classes[2], classes[3], classes[4])
+    }
+
+    pub fn latency_histogram_buckets(&self) -> Vec<(f64, u64)> {
+        self.latency_histogram.buckets()
+    }
+
+    pub fn corrected_latency_percentile(&self, q: f64) -> Option<f64> {
+        self.corrected_latency_histogram
+            .as_ref()
+            .and_then(|histogram| histogram.percentile(q))
+    }
+
+    // At most the tracked capacity, slowest first.
+    pub fn slowest(&self, n: usize) -> Vec<SlowRequest> {
+        self.slowest_requests.top(n)
+    }
+
+    // The most recent latencies, oldest first; empty unless `with_raw_samples` was used.
+    pub fn raw_latencies(&self) -> Vec<f64> {
+        self.raw_samples
+            .as_ref()
+            .map_or_else(Vec::new, RawSamples::samples)
+    }
+
+    pub fn latency_percentile(&self, q: f64) -> Option<f64> {
+        match &self.latency_digest {
+            Some(digest) => digest.quantile(q),
+            None => self.latency_histogram.percentile(q),
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_class_counts() {
+        let mut metrics = Metrics::default();
+        metrics.status_code_counts.insert(101, 1);
+        metrics.status_code_counts.insert(200, 5);
+        metrics.status_code_counts.insert(204, 2);
+        metrics.status_code_counts.insert(301, 3);
+        metrics.status_code_counts.insert(404, 4);
+        metrics.status_code_counts.insert(429, 1);
+        metrics.status_code_counts.insert(503, 6);
+
+        assert_eq!(metrics.status_class_counts(), (1, 7, 3, 5, 6));
+    }
+
+    #[test]
+    fn test_fine_resolution_is_more_accurate_than_coarse() {
+        let mut coarse = Metrics::default().with_latency_resolution(LatencyResolution::Coarse);
+        let mut fine = Metrics::default().with_latency_resolution(LatencyResolution::Fine);
+        let samples: Vec<f64> = (1..=10_000).map(|i| i as f64 / 10_000.0).collect();
+        for &sample in &samples {
+            coarse.record_latency(sample);
+            fine.record_latency(sample);
+        }
+
+        let error = |metrics: &Metrics| -> f64 {
+            [0.5, 0.9, 0.95, 0.99]
+                .iter()
+                .map(|&q| (metrics.latency_percentile(q).unwrap() - q).abs() / q)
+                .sum()
+        };
+        assert!(error(&fine) < error(&coarse));
+        assert!(error(&fine) < 0.01);
+    }
+
+    #[test]
+    fn test_latency_percentile_backends() {
+        let mut histogram = Metrics::default();
+        let mut digest = Metrics::default().with_percentile_backend(PercentileBackend::TDigest);
+        for i in 1..=100 {
+            histogram.record_latency(i as f64 / 100.0);
+            digest.record_latency(i as f64 / 100.0);
+        }
+
+        assert!(digest.latency_digest.is_some());
+        assert!((histogram.latency_percentile(0.5).unwrap() - 0.5).abs() < 0.02);
+        assert!((digest.latency_percentile(0.5).unwrap() - 0.5).abs() < 0.02);
+    }
+}
@@ -0,0 +1,46 @@
+fn media_type(value: &str) -> &str {
+    value.split(';').next().unwrap_or_default().trim()
+}
+
+fn is_refused(range: &str) -> bool {
+    range.split(';').skip(1).any(|param| {
+        param
+            .split_once('=')
+            .is_some_and(|(name, q)| name.trim() == "q" && q.trim().parse() == Ok(0.0))
+    })
+}
+
+// Whether a response `Content-Type` satisfies one of the media ranges in an `Accept` header.
+// Ranges with `q=0` are refusals and never match.
+pub fn is_acceptable(accept: &str, content_type: &str) -> bool {
+    let content_type = media_type(content_type).to_ascii_lowercase();
+    let Some((kind, _)) = content_type.split_once('/') else {
+        return false;
+    };
+
+    accept
+        .split(',')
+        .filter(|range| !is_refused(range))
+        .map(|range| media_type(range).to_ascii_lowercase())
+        .any(|range| match range.split_once('/') {
+            Some(("*", "*")) => true,
+            Some((range_kind, "*")) => range_kind == kind,
+            _ => range == content_type,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_media_ranges() {
+        let accept = "application/json, text/*;q=0.5, image/png;q=0";
+        assert!(is_acceptable(accept, "application/json; charset=utf-8"));
+        assert!(is_acceptable(accept, "Text/HTML"));
+        assert!(!is_acceptable(accept, "image/png"));
+        assert!(!is_acceptable(accept, "application/xml"));
+        assert!(!is_acceptable(accept, ""));
+        assert!(is_acceptable("*/*", "application/octet-stream"));
+    }
+}
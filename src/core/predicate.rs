@@ -0,0 +1,39 @@
+use std::fmt;
+use std::sync::Arc;
+
+type PredicateFn = dyn Fn(u16, &[u8]) -> bool + Send + Sync;
+
+#[derive(Clone)]
+pub struct SuccessPredicate(Arc<PredicateFn>);
+
+impl SuccessPredicate {
+    pub fn new<F>(predicate: F) -> Self
+    where
+        F: Fn(u16, &[u8]) -> bool + Send + Sync + 'static,
+    {
+        Self(Arc::new(predicate))
+    }
+
+    pub fn check(&self, status: u16, body: &[u8]) -> bool {
+        (self.0)(status, body)
+    }
+}
+
+impl fmt::Debug for SuccessPredicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SuccessPredicate(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let predicate = SuccessPredicate::new(|status, body| status == 200 && body == b"ok");
+        assert!(predicate.check(200, b"ok"));
+        assert!(!predicate.check(200, b"error"));
+        assert!(!predicate.check(500, b"ok"));
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, Default)]
+enum State {
+    #[default]
+    Idle,
+    Running {
+        started_at: Instant,
+        planned: Duration,
+    },
+    Finished,
+}
+
+// How far the manager is through its plan, shared so other tasks can follow a run while the
+// manager is busy driving it.
+#[derive(Debug, Default)]
+pub struct RunProgress(Mutex<State>);
+
+impl RunProgress {
+    pub(crate) fn start(&self, planned: Duration) {
+        *self.0.lock().unwrap() = State::Running {
+            started_at: Instant::now(),
+            planned,
+        };
+    }
+
+    pub(crate) fn finish(&self) {
+        *self.0.lock().unwrap() = State::Finished;
+    }
+
+    // The fraction of the planned duration elapsed, from 0.0 before a run to 1.0 once it is
+    // done. A run that overruns its plan, e.g. a slowed feedback ramp, stays at 1.0.
+    pub fn fraction(&self) -> f64 {
+        match *self.0.lock().unwrap() {
+            State::Idle => 0.0,
+            State::Running {
+                started_at,
+                planned,
+            } if !planned.is_zero() => {
+                (started_at.elapsed().as_secs_f64() / planned.as_secs_f64()).min(1.0)
+            }
+            State::Running { .. } | State::Finished => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fraction_covers_the_run() {
+        let progress = RunProgress::default();
+        assert_eq!(progress.fraction(), 0.0);
+        progress.start(Duration::from_secs(3600));
+        assert!(progress.fraction() < 0.01);
+        progress.start(Duration::ZERO);
+        assert_eq!(progress.fraction(), 1.0);
+        progress.finish();
+        assert_eq!(progress.fraction(), 1.0);
+    }
+}
@@ -0,0 +1,94 @@
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy)]
+pub struct RampSample {
+    pub at: Instant,
+    pub requests: usize,
+    pub ideal_vus: f64,
+    pub active_vus: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FidelityWindow {
+    pub ideal_rps: f64,
+    pub achieved_rps: f64,
+}
+
+// Overshoot and undershoot are the largest relative deviations above and below the ideal
+// rate, so 0.25 means a window ran 25% off; both are zero when the run tracked perfectly.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RampFidelity {
+    pub max_overshoot: f64,
+    pub max_undershoot: f64,
+    pub windows: Vec<FidelityWindow>,
+}
+
+impl RampFidelity {
+    // The ideal rate of a window is its planned VU count times the run-wide per-VU rate.
+    pub fn from_samples(samples: &[RampSample], window: Duration) -> Option<Self> {
+        let mut spans = Vec::new();
+        let mut start = samples.first()?;
+        let mut ideal_vu_seconds = 0.0;
+        for pair in samples.windows(2) {
+            let (previous, sample) = (&pair[0], &pair[1]);
+            ideal_vu_seconds +=
+                previous.ideal_vus * sample.at.duration_since(previous.at).as_secs_f64();
+
+            let elapsed = sample.at.duration_since(start.at);
+            if elapsed >= window {
+                let seconds = elapsed.as_secs_f64();
+                let requests = sample.requests.saturating_sub(start.requests) as f64;
+                spans.push((ideal_vu_seconds / seconds, requests / seconds, seconds));
+                ideal_vu_seconds = 0.0;
+                start = sample;
+            }
+        }
+
+        let total_requests: f64 = spans.iter().map(|(_, rps, secs)| rps * secs).sum();
+        let total_vu_seconds: f64 = spans.iter().map(|(vus, _, secs)| vus * secs).sum();
+        if total_vu_seconds <= 0.0 {
+            return None;
+        }
+        let per_vu_rps = total_requests / total_vu_seconds;
+
+        let mut fidelity = RampFidelity::default();
+        for (ideal_vus, achieved_rps, _) in spans {
+            let ideal_rps = ideal_vus * per_vu_rps;
+            if ideal_rps > 0.0 {
+                let deviation = (achieved_rps - ideal_rps) / ideal_rps;
+                fidelity.max_overshoot = fidelity.max_overshoot.max(deviation);
+                fidelity.max_undershoot = fidelity.max_undershoot.max(-deviation);
+            }
+            fidelity.windows.push(FidelityWindow {
+                ideal_rps,
+                achieved_rps,
+            });
+        }
+        Some(fidelity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deviation_is_relative_to_ideal_rate() {
+        let start = Instant::now();
+        let sample = |secs: u64, requests: usize, ideal_vus: f64| RampSample {
+            at: start + Duration::from_secs(secs),
+            requests,
+            ideal_vus,
+            active_vus: 2,
+        };
+        // Two VUs planned throughout; the first second ran 150 requests, the second 50.
+        let samples = [sample(0, 0, 2.0), sample(1, 150, 2.0), sample(2, 200, 2.0)];
+
+        let fidelity = RampFidelity::from_samples(&samples, Duration::from_secs(1)).unwrap();
+        assert_eq!(fidelity.windows.len(), 2);
+        assert!((fidelity.windows[0].ideal_rps - 100.0).abs() < 1e-9);
+        assert!((fidelity.max_overshoot - 0.5).abs() < 1e-9);
+        assert!((fidelity.max_undershoot - 0.5).abs() < 1e-9);
+        assert!(RampFidelity::from_samples(&samples[..1], Duration::from_secs(1)).is_none());
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::VecDeque;
+
+// The latencies of the last `capacity` requests, oldest first. Once full, each new sample
+// pushes out the oldest one.
+#[derive(Debug, Clone)]
+pub struct RawSamples {
+    capacity: usize,
+    samples: VecDeque<f64>,
+}
+
+impl RawSamples {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn record(&mut self, latency: f64) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency);
+    }
+
+    // Appends `other` after these samples; past the cap, the oldest of ours go first.
+    pub fn merge(&mut self, other: &RawSamples) {
+        for latency in &other.samples {
+            self.record(*latency);
+        }
+    }
+
+    pub fn samples(&self) -> Vec<f64> {
+        self.samples.iter().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keeps_most_recent_up_to_capacity() {
+        let mut raw = RawSamples::new(3);
+        raw.record(0.1);
+        raw.record(0.2);
+        assert_eq!(raw.samples(), vec![0.1, 0.2]);
+        for latency in [0.3, 0.4, 0.5] {
+            raw.record(latency);
+        }
+        assert_eq!(raw.samples(), vec![0.3, 0.4, 0.5]);
+
+        let mut other = RawSamples::new(3);
+        other.record(0.6);
+        other.record(0.7);
+        raw.merge(&other);
+        assert_eq!(raw.samples(), vec![0.5, 0.6, 0.7]);
+        assert_eq!(raw.len(), raw.capacity());
+
+        let mut disabled = RawSamples::new(0);
+        disabled.record(0.1);
+        assert!(disabled.is_empty());
+    }
+}
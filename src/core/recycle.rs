@@ -0,0 +1,126 @@
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConnectionRecycle {
+    pub after_requests: Option<usize>,
+    pub max_age: Option<Duration>,
+    pub jitter: f64,
+}
+
+impl ConnectionRecycle {
+    pub fn after_requests(requests: usize) -> Self {
+        if requests == 0 {
+            panic!("connection recycling needs at least one request per connection");
+        }
+
+        Self {
+            after_requests: Some(requests),
+            max_age: None,
+            jitter: 0.0,
+        }
+    }
+
+    pub fn after(max_age: Duration) -> Self {
+        Self {
+            after_requests: None,
+            max_age: Some(max_age),
+            jitter: 0.0,
+        }
+    }
+
+    // Spreads each limit uniformly over +/- `jitter` of itself so VUs don't reconnect in step.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        if !(0.0..1.0).contains(&jitter) {
+            panic!("connection recycle jitter must be in [0, 1)");
+        }
+        self.jitter = jitter;
+        self
+    }
+
+    fn jittered<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        1.0 + self.jitter * (2.0 * rng.gen::<f64>() - 1.0)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct RecycleTracker {
+    policy: ConnectionRecycle,
+    sent: usize,
+    limit: Option<usize>,
+    deadline: Option<Instant>,
+}
+
+impl RecycleTracker {
+    pub(crate) fn new<R: Rng + ?Sized>(policy: ConnectionRecycle, rng: &mut R) -> Self {
+        let mut tracker = Self {
+            policy,
+            sent: 0,
+            limit: None,
+            deadline: None,
+        };
+        tracker.reset(rng);
+        tracker
+    }
+
+    // True when the next request should be the last one on the current connection.
+    pub(crate) fn is_due(&self) -> bool {
+        self.limit.is_some_and(|limit| self.sent + 1 >= limit)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    pub(crate) fn record<R: Rng + ?Sized>(&mut self, recycled: bool, rng: &mut R) {
+        if recycled {
+            self.reset(rng);
+        } else {
+            self.sent += 1;
+        }
+    }
+
+    fn reset<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        self.sent = 0;
+        self.limit = self.policy.after_requests.map(|requests| {
+            ((requests as f64 * self.policy.jittered(rng)).round() as usize).max(1)
+        });
+        self.deadline = self
+            .policy
+            .max_age
+            .map(|age| Instant::now() + age.mul_f64(self.policy.jittered(rng)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_request_limit_is_jittered_around_target() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut tracker =
+            RecycleTracker::new(ConnectionRecycle::after_requests(10).jitter(0.3), &mut rng);
+        let mut lifetimes = Vec::new();
+        let mut sent = 0;
+        for _ in 0..10_000 {
+            sent += 1;
+            let due = tracker.is_due();
+            tracker.record(due, &mut rng);
+            if due {
+                lifetimes.push(sent);
+                sent = 0;
+            }
+        }
+
+        assert!(
+            lifetimes.iter().all(|n| (7..=13).contains(n)),
+            "{lifetimes:?}"
+        );
+        assert!(lifetimes.iter().any(|n| *n != 10));
+        let mean = lifetimes.iter().sum::<usize>() as f64 / lifetimes.len() as f64;
+        assert!((mean - 10.0).abs() < 0.5, "mean {mean}");
+    }
+}
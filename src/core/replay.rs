@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use std::{fs, io};
+
+use reqwest::Method;
+use serde::Deserialize;
+
+use super::request::RequestSpec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplayPacing {
+    // Each request is sent at its recorded offset from the first one.
+    #[default]
+    Original,
+    AsFastAsPossible,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub offset: Duration,
+    pub request: RequestSpec,
+}
+
+// One JSON object per line:
+// `{"offset_ms": 120, "method": "POST", "url": "...", "headers": {...}, "body": "..."}`.
+// Only `url` is required; `method` defaults to GET.
+#[derive(Debug, Deserialize)]
+struct LogLine {
+    #[serde(default)]
+    offset_ms: u64,
+    method: Option<String>,
+    url: String,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    body: Option<String>,
+}
+
+impl LogLine {
+    fn into_recorded(self) -> Option<RecordedRequest> {
+        let method = match self.method {
+            Some(method) => Method::from_bytes(method.as_bytes()).ok()?,
+            None => Method::GET,
+        };
+        let mut headers: Vec<_> = self.headers.into_iter().collect();
+        headers.sort();
+        let mut request = RequestSpec::new(&self.url).method(method);
+        request.headers = headers;
+        if let Some(body) = self.body {
+            request = request.body(body);
+        }
+        Some(RecordedRequest {
+            offset: Duration::from_millis(self.offset_ms),
+            request,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReplayLog {
+    pub requests: Vec<RecordedRequest>,
+    pub skipped_lines: usize,
+}
+
+impl ReplayLog {
+    // Blank lines are ignored; lines that don't parse are skipped and counted.
+    pub fn parse(text: &str) -> Self {
+        let mut log = ReplayLog::default();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str::<LogLine>(line)
+                .ok()
+                .and_then(LogLine::into_recorded)
+            {
+                Some(recorded) => log.requests.push(recorded),
+                None => log.skipped_lines += 1,
+            }
+        }
+        log.requests.sort_by_key(|recorded| recorded.offset);
+        log
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+}
+
+// Hands recorded requests out to VUs in order, at most once each.
+#[derive(Debug)]
+pub(crate) struct ReplayQueue {
+    requests: Vec<RecordedRequest>,
+    pacing: ReplayPacing,
+    next: AtomicUsize,
+    started_at: Instant,
+}
+
+impl ReplayQueue {
+    pub(crate) fn new(requests: Vec<RecordedRequest>, pacing: ReplayPacing) -> Self {
+        Self {
+            requests,
+            pacing,
+            next: AtomicUsize::new(0),
+            started_at: Instant::now(),
+        }
+    }
+
+    pub(crate) fn next(&self) -> Option<&RecordedRequest> {
+        self.requests.get(self.next.fetch_add(1, Ordering::Relaxed))
+    }
+
+    // When `recorded` should go out, or `None` when it should go out right away.
+    pub(crate) fn due_at(&self, recorded: &RecordedRequest) -> Option<Instant> {
+        let first = self.requests.first()?.offset;
+        match self.pacing {
+            ReplayPacing::Original => Some(self.started_at + recorded.offset.saturating_sub(first)),
+            ReplayPacing::AsFastAsPossible => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_skips_bad_lines_and_orders_by_offset() {
+        let log = ReplayLog::parse(concat!(
+            "{\"offset_ms\": 50, \"method\": \"POST\", \"url\": \"http://a/b\", \"body\": \"x\"}\n",
+            "not json\n",
+            "\n",
+            "{\"url\": \"http://a/c\", \"headers\": {\"x-id\": \"1\"}}\n",
+            "{\"method\": \"NOT A METHOD\", \"url\": \"http://a/d\"}\n",
+        ));
+        assert_eq!(log.skipped_lines, 2);
+        assert_eq!(log.requests.len(), 2);
+        assert_eq!(log.requests[0].request.url, "http://a/c");
+        assert_eq!(log.requests[0].request.method, Method::GET);
+        assert_eq!(
+            log.requests[0].request.headers,
+            vec![("x-id".into(), "1".into())]
+        );
+        assert_eq!(log.requests[1].offset, Duration::from_millis(50));
+        assert_eq!(log.requests[1].request.method, Method::POST);
+    }
+}
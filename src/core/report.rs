@@ -0,0 +1,380 @@
+use std::fmt::Write;
+
+use serde_json::{json, Value};
+
+use super::metrics::Metrics;
+use super::summary::Summary;
+
+fn format_ms(seconds: Option<f64>) -> String {
+    match seconds {
+        Some(value) => format!("{:.2}ms", value * 1000.0),
+        None => "-".to_string(),
+    }
+}
+
+fn format_pct(count: u64, total: usize) -> String {
+    if total == 0 {
+        return "-".to_string();
+    }
+    format!("{:.2}%", count as f64 / total as f64 * 100.0)
+}
+
+fn summary_json(summary: &Summary) -> Value {
+    json!({
+        "count": summary.count,
+        "avg": summary.average(),
+        "min": summary.min(),
+        "max": summary.max(),
+    })
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Metrics {
+    pub fn text_report(&self) -> String {
+        let mut report = String::new();
+        let total_requests = self.total_requests();
+
+        let _ = writeln!(report, "requests:          {}", total_requests);
+        let _ = writeln!(
+            report,
+            "errors:            {} (timeouts: {})",
+            self.total_errors(),
+            self.timeouts()
+        );
+        let mut classes: Vec<_> = self.error_classes.iter().collect();
+        classes.sort();
+        let breakdown: Vec<String> = classes
+            .iter()
+            .map(|(class, count)| format!("{} {}", class.label(), count))
+            .collect();
+        let transport_errors = self.total_errors() as u64;
+        let _ = writeln!(
+            report,
+            "transport errors:  {} ({}){}",
+            transport_errors,
+            format_pct(transport_errors, total_requests),
+            if breakdown.is_empty() {
+                String::new()
+            } else {
+                format!(": {}", breakdown.join(", "))
+            }
+        );
+        let (_, _, _, client_errors, server_errors) = self.status_class_counts();
+        let _ = writeln!(
+            report,
+            "http errors:       4xx {} ({}), 5xx {} ({})",
+            client_errors,
+            format_pct(client_errors, total_requests),
+            server_errors,
+            format_pct(server_errors, total_requests)
+        );
+        let _ = writeln!(report, "assertion fails:   {}", self.assertion_failures());
+        let _ = writeln!(
+            report,
+            "latency:           avg {}, min {}, max {}",
+            format_ms(self.total_latency.average()),
+            format_ms(self.total_latency.min()),
+            format_ms(self.total_latency.max())
+        );
+        let _ = writeln!(
+            report,
+            "latency pct:       p50 {}, p90 {}, p95 {}, p99 {}",
+            format_ms(self.latency_percentile(0.5)),
+            format_ms(self.latency_percentile(0.9)),
+            format_ms(self.latency_percentile(0.95)),
+            format_ms(self.latency_percentile(0.99))
+        );
+        let _ = writeln!(
+            report,
+            "ttfb:              avg {}",
+            format_ms(self.ttfb.average())
+        );
+
+        let (info, success, redirect, client_error, server_error) = self.status_class_counts();
+        let _ = writeln!(
+            report,
+            "status classes:    1xx {}, 2xx {}, 3xx {}, 4xx {}, 5xx {}",
+            info, success, redirect, client_error, server_error
+        );
+
+        let mut codes: Vec<_> = self.status_code_counts.iter().collect();
+        codes.sort();
+        for (code, count) in codes {
+            let _ = writeln!(report, "  {}: {}", code, count);
+        }
+
+        let _ = writeln!(report, "connection close:  {}", self.connection_close_count);
+
+        report
+    }
+
+    pub fn json_report(&self) -> Value {
+        let (info, success, redirect, client_error, server_error) = self.status_class_counts();
+        let status_codes: serde_json::Map<String, Value> = self
+            .status_code_counts
+            .iter()
+            .map(|(code, count)| (code.to_string(), json!(count)))
+            .collect();
+
+        let error_classes: serde_json::Map<String, Value> = self
+            .error_classes
+            .iter()
+            .map(|(class, count)| (class.label().to_string(), json!(count)))
+            .collect();
+
+        let per_tag: serde_json::Map<String, Value> = self
+            .per_tag
+            .iter()
+            .map(|(tag, summary)| (tag.clone(), summary_json(summary)))
+            .collect();
+
+        let slowest: Vec<Value> = self
+            .slowest(self.slowest_requests.capacity())
+            .into_iter()
+            .map(|request| {
+                json!({
+                    "url": request.url,
+                    "status": request.status,
+                    "latency": request.latency,
+                })
+            })
+            .collect();
+
+        json!({
+            "requests": self.total_requests(),
+            "throughput": self.throughput(),
+            "rps_series": self.rps_summary.get_all_rps().unwrap_or_default(),
+            "errors": self.total_errors(),
+            "timeouts": self.timeouts(),
+            "assertion_failures": self.assertion_failures(),
+            "retries": self.retries(),
+            "retries_dropped_by_budget": self.retries_dropped_by_budget(),
+            "retries_skipped_non_idempotent": self.retries_skipped_non_idempotent(),
+            "latency": {
+                "avg": self.total_latency.average(),
+                "min": self.total_latency.min(),
+                "max": self.total_latency.max(),
+                "p50": self.latency_percentile(0.5),
+                "p90": self.latency_percentile(0.9),
+                "p95": self.latency_percentile(0.95),
+                "p99": self.latency_percentile(0.99),
+                "min_at": self.latency_min_at().map(|at| at.as_secs_f64()),
+                "max_at": self.latency_max_at().map(|at| at.as_secs_f64()),
+            },
+            "ttfb": summary_json(&self.ttfb),
+            "injected_delay": summary_json(&self.injected_delay),
+            "queue_wait": summary_json(&self.queue_wait),
+            "latency_breakdown": {
+                "dns": summary_json(&self.latency_breakdown.dns),
+                "connect": summary_json(&self.latency_breakdown.connect),
+                "tls": summary_json(&self.latency_breakdown.tls),
+                "ttfb": summary_json(&self.latency_breakdown.ttfb),
+                "transfer": summary_json(&self.latency_breakdown.transfer),
+            },
+            "status_classes": {
+                "1xx": info,
+                "2xx": success,
+                "3xx": redirect,
+                "4xx": client_error,
+                "5xx": server_error,
+            },
+            "status_codes": status_codes,
+            "error_classes": error_classes,
+            "successful_responses": self.successful_responses,
+            "connect_failures": self.connect_failures,
+            "request_failures": self.request_failures,
+            "connection_close": self.connection_close_count,
+            "connection_recycles": self.connection_recycles,
+            "expect_continue_rejected": self.expect_continue_rejected,
+            "upload_bytes": self.upload_bytes,
+            "uncompressed_upload_bytes": self.uncompressed_upload_bytes,
+            "download_bytes": self.download_bytes,
+            "response_size": summary_json(&self.response_size),
+            "per_tag": per_tag,
+            "not_modified": self.not_modified,
+            "repeated_responses": self.repeated_responses,
+            "negotiation_failures": self.negotiation_failures,
+            "connection_probes": {
+                "sent": self.connection_probes,
+                "failed": self.failed_connection_probes,
+            },
+            "slowest": slowest,
+            "tags": self.tags,
+        })
+    }
+
+    // Only occupied buckets are emitted; OpenMetrics allows sparse bucket sets as long as
+    // the counts stay cumulative and end in `+Inf`.
+    pub fn to_openmetrics(&self) -> String {
+        const LATENCY: &str = "rperf_request_duration_seconds";
+        let mut out = String::new();
+        let mut tags: Vec<_> = self.tags.iter().collect();
+        tags.sort();
+        let tags: Vec<String> = tags
+            .into_iter()
+            .map(|(name, value)| format!("{name}=\"{}\"", escape_label_value(value)))
+            .collect();
+        let labels = |extra: Option<String>| -> String {
+            let all: Vec<&str> = tags
+                .iter()
+                .map(String::as_str)
+                .chain(extra.as_deref())
+                .collect();
+            if all.is_empty() {
+                String::new()
+            } else {
+                format!("{{{}}}", all.join(","))
+            }
+        };
+        let plain = labels(None);
+
+        let _ = writeln!(out, "# TYPE rperf_requests counter");
+        let _ = writeln!(out, "rperf_requests_total{plain} {}", self.total_requests());
+        let _ = writeln!(out, "# TYPE rperf_errors counter");
+        let _ = writeln!(out, "rperf_errors_total{plain} {}", self.total_errors());
+
+        let _ = writeln!(out, "# TYPE {LATENCY} histogram");
+        let _ = writeln!(out, "# UNIT {LATENCY} seconds");
+        let mut cumulative = 0;
+        for (upper_bound, count) in self.latency_histogram_buckets() {
+            cumulative += count;
+            let bucket = labels(Some(format!("le=\"{upper_bound}\"")));
+            let _ = writeln!(out, "{LATENCY}_bucket{bucket} {cumulative}");
+        }
+        let total = self.latency_histogram.count();
+        let bucket = labels(Some("le=\"+Inf\"".to_string()));
+        let _ = writeln!(out, "{LATENCY}_bucket{bucket} {total}");
+        let _ = writeln!(out, "{LATENCY}_sum{plain} {}", self.total_latency.sum);
+        let _ = writeln!(out, "{LATENCY}_count{plain} {total}");
+        let _ = writeln!(out, "# EOF");
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::error_class::ErrorClass;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_text_report_contains_status_classes() {
+        let mut metrics = Metrics::default();
+        metrics.status_code_counts.insert(200, 8);
+        metrics.status_code_counts.insert(404, 1);
+        metrics.status_code_counts.insert(500, 2);
+        metrics.record_latency(0.01);
+        metrics.http_request_time.update(0.01);
+
+        let report = metrics.text_report();
+        assert!(report.contains("1xx 0, 2xx 8, 3xx 0, 4xx 1, 5xx 2"));
+        assert!(report.contains("  200: 8"));
+        assert!(report.contains("requests:          1"));
+    }
+
+    #[test]
+    fn test_text_report_separates_transport_and_http_errors() {
+        let mut metrics = Metrics::default();
+        for _ in 0..4 {
+            metrics.record_latency(0.01);
+            metrics.http_request_time.update(0.01);
+        }
+        metrics.status_code_counts.insert(200, 2);
+        metrics.status_code_counts.insert(500, 1);
+        metrics
+            .counters
+            .errors
+            .store(1, std::sync::atomic::Ordering::Relaxed);
+        metrics.error_classes.insert(ErrorClass::Connect, 1);
+
+        let report = metrics.text_report();
+        assert!(report.contains("transport errors:  1 (25.00%): connect 1"));
+        assert!(report.contains("http errors:       4xx 0 (0.00%), 5xx 1 (25.00%)"));
+    }
+
+    #[test]
+    fn test_text_report_on_empty_metrics() {
+        let report = Metrics::default().text_report();
+        assert!(report.contains("latency:           avg -, min -, max -"));
+    }
+
+    #[test]
+    fn test_json_report() {
+        let mut metrics = Metrics::default();
+        metrics.status_code_counts.insert(200, 3);
+        metrics.record_latency(0.02);
+        metrics.http_request_time.update(0.02);
+
+        let report = metrics.json_report();
+        assert_eq!(report["requests"], 1);
+        assert_eq!(report["status_codes"]["200"], 3);
+        assert_eq!(report["status_classes"]["2xx"], 3);
+        assert_eq!(report["latency"]["max"], 0.02);
+        assert!(report["ttfb"]["avg"].is_null());
+    }
+
+    #[test]
+    fn test_tags_appear_in_every_export() {
+        let tags = HashMap::from([
+            ("env".to_string(), "staging".to_string()),
+            ("scenario".to_string(), "checkout \"v2\" flow".to_string()),
+        ]);
+        let mut metrics = Metrics::default().with_tags(tags);
+        metrics.record_latency(0.01);
+        metrics.http_request_time.update(0.01);
+
+        let report = metrics.json_report();
+        assert_eq!(report["tags"]["env"], "staging");
+        assert_eq!(report["tags"]["scenario"], "checkout \"v2\" flow");
+
+        let output = metrics.to_openmetrics();
+        let labels = r#"env="staging",scenario="checkout \"v2\" flow""#;
+        assert!(output.contains(&format!("rperf_requests_total{{{labels}}} 1\n")));
+        assert!(output.contains(&format!(
+            "rperf_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} 1\n"
+        )));
+        assert!(output.contains(&format!(
+            "rperf_request_duration_seconds_count{{{labels}}} 1\n"
+        )));
+    }
+
+    #[test]
+    fn test_openmetrics_histogram_is_cumulative() {
+        let mut metrics = Metrics::default();
+        for i in 1..=50 {
+            let latency = i as f64 / 1000.0;
+            metrics.record_latency(latency);
+            metrics.http_request_time.update(latency);
+        }
+
+        let output = metrics.to_openmetrics();
+        assert!(output.contains("# TYPE rperf_request_duration_seconds histogram"));
+        assert!(output.ends_with("# EOF\n"));
+
+        let buckets: Vec<(String, u64)> = output
+            .lines()
+            .filter_map(|line| line.strip_prefix("rperf_request_duration_seconds_bucket{le=\""))
+            .map(|rest| {
+                let (le, count) = rest.split_once("\"} ").unwrap();
+                (le.to_string(), count.parse().unwrap())
+            })
+            .collect();
+        assert!(buckets.len() > 2);
+        assert!(buckets.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        let finite: Vec<f64> = buckets[..buckets.len() - 1]
+            .iter()
+            .map(|(le, _)| le.parse().unwrap())
+            .collect();
+        assert!(finite.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(buckets.last().unwrap(), &("+Inf".to_string(), 50));
+        assert!(output.contains("rperf_request_duration_seconds_count 50\n"));
+        assert_eq!(metrics.total_requests(), 50);
+    }
+}
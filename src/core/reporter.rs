@@ -0,0 +1,83 @@
+use std::fmt;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime};
+
+use futures_core::Stream;
+use tokio::sync::mpsc;
+
+use super::metrics::Metrics;
+
+type ReportFn = dyn Fn(&Metrics) + Send + Sync;
+
+// Hands the metrics collected so far to a callback every `interval` during a run.
+#[derive(Clone)]
+pub struct IntervalReporter {
+    pub interval: Duration,
+    callback: Arc<ReportFn>,
+}
+
+impl IntervalReporter {
+    pub fn new<F>(interval: Duration, callback: F) -> Self
+    where
+        F: Fn(&Metrics) + Send + Sync + 'static,
+    {
+        if interval.is_zero() {
+            panic!("report interval must be greater than 0");
+        }
+
+        Self {
+            interval,
+            callback: Arc::new(callback),
+        }
+    }
+
+    pub fn report(&self, metrics: &Metrics) {
+        (self.callback)(metrics)
+    }
+
+    pub fn next_due(&self, due: Instant, now: Instant) -> Instant {
+        next_due(self.interval, due, now)
+    }
+}
+
+// The deadline after `due`; one that has already passed is skipped rather than caught up.
+pub(crate) fn next_due(interval: Duration, due: Instant, now: Instant) -> Instant {
+    let next = due + interval;
+    if next <= now {
+        now + interval
+    } else {
+        next
+    }
+}
+
+impl fmt::Debug for IntervalReporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IntervalReporter")
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+// One snapshot, with the wall-clock time it was taken and how far into the run that was, so
+// samples from several runs can be lined up on a common axis.
+#[derive(Debug)]
+pub struct MetricsSample {
+    pub taken_at: SystemTime,
+    pub elapsed: Duration,
+    pub metrics: Metrics,
+}
+
+// Snapshots from `VirtualUserManager::metrics_snapshots`. The last one is the overall metrics
+// of the finished run, after which the stream ends.
+#[derive(Debug)]
+pub struct MetricsStream(pub(crate) mpsc::UnboundedReceiver<MetricsSample>);
+
+impl Stream for MetricsStream {
+    type Item = MetricsSample;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<MetricsSample>> {
+        self.0.poll_recv(cx)
+    }
+}
@@ -0,0 +1,313 @@
+use std::io::Write;
+
+use bytes::Bytes;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, Method, RequestBuilder};
+
+#[derive(Debug, Clone)]
+pub struct MultipartPart {
+    pub name: String,
+    pub data: Bytes,
+    pub file_name: Option<String>,
+    pub mime: Option<String>,
+}
+
+impl MultipartPart {
+    pub fn text(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            data: Bytes::copy_from_slice(value.as_bytes()),
+            file_name: None,
+            mime: None,
+        }
+    }
+
+    pub fn file(name: &str, file_name: &str, data: impl Into<Bytes>) -> Self {
+        Self {
+            name: name.to_string(),
+            data: data.into(),
+            file_name: Some(file_name.to_string()),
+            mime: None,
+        }
+    }
+
+    pub fn mime(mut self, mime: &str) -> Self {
+        if Part::bytes(Vec::new()).mime_str(mime).is_err() {
+            panic!("invalid multipart mime type: {}", mime);
+        }
+        self.mime = Some(mime.to_string());
+        self
+    }
+
+    fn to_part(&self) -> Part {
+        let mut part = Part::stream(self.data.clone());
+        if let Some(file_name) = &self.file_name {
+            part = part.file_name(file_name.clone());
+        }
+        match &self.mime {
+            Some(mime) => part.mime_str(mime).expect("mime type was validated"),
+            None => part,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum RequestBody {
+    #[default]
+    Empty,
+    Bytes(Bytes),
+    Form(Vec<(String, String)>),
+    Multipart(Vec<MultipartPart>),
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestSpec {
+    pub method: Method,
+    pub url: String,
+    pub path: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub body: RequestBody,
+    pub expect_continue: bool,
+    pub uncompressed_len: Option<usize>,
+    pub tag: Option<String>,
+}
+
+impl RequestSpec {
+    pub fn new(url: &str) -> Self {
+        Self {
+            method: Method::GET,
+            url: url.to_string(),
+            path: None,
+            headers: Vec::new(),
+            body: RequestBody::Empty,
+            expect_continue: false,
+            uncompressed_len: None,
+            tag: None,
+        }
+    }
+
+    pub fn method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    // Joined onto `url`, or onto the config's base URL when one is set.
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn resolved_url(&self) -> String {
+        match &self.path {
+            Some(path) => join_url(&self.url, path),
+            None => self.url.clone(),
+        }
+    }
+
+    pub(crate) fn resolve(mut self, base_url: Option<&str>) -> Self {
+        if let Some(path) = self.path.take() {
+            self.url = join_url(base_url.unwrap_or(&self.url), &path);
+        }
+        self
+    }
+
+    // Groups the request's latency with others under the same tag in `Metrics::per_tag`,
+    // whatever their URLs.
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_string());
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = RequestBody::Bytes(body.into());
+        self
+    }
+
+    pub fn form(mut self, fields: Vec<(String, String)>) -> Self {
+        self.body = RequestBody::Form(fields);
+        self
+    }
+
+    pub fn multipart(mut self, parts: Vec<MultipartPart>) -> Self {
+        self.body = RequestBody::Multipart(parts);
+        self
+    }
+
+    pub fn expect_continue(mut self, enabled: bool) -> Self {
+        self.expect_continue = enabled;
+        self
+    }
+
+    // Compresses the body once up front rather than on every request.
+    pub fn gzip(mut self) -> Self {
+        if self.uncompressed_len.is_some() {
+            return self;
+        }
+        match &self.body {
+            RequestBody::Empty => self,
+            RequestBody::Bytes(bytes) => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder
+                    .write_all(bytes)
+                    .expect("writing to a Vec cannot fail");
+                let compressed = encoder.finish().expect("writing to a Vec cannot fail");
+                self.uncompressed_len = Some(bytes.len());
+                self.body = RequestBody::Bytes(compressed.into());
+                self.header("content-encoding", "gzip")
+            }
+            _ => panic!("gzip compression needs a raw byte body"),
+        }
+    }
+
+    pub fn build(&self, client: &Client) -> RequestBuilder {
+        let mut builder = client.request(self.method.clone(), self.resolved_url());
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        // `Expect: 100-continue` is meaningless without a body to hold back.
+        if self.expect_continue && !matches!(self.body, RequestBody::Empty) {
+            builder = builder.header(reqwest::header::EXPECT, "100-continue");
+        }
+
+        match &self.body {
+            RequestBody::Empty => builder,
+            RequestBody::Bytes(bytes) => builder.body(bytes.clone()),
+            RequestBody::Form(fields) => builder.form(fields),
+            // `multipart::Form` is not `Clone`, so it is rebuilt for every request.
+            RequestBody::Multipart(parts) => {
+                let form = parts.iter().fold(Form::new(), |form, part| {
+                    form.part(part.name.clone(), part.to_part())
+                });
+                builder.multipart(form)
+            }
+        }
+    }
+}
+
+// Plain concatenation rather than `Url::join`, which would drop the last segment of a base
+// without a trailing slash.
+pub fn join_url(base: &str, path: &str) -> String {
+    if path.contains("://") {
+        return path.to_string();
+    }
+    if path.is_empty() {
+        return base.to_string();
+    }
+    format!(
+        "{}/{}",
+        base.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_spec_is_get_without_body() {
+        let spec = RequestSpec::new("http://test.com/");
+        let request = spec.build(&Client::new()).build().unwrap();
+        assert_eq!(request.method(), Method::GET);
+        assert!(request.body().is_none());
+    }
+
+    #[test]
+    fn test_bytes_body_and_headers() {
+        let spec = RequestSpec::new("http://test.com/")
+            .method(Method::PUT)
+            .header("x-test", "1")
+            .body("payload");
+        let request = spec.build(&Client::new()).build().unwrap();
+        assert_eq!(request.method(), Method::PUT);
+        assert_eq!(request.headers()["x-test"], "1");
+        assert_eq!(request.body().unwrap().as_bytes(), Some(&b"payload"[..]));
+    }
+
+    #[test]
+    fn test_expect_continue_only_with_body() {
+        let spec = RequestSpec::new("http://test.com/").expect_continue(true);
+        let request = spec.build(&Client::new()).build().unwrap();
+        assert!(request.headers().get(reqwest::header::EXPECT).is_none());
+
+        let spec = spec.method(Method::PUT).body(vec![0u8; 1024]);
+        let request = spec.build(&Client::new()).build().unwrap();
+        assert_eq!(request.headers()[reqwest::header::EXPECT], "100-continue");
+    }
+
+    #[test]
+    fn test_gzip_compresses_once_and_sets_encoding() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let payload = "rperf ".repeat(200);
+        let spec = RequestSpec::new("http://test.com/")
+            .method(Method::POST)
+            .body(payload.clone())
+            .gzip()
+            .gzip();
+        assert_eq!(spec.uncompressed_len, Some(payload.len()));
+
+        let request = spec.build(&Client::new()).build().unwrap();
+        assert_eq!(request.headers()[reqwest::header::CONTENT_ENCODING], "gzip");
+        let body = request.body().unwrap().as_bytes().unwrap();
+        assert!(body.len() < payload.len());
+        let mut decoded = String::new();
+        GzDecoder::new(body).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, payload);
+    }
+
+    #[test]
+    fn test_path_joins_base_url_across_slashes() {
+        for (base, path) in [
+            ("http://test.com/api", "users/1"),
+            ("http://test.com/api/", "users/1"),
+            ("http://test.com/api", "/users/1"),
+            ("http://test.com/api/", "/users/1"),
+            ("http://test.com/api//", "//users/1"),
+        ] {
+            let spec = RequestSpec::new(base).path(path);
+            assert_eq!(
+                spec.resolved_url(),
+                "http://test.com/api/users/1",
+                "{base} + {path}"
+            );
+        }
+
+        let spec = RequestSpec::new("http://ignored.com").path("users?page=2");
+        let spec = spec.resolve(Some("http://test.com/"));
+        assert_eq!(spec.url, "http://test.com/users?page=2");
+        assert!(spec.path.is_none());
+        let request = spec.build(&Client::new()).build().unwrap();
+        assert_eq!(request.url().as_str(), "http://test.com/users?page=2");
+
+        let absolute = RequestSpec::new("http://test.com").path("https://other.com/x");
+        assert_eq!(absolute.resolved_url(), "https://other.com/x");
+    }
+
+    #[test]
+    fn test_form_body_is_urlencoded() {
+        let spec = RequestSpec::new("http://test.com/")
+            .method(Method::POST)
+            .form(vec![
+                ("name".to_string(), "rperf user".to_string()),
+                ("q".to_string(), "a&b".to_string()),
+            ]);
+        let request = spec.build(&Client::new()).build().unwrap();
+        assert_eq!(
+            request.headers()[reqwest::header::CONTENT_TYPE],
+            "application/x-www-form-urlencoded"
+        );
+        assert_eq!(
+            request.body().unwrap().as_bytes(),
+            Some(&b"name=rperf+user&q=a%26b"[..])
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::Mutex;
+use std::time::Instant;
+
+use reqwest::Method;
+
+#[derive(Debug)]
+struct BudgetState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+#[derive(Debug)]
+pub struct RetryBudget {
+    capacity: f64,
+    refill_per_sec: f64,
+    state: Mutex<BudgetState>,
+}
+
+impl RetryBudget {
+    pub fn new(capacity: usize, refill_per_sec: f64) -> Self {
+        if refill_per_sec < 0.0 {
+            panic!("retry budget refill rate must not be negative");
+        }
+
+        Self {
+            capacity: capacity as f64,
+            refill_per_sec,
+            state: Mutex::new(BudgetState {
+                tokens: capacity as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let refill = now.duration_since(state.last_refill).as_secs_f64() * self.refill_per_sec;
+        state.tokens = (state.tokens + refill).min(self.capacity);
+        state.last_refill = now;
+
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// Methods RFC 9110 allows resending without changing the outcome on the server.
+pub fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS | Method::TRACE
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_budget_is_exhausted_and_refills() {
+        let budget = RetryBudget::new(2, 20.0);
+        assert!(budget.try_acquire());
+        assert!(budget.try_acquire());
+        assert!(!budget.try_acquire());
+
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(budget.try_acquire());
+    }
+}
@@ -1,4 +1,4 @@
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use thiserror::Error;
 
@@ -8,15 +8,41 @@ pub enum RpsSummaryError {
     NotStarted,
     #[error("Request count is empty")]
     EmptyRequestCount,
+    #[error("Cannot merge RpsSummary windows of {0:?} and {1:?}")]
+    WindowSizeMismatch(Duration, Duration),
 }
 
 type Result<T> = std::result::Result<T, RpsSummaryError>;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Aggregation {
+    #[default]
+    Mean,
+    Max,
+}
+
+// Folds every `factor` consecutive points into one; a trailing partial group is kept.
+pub fn downsample(series: &[f64], factor: usize, aggregation: Aggregation) -> Vec<f64> {
+    if factor == 0 {
+        panic!("downsampling factor must be greater than 0");
+    }
+
+    series
+        .chunks(factor)
+        .map(|chunk| match aggregation {
+            Aggregation::Mean => chunk.iter().sum::<f64>() / chunk.len() as f64,
+            Aggregation::Max => chunk.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        })
+        .collect()
+}
+
 #[derive(Debug, Default)]
 pub struct RpsSummary {
     request_counts: Vec<usize>,
     window_size: Duration,
     start_time: Option<Instant>,
+    align_to_epoch: bool,
+    epoch_window_index: Option<u64>,
 }
 
 impl RpsSummary {
@@ -25,20 +51,54 @@ impl RpsSummary {
             request_counts: Vec::new(),
             window_size,
             start_time: None,
+            align_to_epoch: false,
+            epoch_window_index: None,
         }
     }
 
+    pub fn with_epoch_alignment(mut self, align_to_epoch: bool) -> Self {
+        self.align_to_epoch = align_to_epoch;
+        self
+    }
+
     pub fn start(&mut self) {
-        self.start_time = Some(Instant::now());
+        let now = Instant::now();
+        if !self.align_to_epoch {
+            self.start_time = Some(now);
+            return;
+        }
+
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let window_nanos = self.window_size.as_nanos();
+        let into_window = Duration::from_nanos((since_epoch % window_nanos) as u64);
+
+        self.start_time = Some(now.checked_sub(into_window).unwrap_or(now));
+        self.epoch_window_index = Some((since_epoch / window_nanos) as u64);
+    }
+
+    pub fn epoch_window_index(&self) -> Option<u64> {
+        self.epoch_window_index
+    }
+
+    pub fn window_counts(&self) -> &[usize] {
+        &self.request_counts
     }
 
     pub fn increment_request_count(&mut self) -> Result<()> {
+        self.record_request_at(Instant::now())
+    }
+
+    // Counts a request in the window it finished in, for samples recorded after the fact.
+    pub fn record_request_at(&mut self, at: Instant) -> Result<()> {
         if self.start_time.is_none() {
             return Err(RpsSummaryError::NotStarted);
         }
         let start_time = self.start_time.unwrap();
 
-        let elapsed = start_time.elapsed();
+        let elapsed = at.saturating_duration_since(start_time);
         let window_index = (elapsed.as_nanos() / self.window_size.as_nanos()) as usize;
 
         if window_index >= self.request_counts.len() {
@@ -73,6 +133,29 @@ impl RpsSummary {
         }
     }
 
+    pub fn get_previous_rps(&self) -> Result<Option<f64>> {
+        if self.start_time.is_none() {
+            return Err(RpsSummaryError::NotStarted);
+        }
+
+        let start_time = self.start_time.unwrap();
+
+        let elapsed = start_time.elapsed();
+        let window_index = (elapsed.as_nanos() / self.window_size.as_nanos()) as usize;
+
+        if window_index == 0 {
+            return Ok(None);
+        }
+
+        let count = self
+            .request_counts
+            .get(window_index - 1)
+            .copied()
+            .unwrap_or(0);
+
+        Ok(Some(count as f64 / self.window_size.as_secs_f64()))
+    }
+
     pub fn get_average_rps(&self) -> Result<Option<f64>> {
         if self.start_time.is_none() {
             return Err(RpsSummaryError::NotStarted);
@@ -106,9 +189,66 @@ impl RpsSummary {
         Ok(rps_vec)
     }
 
+    pub fn get_downsampled_rps(&self, factor: usize, aggregation: Aggregation) -> Result<Vec<f64>> {
+        Ok(downsample(&self.get_all_rps()?, factor, aggregation))
+    }
+
+    // Sums counts window by window. Epoch-aligned summaries line up exactly; otherwise the
+    // offset is the difference in start times rounded to the nearest window.
+    pub fn merge(&mut self, other: &RpsSummary) -> Result<()> {
+        let Some(other_start) = other.start_time else {
+            return Ok(());
+        };
+        let Some(start) = self.start_time else {
+            self.window_size = other.window_size;
+            self.start_time = Some(other_start);
+            self.epoch_window_index = other.epoch_window_index;
+            self.request_counts = other.request_counts.clone();
+            return Ok(());
+        };
+        if self.window_size != other.window_size {
+            return Err(RpsSummaryError::WindowSizeMismatch(
+                self.window_size,
+                other.window_size,
+            ));
+        }
+
+        let offset = match (self.epoch_window_index, other.epoch_window_index) {
+            (Some(index), Some(other_index)) => other_index as i64 - index as i64,
+            _ => {
+                let window = self.window_size.as_secs_f64();
+                if other_start >= start {
+                    (other_start.duration_since(start).as_secs_f64() / window).round() as i64
+                } else {
+                    -(start.duration_since(other_start).as_secs_f64() / window).round() as i64
+                }
+            }
+        };
+
+        let offset = if offset < 0 {
+            let shift = offset.unsigned_abs() as usize;
+            self.request_counts
+                .splice(0..0, std::iter::repeat_n(0, shift));
+            self.start_time = Some(other_start);
+            self.epoch_window_index = other.epoch_window_index;
+            0
+        } else {
+            offset as usize
+        };
+        let needed = offset + other.request_counts.len();
+        if self.request_counts.len() < needed {
+            self.request_counts.resize(needed, 0);
+        }
+        for (index, count) in other.request_counts.iter().enumerate() {
+            self.request_counts[offset + index] += count;
+        }
+        Ok(())
+    }
+
     pub fn reset(&mut self) {
         self.request_counts.clear();
         self.start_time = None;
+        self.epoch_window_index = None;
     }
 }
 
@@ -157,6 +297,108 @@ mod tests {
         assert!(rps_vec[1] > 0.0);
     }
 
+    #[test]
+    fn test_get_previous_rps() {
+        let window = Duration::from_millis(50);
+        let mut rps = RpsSummary::new(window);
+        rps.start();
+        assert!(rps.get_previous_rps().unwrap().is_none());
+
+        assert!(rps.increment_request_count().is_ok());
+        assert!(rps.increment_request_count().is_ok());
+
+        sleep(Duration::from_millis(60));
+
+        let previous_rps = rps.get_previous_rps().unwrap().unwrap();
+        assert!((previous_rps - 40.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_downsampled_rps_aggregates_windows() {
+        let mut rps = RpsSummary::new(Duration::from_secs(1));
+        rps.start();
+        rps.request_counts = (0..100).collect();
+
+        let means = rps.get_downsampled_rps(10, Aggregation::Mean).unwrap();
+        assert_eq!(means.len(), 10);
+        for (i, mean) in means.iter().enumerate() {
+            assert!(
+                (mean - (i * 10) as f64 - 4.5).abs() < 1e-9,
+                "window {i}: {mean}"
+            );
+        }
+
+        let maxes = rps.get_downsampled_rps(10, Aggregation::Max).unwrap();
+        assert_eq!(maxes[0], 9.0);
+        assert_eq!(maxes[9], 99.0);
+        assert_eq!(
+            downsample(&[1.0, 2.0, 3.0], 2, Aggregation::Mean),
+            vec![1.5, 3.0]
+        );
+    }
+
+    #[test]
+    fn test_merge_adds_aligned_window_counts() {
+        let window = Duration::from_secs(1);
+        let start = Instant::now();
+        let summary = |offset: u64, counts: Vec<usize>| RpsSummary {
+            request_counts: counts,
+            window_size: window,
+            start_time: Some(start + window * offset as u32),
+            align_to_epoch: true,
+            epoch_window_index: Some(100 + offset),
+        };
+
+        let mut merged = RpsSummary::new(window);
+        merged.merge(&summary(1, vec![4, 5])).unwrap();
+        merged.merge(&summary(0, vec![1, 2, 3])).unwrap();
+        merged.merge(&summary(3, vec![7])).unwrap();
+        assert_eq!(merged.window_counts(), &[1, 6, 8, 7]);
+        assert_eq!(merged.epoch_window_index(), Some(100));
+
+        let mut forward = summary(0, vec![1, 2, 3]);
+        forward.merge(&summary(1, vec![4, 5])).unwrap();
+        assert_eq!(forward.window_counts(), &[1, 6, 8]);
+
+        let mut unaligned = RpsSummary::new(window);
+        unaligned.start_time = Some(start);
+        unaligned.request_counts = vec![1];
+        assert!(unaligned.merge(&RpsSummary::new(window)).is_ok());
+        let mut other = RpsSummary::new(Duration::from_millis(500));
+        other.start();
+        assert!(unaligned.merge(&other).is_err());
+    }
+
+    #[test]
+    fn test_epoch_aligned_windows_line_up() {
+        let window = Duration::from_millis(50);
+        let mut first = RpsSummary::new(window).with_epoch_alignment(true);
+        let mut second = RpsSummary::new(window).with_epoch_alignment(true);
+
+        first.start();
+        assert!(first.increment_request_count().is_ok());
+        sleep(Duration::from_millis(70));
+        second.start();
+        assert!(first.increment_request_count().is_ok());
+        assert!(second.increment_request_count().is_ok());
+
+        let first_index = first.epoch_window_index().unwrap();
+        let second_index = second.epoch_window_index().unwrap();
+        assert!(second_index > first_index);
+
+        let first_last = first_index + first.window_counts().len() as u64 - 1;
+        let second_last = second_index + second.window_counts().len() as u64 - 1;
+        assert_eq!(first_last, second_last);
+
+        let offset = (second_index - first_index) as usize;
+        let mut combined = first.window_counts().to_vec();
+        for (i, count) in second.window_counts().iter().enumerate() {
+            combined[offset + i] += count;
+        }
+        assert_eq!(combined.iter().sum::<usize>(), 3);
+        assert_eq!(combined[combined.len() - 1], 2);
+    }
+
     #[test]
     fn test_reset() {
         let window = Duration::from_secs(1);
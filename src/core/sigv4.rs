@@ -0,0 +1,260 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use reqwest::header::{HeaderValue, AUTHORIZATION};
+use reqwest::Request;
+use sha2::{Digest, Sha256};
+
+use super::hook::RequestHook;
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+#[derive(Debug, Clone)]
+pub struct SigV4Signer {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+    region: String,
+    service: String,
+}
+
+impl SigV4Signer {
+    pub fn new(access_key: &str, secret_key: &str, region: &str, service: &str) -> Self {
+        Self {
+            access_key: access_key.to_string(),
+            secret_key: secret_key.to_string(),
+            session_token: None,
+            region: region.to_string(),
+            service: service.to_string(),
+        }
+    }
+
+    pub fn session_token(mut self, token: &str) -> Self {
+        self.session_token = Some(token.to_string());
+        self
+    }
+
+    pub fn sign(&self, request: &mut Request, now: SystemTime) {
+        let (date, amz_date) = format_timestamp(now);
+        let headers = request.headers_mut();
+        headers.remove(AUTHORIZATION);
+        headers.insert("x-amz-date", header_value(&amz_date));
+        if let Some(token) = &self.session_token {
+            headers.insert("x-amz-security-token", header_value(token));
+        }
+
+        let payload_hash = match request.body() {
+            Some(body) => match body.as_bytes() {
+                Some(bytes) => hex(&Sha256::digest(bytes)),
+                None => "UNSIGNED-PAYLOAD".to_string(),
+            },
+            None => hex(&Sha256::digest(b"")),
+        };
+
+        let (canonical_headers, signed_headers) = canonical_headers(request);
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            request.method().as_str(),
+            uri_encode(request.url().path(), false),
+            canonical_query(request),
+            canonical_headers,
+            signed_headers,
+            payload_hash
+        );
+
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+        let string_to_sign = format!(
+            "{}\n{}\n{}\n{}",
+            ALGORITHM,
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let signing_key = [self.region.as_str(), &self.service, "aws4_request"]
+            .iter()
+            .fold(
+                hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), &date),
+                |key, part| hmac_sha256(&key, part),
+            );
+        let signature = hex(&hmac_sha256(&signing_key, &string_to_sign));
+
+        let authorization = format!(
+            "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+            ALGORITHM, self.access_key, scope, signed_headers, signature
+        );
+        request
+            .headers_mut()
+            .insert(AUTHORIZATION, header_value(&authorization));
+    }
+}
+
+impl RequestHook for SigV4Signer {
+    fn before_send(&self, request: &mut Request) {
+        self.sign(request, SystemTime::now());
+    }
+}
+
+fn header_value(value: &str) -> HeaderValue {
+    HeaderValue::from_str(value).expect("SigV4 header values are ASCII")
+}
+
+fn canonical_headers(request: &Request) -> (String, String) {
+    let url = request.url();
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+
+    let mut headers: Vec<(String, String)> = vec![("host".to_string(), host)];
+    for name in request.headers().keys() {
+        let values: Vec<String> = request
+            .headers()
+            .get_all(name)
+            .iter()
+            .map(|value| {
+                String::from_utf8_lossy(value.as_bytes())
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect();
+        headers.push((name.as_str().to_string(), values.join(",")));
+    }
+    headers.sort();
+
+    let canonical = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let signed = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    (canonical, signed)
+}
+
+fn canonical_query(request: &Request) -> String {
+    let mut pairs: Vec<(String, String)> = request
+        .url()
+        .query_pairs()
+        .map(|(key, value)| (uri_encode(&key, true), uri_encode(&value, true)))
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn uri_encode(input: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Returns (`YYYYMMDD`, `YYYYMMDDTHHMMSSZ`) in UTC.
+fn format_timestamp(time: SystemTime) -> (String, String) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+    let (days, seconds_of_day) = (secs / 86_400, secs % 86_400);
+
+    // Civil-from-days conversion (Howard Hinnant's algorithm).
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let amz_date = format!(
+        "{}T{:02}{:02}{:02}Z",
+        date,
+        seconds_of_day / 3_600,
+        seconds_of_day % 3_600 / 60,
+        seconds_of_day % 60
+    );
+    (date, amz_date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Client;
+    use std::time::Duration;
+
+    const ACCESS_KEY: &str = "AKIDEXAMPLE";
+    const SECRET_KEY: &str = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+
+    fn test_time() -> SystemTime {
+        // 2015-08-30T12:36:00Z, the timestamp used by the AWS SigV4 test suite.
+        UNIX_EPOCH + Duration::from_secs(1_440_938_160)
+    }
+
+    #[test]
+    fn test_matches_aws_get_vanilla_vector() {
+        let signer = SigV4Signer::new(ACCESS_KEY, SECRET_KEY, "us-east-1", "service");
+        let mut request = Client::new()
+            .get("https://example.amazonaws.com/")
+            .build()
+            .unwrap();
+        signer.sign(&mut request, test_time());
+
+        assert_eq!(request.headers()["x-amz-date"], "20150830T123600Z");
+        assert_eq!(
+            request.headers()[AUTHORIZATION],
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn test_signs_query_headers_and_body() {
+        let signer = SigV4Signer::new(ACCESS_KEY, SECRET_KEY, "eu-west-1", "execute-api")
+            .session_token("token");
+        let mut request = Client::new()
+            .post("https://api.example.com:8443/prod/items?b=2&a=1")
+            .header("content-type", "application/json")
+            .body(r#"{"id":1}"#)
+            .build()
+            .unwrap();
+        signer.before_send(&mut request);
+
+        let authorization = request.headers()[AUTHORIZATION].to_str().unwrap();
+        assert!(authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+        assert!(authorization.contains("/eu-west-1/execute-api/aws4_request"));
+        assert!(authorization
+            .contains("SignedHeaders=content-type;host;x-amz-date;x-amz-security-token,"));
+        let signature = authorization.rsplit("Signature=").next().unwrap();
+        assert_eq!(signature.len(), 64);
+        assert!(signature.bytes().all(|byte| byte.is_ascii_hexdigit()));
+        assert_eq!(request.headers()["x-amz-date"].len(), 16);
+    }
+}
@@ -0,0 +1,136 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+pub const DEFAULT_SLOWEST_CAPACITY: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowRequest {
+    pub url: String,
+    // `None` when the request ended in a transport error or timeout.
+    pub status: Option<u16>,
+    pub latency: f64,
+}
+
+#[derive(Debug, Clone)]
+struct ByLatency(SlowRequest);
+
+impl PartialEq for ByLatency {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ByLatency {}
+
+impl PartialOrd for ByLatency {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ByLatency {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.latency.total_cmp(&other.0.latency)
+    }
+}
+
+// Keeps the `capacity` slowest requests seen; the heap root is the fastest of them, so a new
+// request only has to beat that one to get in.
+#[derive(Debug, Clone)]
+pub struct SlowestRequests {
+    capacity: usize,
+    heap: BinaryHeap<Reverse<ByLatency>>,
+}
+
+impl Default for SlowestRequests {
+    fn default() -> Self {
+        Self::new(DEFAULT_SLOWEST_CAPACITY)
+    }
+}
+
+impl SlowestRequests {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            heap: BinaryHeap::with_capacity(capacity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    pub fn would_keep(&self, latency: f64) -> bool {
+        if self.heap.len() < self.capacity {
+            return true;
+        }
+        self.heap
+            .peek()
+            .is_some_and(|Reverse(fastest)| latency > fastest.0.latency)
+    }
+
+    pub fn record(&mut self, request: SlowRequest) {
+        if !self.would_keep(request.latency) {
+            return;
+        }
+        if self.heap.len() == self.capacity {
+            self.heap.pop();
+        }
+        self.heap.push(Reverse(ByLatency(request)));
+    }
+
+    pub fn merge(&mut self, other: &SlowestRequests) {
+        for Reverse(entry) in &other.heap {
+            self.record(entry.0.clone());
+        }
+    }
+
+    // Slowest first.
+    pub fn top(&self, n: usize) -> Vec<SlowRequest> {
+        let mut entries: Vec<_> = self.heap.iter().map(|Reverse(entry)| entry).collect();
+        entries.sort_by(|a, b| b.cmp(a));
+        entries
+            .into_iter()
+            .take(n)
+            .map(|entry| entry.0.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(latency: f64) -> SlowRequest {
+        SlowRequest {
+            url: format!("http://example.com/{latency}"),
+            status: Some(200),
+            latency,
+        }
+    }
+
+    #[test]
+    fn test_keeps_slowest_and_merges() {
+        let mut slowest = SlowestRequests::new(3);
+        for latency in [0.5, 0.1, 0.9, 0.3, 0.7] {
+            slowest.record(request(latency));
+        }
+        let mut other = SlowestRequests::new(3);
+        other.record(request(0.8));
+        other.record(request(0.2));
+        slowest.merge(&other);
+
+        let latencies: Vec<f64> = slowest.top(5).iter().map(|r| r.latency).collect();
+        assert_eq!(latencies, vec![0.9, 0.8, 0.7]);
+        assert_eq!(slowest.top(1)[0].url, "http://example.com/0.9");
+        assert!(!slowest.would_keep(0.7));
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::{BTreeMap, HashMap};
+
+use reqwest::header::{HeaderMap, SET_COOKIE};
+
+use super::request::{RequestBody, RequestSpec};
+
+#[derive(Debug, Clone)]
+pub enum Extraction {
+    // A JSON pointer into the response body, e.g. `/data/token`.
+    Json(String),
+    Header(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub request: RequestSpec,
+    pub extractions: Vec<(String, Extraction)>,
+}
+
+impl Step {
+    pub fn new(request: RequestSpec) -> Self {
+        Self {
+            request,
+            extractions: Vec::new(),
+        }
+    }
+
+    pub fn extract_json(mut self, name: &str, pointer: &str) -> Self {
+        self.extractions
+            .push((name.to_string(), Extraction::Json(pointer.to_string())));
+        self
+    }
+
+    pub fn extract_header(mut self, name: &str, header: &str) -> Self {
+        self.extractions
+            .push((name.to_string(), Extraction::Header(header.to_string())));
+        self
+    }
+}
+
+// What a step keeps of its response for the steps after it.
+#[derive(Debug, Clone, Default)]
+pub struct StepResponse {
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+// Walks one VU through its steps in order, then starts over. Extracted values fill `{{name}}`
+// placeholders in the URL, headers and body of later steps, and cookies the server sets are
+// sent back on every later step.
+#[derive(Debug, Clone)]
+pub struct Steps {
+    steps: Vec<Step>,
+    next: usize,
+    values: HashMap<String, String>,
+    cookies: BTreeMap<String, String>,
+}
+
+impl Steps {
+    pub fn new(steps: Vec<Step>) -> Self {
+        if steps.is_empty() {
+            panic!("a step sequence needs at least one step");
+        }
+
+        Self {
+            steps,
+            next: 0,
+            values: HashMap::new(),
+            cookies: BTreeMap::new(),
+        }
+    }
+
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    pub fn next_request(&self) -> RequestSpec {
+        let mut request = self.steps[self.next].request.clone();
+        request.url = self.render(&request.url);
+        request.path = request.path.map(|path| self.render(&path));
+        for (_, value) in &mut request.headers {
+            *value = self.render(value);
+        }
+        match &mut request.body {
+            RequestBody::Bytes(bytes) => {
+                if let Ok(text) = std::str::from_utf8(bytes) {
+                    *bytes = self.render(text).into();
+                }
+            }
+            RequestBody::Form(fields) => {
+                for (_, value) in fields {
+                    *value = self.render(value);
+                }
+            }
+            RequestBody::Empty | RequestBody::Multipart(_) => {}
+        }
+        if !self.cookies.is_empty() {
+            let cookies: Vec<_> = self
+                .cookies
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect();
+            request = request.header("cookie", &cookies.join("; "));
+        }
+        request
+    }
+
+    // Finishes the step `next_request` built. A failed step, `None`, starts the flow over.
+    pub fn complete(&mut self, response: Option<&StepResponse>) {
+        let Some(response) = response else {
+            self.next = 0;
+            return;
+        };
+
+        for cookie in response.headers.get_all(SET_COOKIE) {
+            let pair = cookie.to_str().ok().and_then(|cookie| {
+                let (pair, _) = cookie.split_once(';').unwrap_or((cookie, ""));
+                pair.split_once('=')
+            });
+            if let Some((name, value)) = pair {
+                self.cookies
+                    .insert(name.trim().to_string(), value.trim().to_string());
+            }
+        }
+
+        let json = self.steps[self.next]
+            .extractions
+            .iter()
+            .any(|(_, extraction)| matches!(extraction, Extraction::Json(_)))
+            .then(|| serde_json::from_slice::<serde_json::Value>(&response.body).ok())
+            .flatten();
+        for (name, extraction) in &self.steps[self.next].extractions {
+            let value = match extraction {
+                Extraction::Json(pointer) => json
+                    .as_ref()
+                    .and_then(|json| json.pointer(pointer))
+                    .map(|value| match value {
+                        serde_json::Value::String(text) => text.clone(),
+                        other => other.to_string(),
+                    }),
+                Extraction::Header(header) => response
+                    .headers
+                    .get(header)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string),
+            };
+            if let Some(value) = value {
+                self.values.insert(name.clone(), value);
+            }
+        }
+        self.next = (self.next + 1) % self.steps.len();
+    }
+
+    // Unknown placeholders are left as they are.
+    fn render(&self, text: &str) -> String {
+        let mut rendered = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start..].find("}}") else {
+                break;
+            };
+            let name = rest[start + 2..start + end].trim();
+            rendered.push_str(&rest[..start]);
+            match self.values.get(name) {
+                Some(value) => rendered.push_str(value),
+                None => rendered.push_str(&rest[start..start + end + 2]),
+            }
+            rest = &rest[start + end + 2..];
+        }
+        rendered.push_str(rest);
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_extracted_values_and_cookies_reach_later_steps() {
+        let mut steps = Steps::new(vec![
+            Step::new(RequestSpec::new("http://a/login"))
+                .extract_json("token", "/data/token")
+                .extract_header("trace", "x-trace"),
+            Step::new(
+                RequestSpec::new("http://a/orders/{{trace}}")
+                    .header("authorization", "Bearer {{token}}")
+                    .body("{\"token\": \"{{token}}\", \"missing\": \"{{nope}}\"}"),
+            ),
+        ]);
+        let mut headers = HeaderMap::new();
+        headers.insert("x-trace", HeaderValue::from_static("t1"));
+        headers.append(SET_COOKIE, HeaderValue::from_static("session=s1; Path=/"));
+        steps.complete(Some(&StepResponse {
+            headers,
+            body: br#"{"data": {"token": "abc"}}"#.to_vec(),
+        }));
+
+        let request = steps.next_request();
+        assert_eq!(request.url, "http://a/orders/t1");
+        assert_eq!(
+            request.headers,
+            vec![
+                ("authorization".into(), "Bearer abc".into()),
+                ("cookie".into(), "session=s1".into()),
+            ]
+        );
+        assert!(matches!(
+            &request.body,
+            RequestBody::Bytes(body) if body == r#"{"token": "abc", "missing": "{{nope}}"}"#
+        ));
+
+        steps.complete(None);
+        assert_eq!(steps.next_request().url, "http://a/login");
+        assert_eq!(steps.value("token"), Some("abc"));
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Instant;
+
 #[derive(Debug, Clone)]
 pub struct Summary {
     pub min: f64,
@@ -71,6 +73,30 @@ impl Summary {
     }
 }
 
+// When the smallest and largest values were seen, for the few summaries where that matters.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimedExtremes {
+    pub min: Option<(f64, Instant)>,
+    pub max: Option<(f64, Instant)>,
+}
+
+impl TimedExtremes {
+    pub fn update(&mut self, value: f64, at: Instant) {
+        if self.min.is_none_or(|(min, _)| value < min) {
+            self.min = Some((value, at));
+        }
+        if self.max.is_none_or(|(max, _)| value > max) {
+            self.max = Some((value, at));
+        }
+    }
+
+    pub fn merge(&mut self, other: &TimedExtremes) {
+        for (value, at) in other.min.into_iter().chain(other.max) {
+            self.update(value, at);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
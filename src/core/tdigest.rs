@@ -0,0 +1,229 @@
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone, Copy)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct TDigest {
+    compression: f64,
+    centroids: Vec<Centroid>,
+    buffer: Vec<Centroid>,
+    count: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for TDigest {
+    fn default() -> Self {
+        Self::new(100.0)
+    }
+}
+
+impl TDigest {
+    pub fn new(compression: f64) -> Self {
+        if compression <= 0.0 {
+            panic!("t-digest compression must be greater than 0");
+        }
+
+        Self {
+            compression,
+            centroids: Vec::new(),
+            buffer: Vec::new(),
+            count: 0.0,
+            min: f64::MAX,
+            max: f64::MIN,
+        }
+    }
+
+    pub fn add(&mut self, value: f64) {
+        self.push(Centroid {
+            mean: value,
+            weight: 1.0,
+        });
+    }
+
+    pub fn merge(&mut self, other: &TDigest) {
+        for centroid in other.centroids.iter().chain(&other.buffer) {
+            self.push(*centroid);
+        }
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0.0 {
+            return None;
+        }
+
+        if !self.buffer.is_empty() {
+            let mut flushed = self.clone();
+            flushed.flush();
+            return flushed.quantile(q);
+        }
+
+        if q <= 0.0 {
+            return Some(self.min);
+        }
+        if q >= 1.0 {
+            return Some(self.max);
+        }
+        if self.centroids.len() == 1 {
+            return Some(self.centroids[0].mean);
+        }
+
+        let target = q * self.count;
+        let first = self.centroids[0];
+        if target < first.weight / 2.0 {
+            let ratio = target / (first.weight / 2.0);
+            return Some(self.min + (first.mean - self.min) * ratio);
+        }
+
+        let mut cumulative = 0.0;
+        for pair in self.centroids.windows(2) {
+            let (left, right) = (pair[0], pair[1]);
+            let left_center = cumulative + left.weight / 2.0;
+            let right_center = cumulative + left.weight + right.weight / 2.0;
+            if target <= right_center {
+                let ratio = (target - left_center) / (right_center - left_center);
+                return Some(left.mean + (right.mean - left.mean) * ratio);
+            }
+            cumulative += left.weight;
+        }
+
+        let last = self.centroids[self.centroids.len() - 1];
+        let last_center = self.count - last.weight / 2.0;
+        let ratio = (target - last_center) / (last.weight / 2.0);
+        Some(last.mean + (self.max - last.mean) * ratio.min(1.0))
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count as u64
+    }
+
+    fn push(&mut self, centroid: Centroid) {
+        self.buffer.push(centroid);
+        self.count += centroid.weight;
+        self.min = self.min.min(centroid.mean);
+        self.max = self.max.max(centroid.mean);
+
+        if self.buffer.len() as f64 >= self.compression * 5.0 {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        let mut all: Vec<Centroid> = self
+            .centroids
+            .drain(..)
+            .chain(self.buffer.drain(..))
+            .collect();
+        all.sort_by(|a, b| a.mean.total_cmp(&b.mean));
+
+        let total = self.count;
+        let mut merged = Vec::with_capacity(all.len());
+        let mut current = all[0];
+        let mut weight_so_far = 0.0;
+
+        for next in all.into_iter().skip(1) {
+            let q0 = weight_so_far / total;
+            let q2 = (weight_so_far + current.weight + next.weight) / total;
+
+            if self.scale(q2) - self.scale(q0) <= 1.0 {
+                let weight = current.weight + next.weight;
+                current.mean += (next.mean - current.mean) * next.weight / weight;
+                current.weight = weight;
+            } else {
+                weight_so_far += current.weight;
+                merged.push(current);
+                current = next;
+            }
+        }
+        merged.push(current);
+
+        self.centroids = merged;
+    }
+
+    fn scale(&self, q: f64) -> f64 {
+        self.compression / (2.0 * PI) * (2.0 * q.clamp(0.0, 1.0) - 1.0).asin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn skewed_samples(n: usize, seed: u64) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n)
+            .map(|_| {
+                let u: f64 = rng.gen();
+                0.01 * -(1.0 - u).ln() + if rng.gen_bool(0.02) { 0.5 } else { 0.0 }
+            })
+            .collect()
+    }
+
+    fn exact_quantile(samples: &[f64], q: f64) -> f64 {
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let rank = ((q * sorted.len() as f64).ceil() as usize).max(1);
+        sorted[rank - 1]
+    }
+
+    #[test]
+    fn test_empty_digest() {
+        let digest = TDigest::default();
+        assert_eq!(digest.count(), 0);
+        assert_eq!(digest.quantile(0.5), None);
+    }
+
+    #[test]
+    fn test_quantiles_on_skewed_data() {
+        let samples = skewed_samples(50_000, 3);
+        let mut digest = TDigest::default();
+        for &sample in &samples {
+            digest.add(sample);
+        }
+
+        assert_eq!(digest.count(), 50_000);
+        for q in [0.5, 0.9, 0.95, 0.99, 0.999] {
+            let exact = exact_quantile(&samples, q);
+            let estimate = digest.quantile(q).unwrap();
+            assert!(
+                (estimate - exact).abs() / exact < 0.05,
+                "q={q}: estimate {estimate}, exact {exact}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_merge_matches_single_digest() {
+        let first_samples = skewed_samples(20_000, 11);
+        let second_samples = skewed_samples(20_000, 12);
+
+        let mut first = TDigest::default();
+        let mut second = TDigest::default();
+        first_samples.iter().for_each(|&sample| first.add(sample));
+        second_samples.iter().for_each(|&sample| second.add(sample));
+        first.merge(&second);
+
+        let all: Vec<f64> = first_samples.into_iter().chain(second_samples).collect();
+        assert_eq!(first.count(), 40_000);
+        for q in [0.5, 0.95, 0.99] {
+            let exact = exact_quantile(&all, q);
+            let estimate = first.quantile(q).unwrap();
+            assert!(
+                (estimate - exact).abs() / exact < 0.05,
+                "q={q}: estimate {estimate}, exact {exact}"
+            );
+        }
+    }
+}
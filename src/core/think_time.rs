@@ -0,0 +1,187 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ThinkTime {
+    #[default]
+    None,
+    Constant(Duration),
+    Uniform {
+        min: Duration,
+        max: Duration,
+    },
+    Exponential {
+        mean: Duration,
+    },
+}
+
+impl ThinkTime {
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Duration {
+        match *self {
+            ThinkTime::None => Duration::from_secs(0),
+            ThinkTime::Constant(delay) => delay,
+            ThinkTime::Uniform { min, max } => {
+                if max <= min {
+                    return min;
+                }
+                min + (max - min).mul_f64(rng.gen::<f64>())
+            }
+            ThinkTime::Exponential { mean } => {
+                let u: f64 = rng.gen();
+                mean.mul_f64(-(1.0 - u).ln())
+            }
+        }
+    }
+
+    pub fn mean(&self) -> Duration {
+        match *self {
+            ThinkTime::None => Duration::from_secs(0),
+            ThinkTime::Constant(delay) => delay,
+            ThinkTime::Uniform { min, max } => {
+                if max <= min {
+                    return min;
+                }
+                (min + max) / 2
+            }
+            ThinkTime::Exponential { mean } => mean,
+        }
+    }
+}
+
+// Chaos option: before a `probability` fraction of requests, sleep for a `delay` sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DelayInjection {
+    pub probability: f64,
+    pub delay: ThinkTime,
+}
+
+impl DelayInjection {
+    pub fn new(probability: f64, delay: ThinkTime) -> Self {
+        if !(0.0..=1.0).contains(&probability) {
+            panic!("delay injection probability must be between 0 and 1");
+        }
+
+        Self { probability, delay }
+    }
+
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<Duration> {
+        rng.gen_bool(self.probability)
+            .then(|| self.delay.sample(rng))
+    }
+}
+
+// Extra think time that backs a VU off while its requests run over `target_latency` and
+// gives it back once they finish under it. Each request moves the delay by how far its
+// latency missed the target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptivePacing {
+    pub target_latency: Duration,
+    pub max_think_time: Duration,
+}
+
+impl AdaptivePacing {
+    pub fn new(target_latency: Duration) -> Self {
+        Self {
+            target_latency,
+            max_think_time: target_latency * 10,
+        }
+    }
+
+    pub fn max_think_time(mut self, max_think_time: Duration) -> Self {
+        self.max_think_time = max_think_time;
+        self
+    }
+
+    pub fn next(&self, think_time: Duration, latency: Duration) -> Duration {
+        if latency > self.target_latency {
+            (think_time + (latency - self.target_latency)).min(self.max_think_time)
+        } else {
+            think_time.saturating_sub(self.target_latency - latency)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn sample_mean(think_time: ThinkTime, iterations: usize) -> f64 {
+        let mut rng = StdRng::seed_from_u64(42);
+        let total: f64 = (0..iterations)
+            .map(|_| think_time.sample(&mut rng).as_secs_f64())
+            .sum();
+        total / iterations as f64
+    }
+
+    #[test]
+    fn test_none_and_constant() {
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(ThinkTime::None.sample(&mut rng), Duration::from_secs(0));
+
+        let constant = ThinkTime::Constant(Duration::from_millis(25));
+        assert_eq!(constant.sample(&mut rng), Duration::from_millis(25));
+        assert_eq!(constant.mean(), Duration::from_millis(25));
+    }
+
+    #[test]
+    fn test_uniform_stays_in_range() {
+        let think_time = ThinkTime::Uniform {
+            min: Duration::from_millis(10),
+            max: Duration::from_millis(30),
+        };
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..1000 {
+            let delay = think_time.sample(&mut rng);
+            assert!(delay >= Duration::from_millis(10));
+            assert!(delay <= Duration::from_millis(30));
+        }
+
+        let mean = sample_mean(think_time, 20_000);
+        assert!((mean - 0.02).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_exponential_mean() {
+        let think_time = ThinkTime::Exponential {
+            mean: Duration::from_millis(10),
+        };
+        let mean = sample_mean(think_time, 50_000);
+        assert!((mean - 0.01).abs() / 0.01 < 0.05);
+        assert_eq!(think_time.mean(), Duration::from_millis(10));
+    }
+
+    #[test]
+    fn test_delay_injection_hits_requested_fraction() {
+        let injection = DelayInjection::new(0.25, ThinkTime::Constant(Duration::from_millis(5)));
+        let mut rng = StdRng::seed_from_u64(3);
+        let injected: Vec<_> = (0..10_000)
+            .filter_map(|_| injection.sample(&mut rng))
+            .collect();
+        assert!((injected.len() as f64 / 10_000.0 - 0.25).abs() < 0.02);
+        assert!(injected
+            .iter()
+            .all(|delay| *delay == Duration::from_millis(5)));
+    }
+
+    #[test]
+    fn test_adaptive_pacing_follows_latency() {
+        let pacing = AdaptivePacing::new(Duration::from_millis(10));
+        let slow = pacing.next(Duration::ZERO, Duration::from_millis(40));
+        assert_eq!(slow, Duration::from_millis(30));
+        assert_eq!(
+            pacing.next(slow, Duration::from_millis(500)),
+            Duration::from_millis(100)
+        );
+        assert_eq!(
+            pacing.next(slow, Duration::from_millis(4)),
+            Duration::from_millis(24)
+        );
+        assert_eq!(
+            pacing.next(Duration::ZERO, Duration::from_millis(1)),
+            Duration::ZERO
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use std::fmt;
+use std::time::Duration;
+
+use super::metrics::Metrics;
+use super::ramp_fidelity::RampFidelity;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Threshold {
+    LatencyPercentile { q: f64, max: Duration },
+    ErrorRate { max: f64 },
+    MinThroughput { rps: f64 },
+    // Counts successes the way `RunResult::successful_requests` does: responses under 400
+    // that the success predicate accepts. The error rate only counts transport errors, so a
+    // backend that answers every request with a 5xx would otherwise pass.
+    MinSuccesses { count: u64 },
+}
+
+impl Threshold {
+    pub fn check(&self, metrics: &Metrics) -> ThresholdResult {
+        let actual = match self {
+            Threshold::LatencyPercentile { q, .. } => metrics.latency_percentile(*q),
+            Threshold::ErrorRate { .. } => metrics.error_rate(),
+            Threshold::MinThroughput { .. } => metrics.throughput(),
+            Threshold::MinSuccesses { .. } => Some(metrics.successful_responses as f64),
+        };
+        let passed = actual.is_some_and(|value| match self {
+            Threshold::LatencyPercentile { max, .. } => value <= max.as_secs_f64(),
+            Threshold::ErrorRate { max } => value <= *max,
+            Threshold::MinThroughput { rps } => value >= *rps,
+            Threshold::MinSuccesses { count } => value >= *count as f64,
+        });
+
+        ThresholdResult {
+            threshold: *self,
+            actual,
+            passed,
+        }
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Threshold::LatencyPercentile { q, max } => {
+                write!(f, "p{} latency <= {}ms", q * 100.0, max.as_millis())
+            }
+            Threshold::ErrorRate { max } => write!(f, "error rate <= {:.2}%", max * 100.0),
+            Threshold::MinThroughput { rps } => write!(f, "throughput >= {} rps", rps),
+            Threshold::MinSuccesses { count } => {
+                write!(f, "successful responses (< 400) >= {}", count)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThresholdResult {
+    pub threshold: Threshold,
+    pub actual: Option<f64>,
+    pub passed: bool,
+}
+
+// `achieved` is the mean number of requests in flight by Little's law (throughput x mean
+// latency); VUs spend the rest of their time in think time, hooks and bookkeeping.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EffectiveConcurrency {
+    pub achieved: f64,
+    pub average_vus: f64,
+    pub peak_vus: usize,
+}
+
+impl EffectiveConcurrency {
+    pub fn utilization(&self) -> Option<f64> {
+        (self.average_vus > 0.0).then(|| self.achieved / self.average_vus)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RunResult {
+    pub threshold_results: Vec<ThresholdResult>,
+    pub ramp_fidelity: Option<RampFidelity>,
+    // `(elapsed since the run started, running VUs)` at every ramp tick.
+    pub vu_series: Vec<(Duration, usize)>,
+    pub effective_concurrency: Option<EffectiveConcurrency>,
+    // Set when `max_duration` ended the run before its plan did.
+    pub duration_cap_reached: bool,
+    pub iterations_completed: Option<usize>,
+    pub skipped_log_lines: Option<usize>,
+    pub aborted: bool,
+    pub total_requests: usize,
+    // Responses with a status under 400, 304 included, that passed the success predicate, if
+    // one is set.
+    pub successful_requests: usize,
+    // Everything else: 4xx and 5xx responses, failed assertions, transport errors and timeouts.
+    pub failed_requests: usize,
+}
+
+impl RunResult {
+    pub fn passed(&self) -> bool {
+        !self.aborted && self.threshold_results.iter().all(|result| result.passed)
+    }
+
+    /// Process exit code for CI: `0` when every threshold passed, `1` when any
+    /// threshold failed, and `2` when the run was aborted before completing.
+    pub fn exit_code(&self) -> i32 {
+        if self.aborted {
+            2
+        } else if self.passed() {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics_with_latencies(latencies: &[f64], errors: usize) -> Metrics {
+        let mut metrics = Metrics::default();
+        for &latency in latencies {
+            metrics.record_latency(latency);
+            metrics.http_request_time.update(latency);
+        }
+        metrics
+            .counters
+            .errors
+            .store(errors, std::sync::atomic::Ordering::Relaxed);
+        metrics
+    }
+
+    #[test]
+    fn test_exit_code_reflects_thresholds() {
+        let latencies: Vec<f64> = (1..=100).map(|i| i as f64 / 1000.0).collect();
+        let metrics = metrics_with_latencies(&latencies, 2);
+        let check = |thresholds: &[Threshold]| RunResult {
+            threshold_results: thresholds.iter().map(|t| t.check(&metrics)).collect(),
+            ramp_fidelity: None,
+            vu_series: Vec::new(),
+            effective_concurrency: None,
+            duration_cap_reached: false,
+            iterations_completed: None,
+            skipped_log_lines: None,
+            aborted: false,
+            total_requests: 0,
+            successful_requests: 0,
+            failed_requests: 0,
+        };
+
+        let passing = check(&[
+            Threshold::LatencyPercentile {
+                q: 0.95,
+                max: Duration::from_millis(100),
+            },
+            Threshold::ErrorRate { max: 0.05 },
+        ]);
+        assert!(passing.passed());
+        assert_eq!(passing.exit_code(), 0);
+
+        let failing = check(&[Threshold::LatencyPercentile {
+            q: 0.95,
+            max: Duration::from_millis(50),
+        }]);
+        assert!(!failing.threshold_results[0].passed);
+        assert_eq!(failing.exit_code(), 1);
+
+        let aborted = RunResult {
+            aborted: true,
+            ..passing
+        };
+        assert_eq!(aborted.exit_code(), 2);
+    }
+
+    #[test]
+    fn test_threshold_without_data_fails() {
+        let result = Threshold::ErrorRate { max: 1.0 }.check(&Metrics::default());
+        assert_eq!(result.actual, None);
+        assert!(!result.passed);
+    }
+}
@@ -0,0 +1,140 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use rustls::client::{
+    ClientSessionMemoryCache, ClientSessionStore, Resumption, Tls12ClientSessionValue,
+    Tls13ClientSessionValue,
+};
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, NamedGroup, RootCertStore};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("failed to parse root certificate: {0}")]
+    Pem(#[from] std::io::Error),
+    #[error("invalid root certificate: {0}")]
+    Certificate(#[from] rustls::Error),
+    #[error("failed to build client: {0}")]
+    Client(#[from] reqwest::Error),
+    #[error("client identity has no private key")]
+    MissingKey,
+    #[error("PKCS#12 client identities are not supported with TLS session tracking")]
+    Pkcs12,
+}
+
+#[derive(Debug, Default)]
+pub struct TlsSessionStats {
+    resumed: AtomicUsize,
+    full: AtomicUsize,
+}
+
+impl TlsSessionStats {
+    pub fn resumed(&self) -> usize {
+        self.resumed.load(Ordering::Relaxed)
+    }
+
+    pub fn full(&self) -> usize {
+        self.full.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+struct CountingSessionStore {
+    inner: ClientSessionMemoryCache,
+    stats: Arc<TlsSessionStats>,
+}
+
+impl ClientSessionStore for CountingSessionStore {
+    fn set_kx_hint(&self, server_name: ServerName<'static>, group: NamedGroup) {
+        self.inner.set_kx_hint(server_name, group);
+    }
+
+    fn kx_hint(&self, server_name: &ServerName<'_>) -> Option<NamedGroup> {
+        self.inner.kx_hint(server_name)
+    }
+
+    fn set_tls12_session(&self, server_name: ServerName<'static>, value: Tls12ClientSessionValue) {
+        self.inner.set_tls12_session(server_name, value);
+    }
+
+    fn tls12_session(&self, server_name: &ServerName<'_>) -> Option<Tls12ClientSessionValue> {
+        // rustls only falls back to a TLS1.2 session after `take_tls13_ticket`
+        // came up empty, which already counted the handshake as a full one.
+        let session = self.inner.tls12_session(server_name);
+        if session.is_some() {
+            self.stats.full.fetch_sub(1, Ordering::Relaxed);
+            self.stats.resumed.fetch_add(1, Ordering::Relaxed);
+        }
+        session
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName<'static>) {
+        self.inner.remove_tls12_session(server_name);
+    }
+
+    fn insert_tls13_ticket(
+        &self,
+        server_name: ServerName<'static>,
+        value: Tls13ClientSessionValue,
+    ) {
+        self.inner.insert_tls13_ticket(server_name, value);
+    }
+
+    fn take_tls13_ticket(
+        &self,
+        server_name: &ServerName<'static>,
+    ) -> Option<Tls13ClientSessionValue> {
+        let ticket = self.inner.take_tls13_ticket(server_name);
+        if ticket.is_some() {
+            self.stats.resumed.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.stats.full.fetch_add(1, Ordering::Relaxed);
+        }
+        ticket
+    }
+}
+
+pub fn session_tracking_client(
+    builder: reqwest::ClientBuilder,
+    root_certificates: &[Vec<u8>],
+    identity: Option<(&[u8], &[u8])>,
+    stats: Arc<TlsSessionStats>,
+) -> Result<reqwest::Client, TlsError> {
+    let mut roots = RootCertStore::empty();
+    for pem in root_certificates {
+        for cert in rustls_pemfile::certs(&mut pem.as_slice()) {
+            roots.add(cert?)?;
+        }
+    }
+
+    let tls = ClientConfig::builder().with_root_certificates(roots);
+    let mut tls = match identity {
+        Some((cert, key)) => {
+            let certs = rustls_pemfile::certs(&mut &cert[..]).collect::<Result<_, _>>()?;
+            let key = rustls_pemfile::private_key(&mut &key[..])?.ok_or(TlsError::MissingKey)?;
+            tls.with_client_auth_cert(certs, key)?
+        }
+        None => tls.with_no_client_auth(),
+    };
+    tls.resumption = Resumption::store(Arc::new(CountingSessionStore {
+        inner: ClientSessionMemoryCache::new(256),
+        stats,
+    }));
+
+    let client = builder.use_preconfigured_tls(tls).build()?;
+
+    Ok(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_root_certificate_is_rejected() {
+        let stats = Arc::new(TlsSessionStats::default());
+        let pem = b"-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n".to_vec();
+        assert!(session_tracking_client(reqwest::Client::builder(), &[pem], None, stats).is_err());
+    }
+}
@@ -1,26 +1,504 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hasher};
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use once_cell::sync::Lazy;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use reqwest;
-use tokio::sync::{watch, Mutex};
-use tokio::task::JoinHandle;
+use reqwest::header::{
+    HeaderValue, ACCEPT, CACHE_CONTROL, CONNECTION, CONTENT_LENGTH, CONTENT_TYPE, ETAG,
+    IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use tokio::sync::{watch, Mutex, Semaphore};
+use tokio::task::{JoinError, JoinHandle};
 
-use super::metrics::Metrics;
+#[cfg(feature = "alarm")]
+use super::alarm::LatencyAlarm;
+use super::batch::{MetricsBatching, SampleBuffer};
+use super::breakdown::{self, ConnectTimings, LatencyBreakdown};
+use super::byte_budget::ByteBudget;
+use super::circuit_breaker::{CircuitBreaker, Permit};
+use super::error_class::ErrorClass;
+use super::fail_fast::FailFast;
+use super::fake;
+use super::hook::RequestHook;
+use super::iteration::IterationBudget;
+use super::metrics::{Counters, LatencyResolution, Metrics, PercentileBackend};
+use super::negotiation;
+use super::predicate::SuccessPredicate;
+use super::recycle::{ConnectionRecycle, RecycleTracker};
+use super::replay::ReplayQueue;
+use super::request::RequestSpec;
+use super::retry::{self, RetryBudget};
+use super::rps_summary::RpsSummary;
+use super::slowest::{SlowRequest, DEFAULT_SLOWEST_CAPACITY};
+use super::steps::{Step, StepResponse, Steps};
+use super::think_time::{AdaptivePacing, DelayInjection, ThinkTime};
+use super::virtual_user_manager::VirtualUserConfig;
+use super::vu_rate::VuRate;
+use super::weighted::WeightedSelector;
 
-static GLOBAL_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+pub(crate) static GLOBAL_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
     reqwest::Client::builder()
         .build()
         .expect("failed to build client")
 });
 
+#[derive(Debug, Clone, Default)]
+struct Validators {
+    etag: Option<HeaderValue>,
+    last_modified: Option<HeaderValue>,
+}
+
+impl Validators {
+    fn from_response(resp: &reqwest::Response) -> Self {
+        Self {
+            etag: resp.headers().get(ETAG).cloned(),
+            last_modified: resp.headers().get(LAST_MODIFIED).cloned(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+
+    fn apply(&self, req: &mut reqwest::Request) {
+        if let Some(etag) = &self.etag {
+            req.headers_mut().insert(IF_NONE_MATCH, etag.clone());
+        }
+        if let Some(last_modified) = &self.last_modified {
+            req.headers_mut()
+                .insert(IF_MODIFIED_SINCE, last_modified.clone());
+        }
+    }
+}
+
+struct ResponseInfo {
+    status: u16,
+    remote_addr: Option<SocketAddr>,
+    ttfb: f64,
+    breakdown: Option<LatencyBreakdown>,
+    body_hash: Option<u64>,
+    negotiation_failed: bool,
+    connection_close: bool,
+    assertion_failed: bool,
+    upload_bytes: usize,
+    download_bytes: usize,
+    validators: Validators,
+    step_response: Option<StepResponse>,
+}
+
+enum RequestOutcome {
+    Response(Box<ResponseInfo>),
+    Error(reqwest::Error),
+    TimedOut,
+}
+
+impl RequestOutcome {
+    fn is_failure(&self) -> bool {
+        match self {
+            RequestOutcome::Response(info) => info.status >= 500,
+            RequestOutcome::Error(_) | RequestOutcome::TimedOut => true,
+        }
+    }
+
+    fn is_success(&self) -> bool {
+        matches!(self, RequestOutcome::Response(info) if !info.assertion_failed && info.status < 400)
+    }
+
+    fn is_connect_error(&self) -> bool {
+        matches!(self, RequestOutcome::Error(e) if e.is_connect())
+    }
+
+    // The counters a VU bumps as soon as a request finishes, ahead of its batched samples.
+    fn count(&self, counters: &Counters) {
+        counters.requests.fetch_add(1, Ordering::Relaxed);
+        match self {
+            RequestOutcome::Response(info) => {
+                if info.assertion_failed {
+                    counters.assertion_failures.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            RequestOutcome::Error(_) => {
+                counters.errors.fetch_add(1, Ordering::Relaxed);
+            }
+            RequestOutcome::TimedOut => {
+                counters.errors.fetch_add(1, Ordering::Relaxed);
+                counters.timeouts.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    // Describes the outcome when it should stop a fail-fast run.
+    fn failure(&self, url: &str) -> Option<String> {
+        match self {
+            RequestOutcome::Response(info) if info.assertion_failed => {
+                Some(format!("{url}: assertion failed (HTTP {})", info.status))
+            }
+            RequestOutcome::Response(info) if info.status >= 400 => {
+                Some(format!("{url}: HTTP {}", info.status))
+            }
+            RequestOutcome::Response(_) => None,
+            RequestOutcome::Error(e) => Some(format!("{url}: {e}")),
+            RequestOutcome::TimedOut => Some(format!("{url}: timed out")),
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    fn record_on(&self, span: &tracing::Span, latency: f64) {
+        match self {
+            RequestOutcome::Response(info) => span.record("status", info.status),
+            RequestOutcome::Error(e) => span.record("error", tracing::field::display(e)),
+            RequestOutcome::TimedOut => span.record("error", "timed out"),
+        };
+        span.record("latency", latency);
+    }
+}
+
+// One span per request, retries included, for exporters to correlate with server-side traces.
+#[cfg(feature = "tracing")]
+fn request_span(request: &RequestSpec) -> tracing::Span {
+    tracing::info_span!(
+        "request",
+        method = %request.method,
+        url = %request.resolved_url(),
+        status = tracing::field::Empty,
+        latency = tracing::field::Empty,
+        error = tracing::field::Empty,
+    )
+}
+
+struct CompletedRequest {
+    finished_at: Instant,
+    url: String,
+    latency: f64,
+    recycled: bool,
+    repeated_body: bool,
+    expect_continue: bool,
+    uncompressed_len: Option<usize>,
+    tag: Option<String>,
+    outcome: RequestOutcome,
+}
+
+enum Sample {
+    InjectedDelay(f64),
+    QueueWait(f64),
+    Request(Box<CompletedRequest>),
+}
+
+impl Sample {
+    fn apply(self, m: &mut Metrics) {
+        let request = match self {
+            Sample::InjectedDelay(delay) => return m.injected_delay.update(delay),
+            Sample::QueueWait(wait) => return m.queue_wait.update(wait),
+            Sample::Request(request) => *request,
+        };
+
+        m.record_latency(request.latency);
+        m.http_request_time.update(request.latency);
+        if let Some(extremes) = m.latency_extremes.as_mut() {
+            extremes.update(request.latency, request.finished_at);
+        }
+        if m.slowest_requests.would_keep(request.latency) {
+            let status = match &request.outcome {
+                RequestOutcome::Response(info) => Some(info.status),
+                RequestOutcome::Error(_) | RequestOutcome::TimedOut => None,
+            };
+            m.slowest_requests.record(SlowRequest {
+                url: request.url,
+                status,
+                latency: request.latency,
+            });
+        }
+        if request.outcome.is_success() {
+            m.successful_responses += 1;
+        }
+        if let Some(tag) = request.tag {
+            m.per_tag.entry(tag).or_default().update(request.latency);
+        }
+        let _ = m.rps_summary.record_request_at(request.finished_at);
+        if request.recycled {
+            m.connection_recycles += 1;
+        }
+        if request.repeated_body {
+            m.repeated_responses += 1;
+        }
+
+        match request.outcome {
+            RequestOutcome::Response(info) => {
+                m.ttfb.update(info.ttfb);
+                if let Some(breakdown) = &info.breakdown {
+                    m.latency_breakdown.record(breakdown);
+                }
+                m.upload_bytes += info.upload_bytes;
+                m.download_bytes += info.download_bytes;
+                m.response_size.update(info.download_bytes as f64);
+                m.uncompressed_upload_bytes +=
+                    request.uncompressed_len.unwrap_or(info.upload_bytes);
+                if info.status == 304 {
+                    m.not_modified += 1;
+                }
+                if info.connection_close {
+                    m.connection_close_count += 1;
+                }
+                if info.negotiation_failed {
+                    m.negotiation_failures += 1;
+                }
+                *m.status_code_counts.entry(info.status).or_insert(0) += 1;
+                if let Some(addr) = info.remote_addr {
+                    *m.remote_addr_counts.entry(addr).or_insert(0) += 1;
+                }
+                // hyper swallows interim 100 responses, so a 417 is the only
+                // observable sign of the server not honoring the expectation.
+                if request.expect_continue && info.status == 417 {
+                    m.expect_continue_rejected += 1;
+                }
+            }
+            RequestOutcome::Error(e) => {
+                *m.error_classes.entry(ErrorClass::of(&e)).or_insert(0) += 1;
+                if e.is_connect() {
+                    m.connect_failures += 1;
+                } else {
+                    m.request_failures += 1;
+                }
+                m.other_errors.push(e.to_string());
+            }
+            RequestOutcome::TimedOut => {
+                *m.error_classes.entry(ErrorClass::Timeout).or_insert(0) += 1;
+            }
+        }
+    }
+}
+
+type PendingSamples = std::sync::Mutex<SampleBuffer<Sample>>;
+
+// Writes buffered samples when they are due, backing off from a contended lock until the
+// backlog forces a wait. `force` drains everything, e.g. when the VU stops.
+async fn flush_samples(metrics: &Mutex<Metrics>, pending: &PendingSamples, force: bool) {
+    let (due, must_block) = {
+        let buffer = pending.lock().unwrap();
+        (
+            !buffer.is_empty() && (force || buffer.is_due()),
+            force || buffer.must_block(),
+        )
+    };
+    if !due {
+        return;
+    }
+    let mut m = match metrics.try_lock() {
+        Ok(m) => m,
+        Err(_) if must_block => metrics.lock().await,
+        Err(_) => return,
+    };
+    // Taken only once the guard is held, so an abort can't drop samples in between.
+    drain_samples(&mut m, pending);
+}
+
+fn drain_samples(m: &mut Metrics, pending: &PendingSamples) {
+    for sample in pending.lock().unwrap().take() {
+        sample.apply(m);
+    }
+}
+
+impl From<reqwest::Result<ResponseInfo>> for RequestOutcome {
+    fn from(result: reqwest::Result<ResponseInfo>) -> Self {
+        match result {
+            Ok(info) => RequestOutcome::Response(Box::new(info)),
+            Err(e) => RequestOutcome::Error(e),
+        }
+    }
+}
+
+struct RequestContext {
+    client: reqwest::Client,
+    request: RequestSpec,
+    request_hooks: Vec<Arc<dyn RequestHook>>,
+    success_predicate: Option<SuccessPredicate>,
+    request_deadline: Option<Duration>,
+    conditional_requests: bool,
+    validate_no_store: bool,
+    accept: Option<HeaderValue>,
+    strict_negotiation: bool,
+    latency_breakdown: bool,
+}
+
+impl RequestContext {
+    async fn send(
+        &self,
+        request: &RequestSpec,
+        validators: Option<&Validators>,
+        close_connection: bool,
+        req_start: Instant,
+        capture: bool,
+    ) -> RequestOutcome {
+        let pending = self.execute(request, validators, close_connection, req_start, capture);
+        match self.request_deadline {
+            Some(deadline) => tokio::select! {
+                result = pending => RequestOutcome::from(result),
+                _ = tokio::time::sleep(deadline) => RequestOutcome::TimedOut,
+            },
+            None => RequestOutcome::from(pending.await),
+        }
+    }
+
+    async fn execute(
+        &self,
+        request: &RequestSpec,
+        validators: Option<&Validators>,
+        close_connection: bool,
+        req_start: Instant,
+        capture: bool,
+    ) -> reqwest::Result<ResponseInfo> {
+        let mut req = request.build(&self.client).build()?;
+        if let Some(validators) = validators {
+            validators.apply(&mut req);
+        }
+        if close_connection {
+            req.headers_mut()
+                .insert(CONNECTION, HeaderValue::from_static("close"));
+        }
+        if self.validate_no_store {
+            req.headers_mut()
+                .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        }
+        // A per-request Accept header wins over the configured default.
+        if let Some(accept) = &self.accept {
+            req.headers_mut()
+                .entry(ACCEPT)
+                .or_insert_with(|| accept.clone());
+        }
+        for hook in &self.request_hooks {
+            hook.before_send(&mut req);
+        }
+        let accept = self
+            .strict_negotiation
+            .then(|| req.headers().get(ACCEPT).and_then(|v| v.to_str().ok()))
+            .flatten()
+            .map(str::to_string);
+        // Multipart bodies are streamed, so take their encoded length, boundaries and part
+        // headers included, from the Content-Length reqwest set for them.
+        let upload_bytes = match req.body().and_then(|body| body.as_bytes()) {
+            Some(bytes) => bytes.len(),
+            None => req
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|len| len.to_str().ok()?.parse().ok())
+                .unwrap_or(0),
+        };
+        let timings = self.latency_breakdown.then(ConnectTimings::default);
+        let sent_at = Instant::now();
+        let mut resp = match &timings {
+            Some(timings) => breakdown::track(timings.clone(), self.client.execute(req)).await?,
+            None => self.client.execute(req).await?,
+        };
+        let headers_at = Instant::now();
+        let ttfb = headers_at.duration_since(req_start).as_secs_f64();
+        let status = resp.status().as_u16();
+        // reqwest does not say whether the connection was reused, only where it went.
+        let remote_addr = resp.remote_addr();
+        let connection_close = VirtualUser::is_connection_close(&resp);
+        let validators = Validators::from_response(&resp);
+        // Error pages rarely honor Accept, so only successful responses are checked.
+        let negotiation_failed = accept.is_some_and(|accept| {
+            let content_type = resp
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default();
+            (200..300).contains(&status) && !negotiation::is_acceptable(&accept, content_type)
+        });
+
+        let headers = capture.then(|| resp.headers().clone());
+        let mut hasher = self.validate_no_store.then(DefaultHasher::new);
+        let keep_body = capture || self.success_predicate.is_some();
+        let mut body = Vec::new();
+        let mut download_bytes = 0;
+        while let Some(chunk) = resp.chunk().await? {
+            if let Some(hasher) = &mut hasher {
+                hasher.write(&chunk);
+            }
+            if keep_body {
+                body.extend_from_slice(&chunk);
+            }
+            download_bytes += chunk.len();
+        }
+        let assertion_failed = self
+            .success_predicate
+            .as_ref()
+            .is_some_and(|predicate| !predicate.check(status, &body));
+
+        let breakdown =
+            timings.map(|timings| timings.breakdown(sent_at, headers_at, Instant::now()));
+        Ok(ResponseInfo {
+            status,
+            remote_addr,
+            ttfb,
+            breakdown,
+            body_hash: hasher.map(|hasher| hasher.finish()),
+            negotiation_failed,
+            connection_close,
+            assertion_failed,
+            upload_bytes,
+            download_bytes,
+            validators,
+            step_response: headers.map(|headers| StepResponse { headers, body }),
+        })
+    }
+}
+
 pub struct VirtualUser {
     url: String,
+    request: RequestSpec,
+    rps_window_size: Duration,
+    percentile_backend: PercentileBackend,
+    latency_resolution: LatencyResolution,
+    slowest_capacity: usize,
+    latency_timestamps: bool,
+    raw_sample_capacity: Option<usize>,
+    align_windows: bool,
     metrics: Arc<Mutex<Metrics>>,
+    counters: Arc<Counters>,
     client: reqwest::Client,
     graceful_shutdown: Duration,
+    think_time: ThinkTime,
+    adaptive_pacing: Option<AdaptivePacing>,
+    delay_injection: Option<DelayInjection>,
+    request_deadline: Option<Duration>,
+    success_predicate: Option<SuccessPredicate>,
+    request_hooks: Vec<Arc<dyn RequestHook>>,
+    max_retries: usize,
+    retry_non_idempotent: bool,
+    retry_budget: Option<Arc<RetryBudget>>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    in_flight_limit: Option<Arc<Semaphore>>,
+    iteration_budget: Option<Arc<IterationBudget>>,
+    byte_budget: Option<Arc<ByteBudget>>,
+    vu_rate: Option<Arc<VuRate>>,
+    replay: Option<Arc<ReplayQueue>>,
+    fail_fast: Option<Arc<FailFast>>,
+    #[cfg(feature = "alarm")]
+    latency_alarm: Option<Arc<LatencyAlarm>>,
+    endpoints: Vec<(RequestSpec, u32)>,
+    steps: Vec<Step>,
+    seed: Option<u64>,
+    conditional_requests: bool,
+    validate_no_store: bool,
+    accept: Option<String>,
+    strict_negotiation: bool,
+    latency_breakdown: bool,
+    record_connection_probe: bool,
+    fake_data: bool,
+    connection_recycle: Option<ConnectionRecycle>,
+    metrics_batching: Option<MetricsBatching>,
+    pending_samples: Arc<PendingSamples>,
+    expected_interval: Option<Duration>,
     shutdown_tx: Option<watch::Sender<bool>>,
     join_handle: Option<JoinHandle<()>>,
+    join_error: Option<JoinError>,
 }
 
 impl VirtualUser {
@@ -29,16 +507,108 @@ impl VirtualUser {
             panic!("rps_window_size must be greater than 0");
         }
 
+        let metrics = Metrics::new(rps_window_size);
         Self {
             url: url.to_string(),
-            metrics: Arc::new(Metrics::new(rps_window_size).into()),
+            request: RequestSpec::new(url),
+            rps_window_size,
+            percentile_backend: PercentileBackend::Histogram,
+            latency_resolution: LatencyResolution::Normal,
+            slowest_capacity: DEFAULT_SLOWEST_CAPACITY,
+            latency_timestamps: false,
+            raw_sample_capacity: None,
+            align_windows: false,
+            counters: metrics.counters.clone(),
+            metrics: Arc::new(metrics.into()),
             client: GLOBAL_CLIENT.clone(),
             graceful_shutdown: Duration::from_secs(0),
+            think_time: ThinkTime::None,
+            adaptive_pacing: None,
+            delay_injection: None,
+            request_deadline: None,
+            success_predicate: None,
+            request_hooks: Vec::new(),
+            max_retries: 0,
+            retry_non_idempotent: false,
+            retry_budget: None,
+            circuit_breaker: None,
+            in_flight_limit: None,
+            iteration_budget: None,
+            byte_budget: None,
+            vu_rate: None,
+            replay: None,
+            fail_fast: None,
+            #[cfg(feature = "alarm")]
+            latency_alarm: None,
+            endpoints: Vec::new(),
+            steps: Vec::new(),
+            seed: None,
+            conditional_requests: false,
+            validate_no_store: false,
+            accept: None,
+            strict_negotiation: false,
+            latency_breakdown: false,
+            record_connection_probe: false,
+            fake_data: false,
+            connection_recycle: None,
+            metrics_batching: None,
+            pending_samples: Arc::new(std::sync::Mutex::new(SampleBuffer::new(None))),
+            expected_interval: None,
             shutdown_tx: None,
             join_handle: None,
+            join_error: None,
         }
     }
 
+    pub fn from_config(config: &VirtualUserConfig) -> reqwest::Result<Self> {
+        let client = if let Some(client) = &config.client {
+            client.clone()
+        } else if config.uses_default_client() {
+            GLOBAL_CLIENT.clone()
+        } else {
+            config.build_client()?
+        };
+        Ok(Self::with_client(config, client))
+    }
+
+    pub fn with_client(config: &VirtualUserConfig, client: reqwest::Client) -> Self {
+        let user = Self::new(&config.url, config.rps_window_size)
+            .set_client(client)
+            .set_graceful_shutdown(config.graceful_shutdown)
+            .set_request(config.request_spec())
+            .set_think_time(config.think_time)
+            .set_adaptive_pacing(config.adaptive_pacing)
+            .set_delay_injection(config.delay_injection)
+            .set_percentile_backend(config.percentile_backend)
+            .set_latency_resolution(config.latency_resolution)
+            .set_slowest_capacity(config.slowest_capacity)
+            .set_latency_timestamps(config.latency_timestamps)
+            .set_raw_sample_capacity(config.raw_sample_capacity)
+            .set_request_deadline(config.request_deadline)
+            .set_align_windows(config.align_windows)
+            .set_success_predicate(config.success_predicate.clone())
+            .set_request_hooks(config.request_hooks.clone())
+            .set_retries(config.max_retries, config.retry_budget.clone())
+            .set_retry_non_idempotent(config.retry_non_idempotent)
+            .set_circuit_breaker(config.circuit_breaker.clone())
+            .set_endpoints(config.resolved_endpoints())
+            .set_steps(config.resolved_steps())
+            .set_seed(config.seed)
+            .set_conditional_requests(config.conditional_requests)
+            .set_validate_no_store(config.validate_no_store)
+            .set_accept(config.accept.clone())
+            .set_strict_negotiation(config.strict_negotiation)
+            .set_latency_breakdown(config.latency_breakdown)
+            .set_record_connection_probe(config.record_connection_probe)
+            .set_fake_data(config.fake_data)
+            .set_connection_recycle(config.connection_recycle)
+            .set_metrics_batching(config.metrics_batching)
+            .set_expected_interval(config.expected_interval);
+        #[cfg(feature = "alarm")]
+        let user = user.set_latency_alarm(config.latency_alarm.clone());
+        user
+    }
+
     pub fn set_graceful_shutdown(self, graceful_shutdown: Duration) -> Self {
         Self {
             graceful_shutdown,
@@ -46,140 +616,1381 @@ impl VirtualUser {
         }
     }
 
-    pub fn start(&mut self) {
-        let (tx, rx) = watch::channel(false);
-        self.shutdown_tx = Some(tx);
+    pub fn set_client(self, client: reqwest::Client) -> Self {
+        Self { client, ..self }
+    }
 
-        let url = self.url.clone();
-        let client = self.client.clone();
-        let metrics = self.metrics.clone();
+    pub fn set_request(self, request: RequestSpec) -> Self {
+        Self { request, ..self }
+    }
 
-        let handle = tokio::spawn(async move {
-            let _ = client.get(&url).send().await;
+    pub fn set_think_time(self, think_time: ThinkTime) -> Self {
+        Self { think_time, ..self }
+    }
 
-            {
-                let mut m = metrics.lock().await;
-                m.rps_summary.start();
-            }
-            loop {
-                if *rx.borrow() {
-                    break;
-                }
+    pub fn set_adaptive_pacing(self, adaptive_pacing: Option<AdaptivePacing>) -> Self {
+        Self {
+            adaptive_pacing,
+            ..self
+        }
+    }
 
-                let req_start = Instant::now();
-                let response_result = client.get(&url).send().await;
-                let latency = req_start.elapsed().as_secs_f64();
+    pub fn set_delay_injection(self, delay_injection: Option<DelayInjection>) -> Self {
+        Self {
+            delay_injection,
+            ..self
+        }
+    }
 
-                {
-                    let mut m = metrics.lock().await;
-                    m.total_latency.update(latency);
-                    m.http_request_time.update(latency);
-                    let _ = m.rps_summary.increment_request_count();
-                }
+    pub fn set_request_deadline(self, request_deadline: Option<Duration>) -> Self {
+        Self {
+            request_deadline,
+            ..self
+        }
+    }
 
-                match response_result {
-                    Ok(resp) => {
-                        let status = resp.status().as_u16();
-                        let mut m = metrics.lock().await;
-                        *m.status_code_counts.entry(status).or_insert(0) += 1;
-                    }
-                    Err(e) => {
-                        let mut m = metrics.lock().await;
-                        m.total_errors += 1;
-                        m.other_errors.push(e.to_string());
-                    }
-                }
-            }
-        });
+    pub fn set_success_predicate(self, success_predicate: Option<SuccessPredicate>) -> Self {
+        Self {
+            success_predicate,
+            ..self
+        }
+    }
 
-        self.join_handle = Some(handle);
+    pub fn set_request_hooks(self, request_hooks: Vec<Arc<dyn RequestHook>>) -> Self {
+        Self {
+            request_hooks,
+            ..self
+        }
     }
 
-    pub async fn stop(&mut self) {
-        if let Some(tx) = self.shutdown_tx.take() {
-            let _ = tx.send(true);
+    pub fn set_retries(self, max_retries: usize, retry_budget: Option<Arc<RetryBudget>>) -> Self {
+        Self {
+            max_retries,
+            retry_budget,
+            ..self
         }
+    }
 
-        if let Some(mut handle) = self.join_handle.take() {
-            if self.graceful_shutdown > Duration::from_secs(0) {
-                tokio::select! {
-                    _ = &mut handle => {},
-                    _ = tokio::time::sleep(self.graceful_shutdown) => {
-                        handle.abort();
-                    },
-                }
-            } else {
-                handle.abort();
-            }
+    pub fn set_retry_non_idempotent(self, retry_non_idempotent: bool) -> Self {
+        Self {
+            retry_non_idempotent,
+            ..self
         }
     }
 
-    pub fn metrics(&self) -> Arc<Mutex<Metrics>> {
-        self.metrics.clone()
+    pub fn set_circuit_breaker(self, circuit_breaker: Option<Arc<CircuitBreaker>>) -> Self {
+        Self {
+            circuit_breaker,
+            ..self
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tokio::time::{sleep, Duration};
-    use wiremock::matchers::{method, path};
-    use wiremock::{Mock, MockServer, ResponseTemplate};
+    #[cfg(feature = "alarm")]
+    pub fn set_latency_alarm(self, latency_alarm: Option<Arc<LatencyAlarm>>) -> Self {
+        Self {
+            latency_alarm,
+            ..self
+        }
+    }
 
-    #[test]
-    #[should_panic]
-    fn test_create_new_virtual_user_with_zero_second() {
-        let url = "http://test.com";
-        let rps_window_size = Duration::from_secs(0);
-        VirtualUser::new(url, rps_window_size);
+    pub fn set_in_flight_limit(self, in_flight_limit: Option<Arc<Semaphore>>) -> Self {
+        Self {
+            in_flight_limit,
+            ..self
+        }
     }
 
-    #[test]
-    fn test_create_new_virtual_user() {
-        let url = "http://test.com";
-        let rps_window_size = Duration::from_secs(1);
-        let vu = VirtualUser::new(url, rps_window_size);
-        assert_eq!(vu.url, url);
-        assert_eq!(vu.graceful_shutdown, Duration::from_secs(0));
+    pub fn set_iteration_budget(self, iteration_budget: Option<Arc<IterationBudget>>) -> Self {
+        Self {
+            iteration_budget,
+            ..self
+        }
     }
 
-    #[tokio::test]
-    async fn test_virtual_user_success() {
-        let mock_server = MockServer::start().await;
-        Mock::given(method("GET"))
-            .and(path("/"))
-            .respond_with(ResponseTemplate::new(200))
-            .mount(&mock_server)
-            .await;
+    pub fn set_byte_budget(self, byte_budget: Option<Arc<ByteBudget>>) -> Self {
+        Self {
+            byte_budget,
+            ..self
+        }
+    }
 
-        let url = mock_server.uri();
-        let mut vu = VirtualUser::new(&url, Duration::from_secs(1))
-            .set_graceful_shutdown(Duration::from_millis(50));
-        vu.start();
+    pub fn set_vu_rate(self, vu_rate: Option<Arc<VuRate>>) -> Self {
+        Self { vu_rate, ..self }
+    }
 
-        sleep(Duration::from_millis(200)).await;
-        vu.stop().await;
+    pub fn set_fail_fast(self, fail_fast: Option<Arc<FailFast>>) -> Self {
+        Self { fail_fast, ..self }
+    }
 
-        let metrics = vu.metrics();
-        let m = metrics.lock().await;
-        assert!(m.http_request_time.count() > 0);
-        assert!(m.status_code_counts.contains_key(&200));
-        assert_eq!(m.total_errors, 0);
+    pub(crate) fn set_replay(self, replay: Option<Arc<ReplayQueue>>) -> Self {
+        Self { replay, ..self }
     }
 
-    #[tokio::test]
-    async fn test_virtual_user_failure() {
-        let invalid_url = "http://127.0.0.1:12345";
-        let mut vu = VirtualUser::new(invalid_url, Duration::from_secs(1))
-            .set_graceful_shutdown(Duration::from_millis(50));
-        vu.start();
+    pub fn set_endpoints(self, endpoints: Vec<(RequestSpec, u32)>) -> Self {
+        Self { endpoints, ..self }
+    }
 
-        sleep(Duration::from_millis(200)).await;
-        vu.stop().await;
+    pub fn set_steps(self, steps: Vec<Step>) -> Self {
+        Self { steps, ..self }
+    }
 
-        let metrics = vu.metrics();
-        let m = metrics.lock().await;
-        assert!(m.total_errors > 0);
-        assert!(m.status_code_counts.is_empty());
+    pub fn set_seed(self, seed: Option<u64>) -> Self {
+        Self { seed, ..self }
+    }
+
+    pub fn set_conditional_requests(self, conditional_requests: bool) -> Self {
+        Self {
+            conditional_requests,
+            ..self
+        }
+    }
+
+    pub fn set_validate_no_store(self, validate_no_store: bool) -> Self {
+        Self {
+            validate_no_store,
+            ..self
+        }
+    }
+
+    pub fn set_accept(self, accept: Option<String>) -> Self {
+        Self { accept, ..self }
+    }
+
+    pub fn set_strict_negotiation(self, strict_negotiation: bool) -> Self {
+        Self {
+            strict_negotiation,
+            ..self
+        }
+    }
+
+    pub fn set_latency_breakdown(self, latency_breakdown: bool) -> Self {
+        Self {
+            latency_breakdown,
+            ..self
+        }
+    }
+
+    pub fn set_record_connection_probe(self, record_connection_probe: bool) -> Self {
+        Self {
+            record_connection_probe,
+            ..self
+        }
+    }
+
+    pub fn set_fake_data(self, fake_data: bool) -> Self {
+        Self { fake_data, ..self }
+    }
+
+    pub fn set_connection_recycle(self, connection_recycle: Option<ConnectionRecycle>) -> Self {
+        Self {
+            connection_recycle,
+            ..self
+        }
+    }
+
+    pub fn set_metrics_batching(self, metrics_batching: Option<MetricsBatching>) -> Self {
+        Self {
+            metrics_batching,
+            ..self
+        }
+    }
+
+    pub fn set_expected_interval(self, expected_interval: Option<Duration>) -> Self {
+        Self {
+            expected_interval,
+            ..self
+        }
+        .rebuild_metrics()
+    }
+
+    pub fn set_percentile_backend(self, percentile_backend: PercentileBackend) -> Self {
+        Self {
+            percentile_backend,
+            ..self
+        }
+        .rebuild_metrics()
+    }
+
+    pub fn set_latency_resolution(self, latency_resolution: LatencyResolution) -> Self {
+        Self {
+            latency_resolution,
+            ..self
+        }
+        .rebuild_metrics()
+    }
+
+    pub fn set_slowest_capacity(self, slowest_capacity: usize) -> Self {
+        Self {
+            slowest_capacity,
+            ..self
+        }
+        .rebuild_metrics()
+    }
+
+    pub fn set_latency_timestamps(self, latency_timestamps: bool) -> Self {
+        Self {
+            latency_timestamps,
+            ..self
+        }
+        .rebuild_metrics()
+    }
+
+    pub fn set_raw_sample_capacity(self, raw_sample_capacity: Option<usize>) -> Self {
+        Self {
+            raw_sample_capacity,
+            ..self
+        }
+        .rebuild_metrics()
+    }
+
+    pub fn set_align_windows(self, align_windows: bool) -> Self {
+        Self {
+            align_windows,
+            ..self
+        }
+        .rebuild_metrics()
+    }
+
+    pub fn start(&mut self) {
+        let (tx, mut rx) = watch::channel(false);
+        self.shutdown_tx = Some(tx);
+
+        let url = self.url.clone();
+        let context = RequestContext {
+            client: self.client.clone(),
+            request: self.request.clone(),
+            request_hooks: self.request_hooks.clone(),
+            success_predicate: self.success_predicate.clone(),
+            request_deadline: self.request_deadline,
+            conditional_requests: self.conditional_requests,
+            validate_no_store: self.validate_no_store,
+            accept: self
+                .accept
+                .as_deref()
+                .and_then(|accept| HeaderValue::from_str(accept).ok()),
+            strict_negotiation: self.strict_negotiation,
+            latency_breakdown: self.latency_breakdown,
+        };
+        let metrics = self.metrics.clone();
+        let counters = self.counters.clone();
+        let think_time = self.think_time;
+        let adaptive_pacing = self.adaptive_pacing;
+        let delay_injection = self.delay_injection;
+        let max_retries = self.max_retries;
+        let retry_non_idempotent = self.retry_non_idempotent;
+        let retry_budget = self.retry_budget.clone();
+        let circuit_breaker = self.circuit_breaker.clone();
+        let in_flight_limit = self.in_flight_limit.clone();
+        let iteration_budget = self.iteration_budget.clone();
+        let byte_budget = self.byte_budget.clone();
+        let vu_rate = self.vu_rate.clone();
+        let replay = self.replay.clone();
+        let fail_fast = self.fail_fast.clone();
+        #[cfg(feature = "alarm")]
+        let latency_alarm = self.latency_alarm.clone();
+        let endpoints = self.endpoints.clone();
+        let steps = self.steps.clone();
+        let seed = self.seed;
+        let connection_recycle = self.connection_recycle;
+        let record_connection_probe = self.record_connection_probe;
+        let fake_data = self.fake_data;
+        self.pending_samples = Arc::new(std::sync::Mutex::new(SampleBuffer::new(
+            self.metrics_batching,
+        )));
+        let samples = self.pending_samples.clone();
+
+        let handle = tokio::spawn(async move {
+            let mut rng = seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+            let mut recycle_tracker =
+                connection_recycle.map(|policy| RecycleTracker::new(policy, &mut rng));
+            let selector = WeightedSelector::new(endpoints.iter().map(|(_, weight)| *weight));
+            let mut validator_cache: HashMap<String, Validators> = HashMap::new();
+            let mut last_body_hashes: HashMap<String, u64> = HashMap::new();
+            let mut steps = (!steps.is_empty()).then(|| Steps::new(steps));
+            let mut paced_delay = Duration::ZERO;
+            let mut last_started: Option<Instant> = None;
+            // The warm-up request doubles as a connection probe. A replay reproduces the log
+            // exactly, so it skips it.
+            let mut probe_error = None;
+            if replay.is_none() {
+                let warm_up = context.client.get(&url).send();
+                probe_error = match context.request_deadline {
+                    Some(deadline) => match tokio::time::timeout(deadline, warm_up).await {
+                        Ok(result) => result.err().map(|e| e.to_string()),
+                        Err(_) => Some("timed out".to_string()),
+                    },
+                    None => warm_up.await.err().map(|e| e.to_string()),
+                };
+            }
+
+            {
+                let mut m = metrics.lock().await;
+                m.rps_summary.start();
+                if record_connection_probe && replay.is_none() {
+                    m.connection_probes += 1;
+                    m.failed_connection_probes += usize::from(probe_error.is_some());
+                }
+            }
+            if let (Some(fail_fast), Some(e)) = (&fail_fast, probe_error) {
+                fail_fast.trip(format!("{url}: connection probe failed: {e}"));
+                return;
+            }
+            loop {
+                if *rx.borrow() || byte_budget.as_ref().is_some_and(|budget| budget.is_spent()) {
+                    break;
+                }
+                if let (Some(rate), Some(started_at)) = (&vu_rate, last_started) {
+                    tokio::select! {
+                        _ = tokio::time::sleep_until(rate.next_start(started_at).into()) => {},
+                        _ = rx.changed() => break,
+                    }
+                }
+
+                let permit = circuit_breaker.as_ref().map(|breaker| breaker.permit());
+                if let Some(Permit::Wait(delay)) = permit {
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => continue,
+                        _ = rx.changed() => break,
+                    }
+                }
+
+                if iteration_budget
+                    .as_ref()
+                    .is_some_and(|budget| !budget.try_claim())
+                {
+                    break;
+                }
+
+                let replayed = match &replay {
+                    Some(replay) => match replay.next() {
+                        Some(recorded) => Some((recorded, replay.due_at(recorded))),
+                        None => break,
+                    },
+                    None => None,
+                };
+                if let Some((_, Some(due))) = replayed {
+                    tokio::select! {
+                        _ = tokio::time::sleep_until(due.into()) => {},
+                        _ = rx.changed() => break,
+                    }
+                }
+
+                let step_request = match (&replayed, &steps) {
+                    (None, Some(steps)) => Some(steps.next_request()),
+                    _ => None,
+                };
+                let request = match (replayed, &step_request, &selector) {
+                    (Some((recorded, _)), _, _) => &recorded.request,
+                    (None, Some(step_request), _) => step_request,
+                    (None, None, Some(selector)) => &endpoints[selector.pick(&mut rng)].0,
+                    (None, None, None) => &context.request,
+                };
+                let faked = fake_data.then(|| fake::fill(request, &mut rng));
+                let request = faked.as_ref().unwrap_or(request);
+                // Injected before `req_start` so it never counts as server latency.
+                if let Some(delay) =
+                    delay_injection.and_then(|injection| injection.sample(&mut rng))
+                {
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {},
+                        _ = rx.changed() => break,
+                    }
+                    samples
+                        .lock()
+                        .unwrap()
+                        .push(Sample::InjectedDelay(delay.as_secs_f64()));
+                    flush_samples(&metrics, &samples, false).await;
+                }
+
+                // Held until the request and its retries finish.
+                let slot = match &in_flight_limit {
+                    Some(limit) => {
+                        let wanted = Instant::now();
+                        let permit = tokio::select! {
+                            permit = limit.acquire() => permit.ok(),
+                            _ = rx.changed() => break,
+                        };
+                        samples
+                            .lock()
+                            .unwrap()
+                            .push(Sample::QueueWait(wanted.elapsed().as_secs_f64()));
+                        flush_samples(&metrics, &samples, false).await;
+                        permit
+                    }
+                    None => None,
+                };
+
+                let recycle = recycle_tracker
+                    .as_ref()
+                    .is_some_and(|tracker| tracker.is_due());
+                let req_start = Instant::now();
+                last_started = Some(req_start);
+                #[cfg(feature = "tracing")]
+                let span = request_span(request);
+                let mut attempt = 0;
+                let mut outcome = loop {
+                    let validators = context
+                        .conditional_requests
+                        .then(|| validator_cache.get(&request.url))
+                        .flatten();
+                    let send = context.send(
+                        request,
+                        validators,
+                        recycle,
+                        req_start,
+                        step_request.is_some(),
+                    );
+                    #[cfg(feature = "tracing")]
+                    let send = tracing::Instrument::instrument(send, span.clone());
+                    let outcome = send.await;
+                    if attempt >= max_retries || !outcome.is_failure() {
+                        break outcome;
+                    }
+                    // Anything that reached the server may have taken effect there.
+                    if !retry_non_idempotent
+                        && !retry::is_idempotent(&request.method)
+                        && !outcome.is_connect_error()
+                    {
+                        counters
+                            .retries_skipped_non_idempotent
+                            .fetch_add(1, Ordering::Relaxed);
+                        break outcome;
+                    }
+                    if retry_budget
+                        .as_ref()
+                        .is_some_and(|budget| !budget.try_acquire())
+                    {
+                        counters
+                            .retries_dropped_by_budget
+                            .fetch_add(1, Ordering::Relaxed);
+                        break outcome;
+                    }
+                    attempt += 1;
+                    counters.retries.fetch_add(1, Ordering::Relaxed);
+                };
+                let latency = req_start.elapsed().as_secs_f64();
+                drop(slot);
+                if let Some(pacing) = &adaptive_pacing {
+                    paced_delay = pacing.next(paced_delay, req_start.elapsed());
+                }
+                #[cfg(feature = "tracing")]
+                outcome.record_on(&span, latency);
+                #[cfg(feature = "alarm")]
+                if let Some(alarm) = &latency_alarm {
+                    alarm.record(latency);
+                }
+                if let Some(tracker) = &mut recycle_tracker {
+                    tracker.record(recycle, &mut rng);
+                }
+                if let (Some(breaker), Some(permit)) = (&circuit_breaker, permit) {
+                    breaker.record(permit, outcome.is_failure());
+                }
+
+                outcome.count(&counters);
+                let url = request.resolved_url();
+                let failure = fail_fast.as_ref().and_then(|_| outcome.failure(&url));
+                if let (Some(steps), Some(_)) = (&mut steps, &step_request) {
+                    let response = match &mut outcome {
+                        RequestOutcome::Response(info)
+                            if !info.assertion_failed && info.status < 400 =>
+                        {
+                            info.step_response.take()
+                        }
+                        _ => None,
+                    };
+                    steps.complete(response.as_ref());
+                }
+                let mut repeated_body = false;
+                if let RequestOutcome::Response(info) = &mut outcome {
+                    if let Some(budget) = &byte_budget {
+                        budget.record((info.upload_bytes + info.download_bytes) as u64);
+                    }
+                    if let Some(hash) = info.body_hash {
+                        repeated_body = last_body_hashes.insert(url.clone(), hash) == Some(hash);
+                    }
+                    if context.conditional_requests
+                        && (200..300).contains(&info.status)
+                        && !info.validators.is_empty()
+                    {
+                        validator_cache
+                            .insert(request.url.clone(), std::mem::take(&mut info.validators));
+                    }
+                }
+                samples
+                    .lock()
+                    .unwrap()
+                    .push(Sample::Request(Box::new(CompletedRequest {
+                        finished_at: Instant::now(),
+                        url,
+                        latency,
+                        recycled: recycle,
+                        repeated_body,
+                        expect_continue: request.expect_continue,
+                        uncompressed_len: request.uncompressed_len,
+                        tag: request.tag.clone(),
+                        outcome,
+                    })));
+                flush_samples(&metrics, &samples, false).await;
+                if let Some(budget) = &iteration_budget {
+                    budget.complete();
+                }
+                if let (Some(fail_fast), Some(failure)) = (&fail_fast, failure) {
+                    fail_fast.trip(failure);
+                    break;
+                }
+
+                // Replayed requests keep the log's own pacing.
+                let delay = match replay {
+                    Some(_) => Duration::ZERO,
+                    None => think_time.sample(&mut rng) + paced_delay,
+                };
+                if !delay.is_zero() {
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {},
+                        _ = rx.changed() => break,
+                    }
+                }
+            }
+            flush_samples(&metrics, &samples, true).await;
+        });
+
+        self.join_handle = Some(handle);
+    }
+
+    pub async fn stop(&mut self) {
+        self.stop_with_grace(self.graceful_shutdown).await;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.join_handle
+            .as_ref()
+            .is_none_or(|handle| handle.is_finished())
+    }
+
+    pub fn signal_stop(&self) {
+        if let Some(tx) = &self.shutdown_tx {
+            let _ = tx.send(true);
+        }
+    }
+
+    pub async fn stop_with_grace(&mut self, graceful_shutdown: Duration) {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(true);
+        }
+
+        if let Some(mut handle) = self.join_handle.take() {
+            let finished = if graceful_shutdown > Duration::from_secs(0) {
+                tokio::select! {
+                    result = &mut handle => Some(result),
+                    _ = tokio::time::sleep(graceful_shutdown) => None,
+                }
+            } else {
+                None
+            };
+
+            let result = match finished {
+                Some(result) => result,
+                None => {
+                    handle.abort();
+                    handle.await
+                }
+            };
+            // Cancellation is how a VU is normally stopped; only a panic is a failure.
+            if let Err(e) = result {
+                if e.is_panic() {
+                    self.join_error = Some(e);
+                }
+            }
+            drain_samples(&mut *self.metrics.lock().await, &self.pending_samples);
+        }
+    }
+
+    pub fn take_join_error(&mut self) -> Option<JoinError> {
+        self.join_error.take()
+    }
+
+    pub fn metrics(&self) -> Arc<Mutex<Metrics>> {
+        self.metrics.clone()
+    }
+
+    pub fn counters(&self) -> &Counters {
+        &self.counters
+    }
+
+    fn rebuild_metrics(self) -> Self {
+        let mut metrics = Metrics::new(self.rps_window_size)
+            .with_percentile_backend(self.percentile_backend)
+            .with_latency_resolution(self.latency_resolution)
+            .with_slowest_capacity(self.slowest_capacity);
+        if let Some(interval) = self.expected_interval {
+            metrics = metrics.with_coordinated_omission_correction(interval);
+        }
+        if self.latency_timestamps {
+            metrics = metrics.with_latency_timestamps();
+        }
+        if let Some(capacity) = self.raw_sample_capacity {
+            metrics = metrics.with_raw_samples(capacity);
+        }
+        metrics.rps_summary =
+            RpsSummary::new(self.rps_window_size).with_epoch_alignment(self.align_windows);
+        Self {
+            counters: metrics.counters.clone(),
+            metrics: Arc::new(Mutex::new(metrics)),
+            ..self
+        }
+    }
+
+    fn is_connection_close(resp: &reqwest::Response) -> bool {
+        let connection = resp
+            .headers()
+            .get(reqwest::header::CONNECTION)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_ascii_lowercase());
+
+        match connection {
+            Some(value) if value.contains("close") => true,
+            Some(value) if value.contains("keep-alive") => false,
+            _ => resp.version() <= reqwest::Version::HTTP_10,
+        }
+    }
+}
+
+#[cfg(test)]
+mod synthetic;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::request::MultipartPart;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::time::{sleep, Duration};
+    use wiremock::matchers::{body_string, header, method, path};
+    use wiremock::{Mock, MockServer, Respond, ResponseTemplate};
+
+    #[test]
+    #[should_panic]
+    fn test_create_new_virtual_user_with_zero_second() {
+        let url = "http://test.com";
+        let rps_window_size = Duration::from_secs(0);
+        VirtualUser::new(url, rps_window_size);
+    }
+
+    #[test]
+    fn test_create_new_virtual_user() {
+        let url = "http://test.com";
+        let rps_window_size = Duration::from_secs(1);
+        let vu = VirtualUser::new(url, rps_window_size);
+        assert_eq!(vu.url, url);
+        assert_eq!(vu.graceful_shutdown, Duration::from_secs(0));
+    }
+
+    #[tokio::test]
+    async fn test_virtual_user_success() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let url = mock_server.uri();
+        let mut vu = VirtualUser::new(&url, Duration::from_secs(1))
+            .set_graceful_shutdown(Duration::from_millis(50));
+        vu.start();
+
+        sleep(Duration::from_millis(200)).await;
+        vu.stop().await;
+
+        let metrics = vu.metrics();
+        let m = metrics.lock().await;
+        assert!(m.http_request_time.count() > 0);
+        assert!(m.status_code_counts.contains_key(&200));
+        assert_eq!(m.total_errors(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_virtual_user_histogram_buckets_cover_requests() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let mut vu = VirtualUser::new(&mock_server.uri(), Duration::from_secs(1));
+        vu.start();
+
+        sleep(Duration::from_millis(100)).await;
+        vu.stop().await;
+
+        let metrics = vu.metrics();
+        let m = metrics.lock().await;
+        let buckets = m.latency_histogram_buckets();
+        let bucket_total: u64 = buckets.iter().map(|&(_, count)| count).sum();
+        assert!(bucket_total > 0);
+        assert_eq!(bucket_total as usize, m.total_requests());
+        assert!(buckets.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[tokio::test]
+    async fn test_virtual_user_counts_connection_close() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(ResponseTemplate::new(200).insert_header("connection", "close"))
+            .mount(&mock_server)
+            .await;
+
+        let url = mock_server.uri();
+        let mut vu = VirtualUser::new(&url, Duration::from_secs(1))
+            .set_graceful_shutdown(Duration::from_millis(50));
+        vu.start();
+
+        sleep(Duration::from_millis(200)).await;
+        vu.stop().await;
+
+        let metrics = vu.metrics();
+        let m = metrics.lock().await;
+        let ok_count = m.status_code_counts.get(&200).copied().unwrap_or(0);
+        assert!(ok_count > 1);
+        assert_eq!(m.connection_close_count, ok_count);
+        assert_eq!(m.total_errors(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_virtual_user_applies_think_time() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let url = mock_server.uri();
+        let mut vu = VirtualUser::new(&url, Duration::from_secs(1))
+            .set_think_time(ThinkTime::Constant(Duration::from_millis(50)));
+        vu.start();
+
+        sleep(Duration::from_millis(300)).await;
+        vu.stop().await;
+
+        let metrics = vu.metrics();
+        let m = metrics.lock().await;
+        let count = m.http_request_time.count();
+        assert!(count > 0);
+        assert!(
+            count <= 7,
+            "expected think time to pace requests, got {count}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_virtual_user_cancels_requests_at_deadline() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(60)))
+            .mount(&mock_server)
+            .await;
+
+        let url = mock_server.uri();
+        let mut vu = VirtualUser::new(&url, Duration::from_secs(1))
+            .set_request_deadline(Some(Duration::from_millis(50)));
+        vu.start();
+
+        sleep(Duration::from_millis(300)).await;
+        vu.stop().await;
+
+        let metrics = vu.metrics();
+        let m = metrics.lock().await;
+        assert!(
+            m.timeouts() >= 2,
+            "expected repeated timeouts, got {}",
+            m.timeouts()
+        );
+        assert_eq!(m.total_errors(), m.timeouts());
+        assert!(m.status_code_counts.is_empty());
+        assert!(m.total_latency.max().unwrap() < 0.2);
+    }
+
+    #[tokio::test]
+    async fn test_virtual_user_sends_form_body() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/login"))
+            .and(header("content-type", "application/x-www-form-urlencoded"))
+            .and(body_string("user=rperf+bot&pass=p%40ss"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/login", mock_server.uri());
+        let request = RequestSpec::new(&url)
+            .method(reqwest::Method::POST)
+            .form(vec![
+                ("user".to_string(), "rperf bot".to_string()),
+                ("pass".to_string(), "p@ss".to_string()),
+            ]);
+        let mut vu = VirtualUser::new(&url, Duration::from_secs(1)).set_request(request);
+        vu.start();
+
+        sleep(Duration::from_millis(200)).await;
+        vu.stop().await;
+
+        let metrics = vu.metrics();
+        let m = metrics.lock().await;
+        assert!(m.status_code_counts.get(&200).copied().unwrap_or(0) > 0);
+        assert_eq!(m.status_code_counts.get(&404), None);
+    }
+
+    #[derive(Debug)]
+    struct TraceHook;
+
+    impl RequestHook for TraceHook {
+        fn before_send(&self, request: &mut reqwest::Request) {
+            request
+                .headers_mut()
+                .insert("x-trace", "rperf".parse().unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_virtual_user_runs_request_hooks() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/hooked"))
+            .and(header("x-trace", "rperf"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/hooked", mock_server.uri());
+        let mut vu = VirtualUser::new(&url, Duration::from_secs(1))
+            .set_request_hooks(vec![Arc::new(TraceHook)]);
+        vu.start();
+
+        sleep(Duration::from_millis(200)).await;
+        vu.stop().await;
+
+        let metrics = vu.metrics();
+        let m = metrics.lock().await;
+        assert!(m.status_code_counts.get(&200).copied().unwrap_or(0) > 0);
+        assert_eq!(m.status_code_counts.get(&404), None);
+    }
+
+    #[tokio::test]
+    async fn test_virtual_user_sends_conditional_requests() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/cached"))
+            .and(header("if-none-match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .with_priority(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/cached"))
+            .respond_with(ResponseTemplate::new(200).insert_header("etag", "\"v1\""))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/cached", mock_server.uri());
+        let mut vu = VirtualUser::new(&url, Duration::from_secs(1)).set_conditional_requests(true);
+        vu.start();
+
+        sleep(Duration::from_millis(200)).await;
+        vu.stop().await;
+
+        let metrics = vu.metrics();
+        let m = metrics.lock().await;
+        assert_eq!(m.status_code_counts.get(&200), Some(&1));
+        assert!(m.not_modified > 0);
+        assert_eq!(m.status_code_counts.get(&304), Some(&m.not_modified));
+    }
+
+    struct DistinctBodies(std::sync::atomic::AtomicUsize);
+
+    impl Respond for DistinctBodies {
+        fn respond(&self, _: &wiremock::Request) -> ResponseTemplate {
+            let seen = self.0.fetch_add(1, Ordering::Relaxed);
+            ResponseTemplate::new(200).set_body_string(format!("response {seen}"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_store_validation_flags_no_distinct_bodies() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("cache-control", "no-store"))
+            .respond_with(DistinctBodies(Default::default()))
+            .mount(&mock_server)
+            .await;
+
+        let mut vu = VirtualUser::new(&mock_server.uri(), Duration::from_secs(1))
+            .set_validate_no_store(true);
+        vu.start();
+
+        sleep(Duration::from_millis(200)).await;
+        vu.stop().await;
+
+        let metrics = vu.metrics();
+        let m = metrics.lock().await;
+        assert!(m.total_requests() > 1);
+        assert_eq!(
+            m.status_code_counts.get(&200).copied(),
+            Some(m.total_requests())
+        );
+        assert_eq!(m.repeated_responses, 0);
+    }
+
+    #[tokio::test]
+    async fn test_no_store_validation_counts_identical_bodies() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("cached"))
+            .mount(&mock_server)
+            .await;
+
+        let mut vu = VirtualUser::new(&mock_server.uri(), Duration::from_secs(1))
+            .set_validate_no_store(true);
+        vu.start();
+
+        sleep(Duration::from_millis(200)).await;
+        vu.stop().await;
+
+        let metrics = vu.metrics();
+        let m = metrics.lock().await;
+        assert!(m.repeated_responses > 0);
+        assert_eq!(m.repeated_responses, m.total_requests() - 1);
+    }
+
+    // Counts requests completed while something else holds the VU's metrics lock.
+    async fn requests_while_metrics_locked(batching: Option<MetricsBatching>) -> usize {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let mut vu = VirtualUser::new(&mock_server.uri(), Duration::from_secs(1))
+            .set_metrics_batching(batching);
+        vu.start();
+        sleep(Duration::from_millis(100)).await;
+
+        let metrics = vu.metrics();
+        let guard = metrics.lock().await;
+        let before = vu.counters().requests.load(Ordering::Relaxed);
+        sleep(Duration::from_millis(300)).await;
+        let during = vu.counters().requests.load(Ordering::Relaxed) - before;
+        drop(guard);
+        vu.stop().await;
+
+        let m = metrics.lock().await;
+        assert_eq!(
+            m.total_requests(),
+            vu.counters().requests.load(Ordering::Relaxed)
+        );
+        during
+    }
+
+    #[tokio::test]
+    async fn test_batched_metrics_keep_progress_under_lock_contention() {
+        let baseline = requests_while_metrics_locked(None).await;
+        let batched = requests_while_metrics_locked(Some(MetricsBatching::new(
+            100_000,
+            Duration::from_millis(50),
+        )))
+        .await;
+        assert!(baseline <= 1, "baseline made {baseline} requests");
+        assert!(
+            batched > 10 * (baseline + 1),
+            "batched made {batched} requests"
+        );
+    }
+
+    struct StallEvery(usize, std::sync::atomic::AtomicUsize);
+
+    impl Respond for StallEvery {
+        fn respond(&self, _: &wiremock::Request) -> ResponseTemplate {
+            let seen = self.1.fetch_add(1, Ordering::Relaxed) + 1;
+            let template = ResponseTemplate::new(200);
+            if seen.is_multiple_of(self.0) {
+                template.set_delay(Duration::from_millis(150))
+            } else {
+                template
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_coordinated_omission_correction_raises_tail() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(StallEvery(300, Default::default()))
+            .mount(&mock_server)
+            .await;
+
+        let mut vu = VirtualUser::new(&mock_server.uri(), Duration::from_secs(1))
+            .set_expected_interval(Some(Duration::from_millis(2)));
+        vu.start();
+
+        sleep(Duration::from_millis(1000)).await;
+        vu.stop().await;
+
+        let metrics = vu.metrics();
+        let m = metrics.lock().await;
+        let uncorrected = m.latency_percentile(0.99).unwrap();
+        let corrected = m.corrected_latency_percentile(0.99).unwrap();
+        assert!(
+            corrected > uncorrected * 2.0,
+            "corrected p99 {corrected}, uncorrected p99 {uncorrected}"
+        );
+        assert!(
+            m.corrected_latency_histogram.as_ref().unwrap().count() > m.latency_histogram.count()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_virtual_user_uploads_multipart() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/upload"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/upload", mock_server.uri());
+        let request = RequestSpec::new(&url)
+            .method(reqwest::Method::POST)
+            .multipart(vec![
+                MultipartPart::text("title", "report"),
+                MultipartPart::file("file", "data.csv", "a,b\n1,2\n").mime("text/csv"),
+            ]);
+        let mut vu = VirtualUser::new(&url, Duration::from_secs(1)).set_request(request);
+        vu.start();
+
+        sleep(Duration::from_millis(200)).await;
+        vu.stop().await;
+
+        let metrics = vu.metrics();
+        let m = metrics.lock().await;
+        let ok_count = m.status_code_counts.get(&200).copied().unwrap_or(0);
+        assert!(ok_count > 0);
+
+        let received = mock_server.received_requests().await.unwrap();
+        let upload = received
+            .iter()
+            .find(|request| request.method == wiremock::http::Method::POST)
+            .unwrap();
+        assert_eq!(
+            upload.headers["content-length"].to_str().unwrap(),
+            upload.body.len().to_string()
+        );
+        assert_eq!(m.upload_bytes, ok_count * upload.body.len());
+        let content_type = upload.headers["content-type"].to_str().unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/form-data; boundary=")
+            .unwrap();
+        let body = String::from_utf8_lossy(&upload.body);
+        assert!(body.starts_with(&format!("--{}\r\n", boundary)));
+        assert!(body.contains("Content-Disposition: form-data; name=\"title\"\r\n\r\nreport"));
+        assert!(body.contains(
+            "Content-Disposition: form-data; name=\"file\"; filename=\"data.csv\"\r\n\
+             Content-Type: text/csv\r\n\r\na,b\n1,2\n"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_virtual_user_sends_expect_continue() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(header("expect", "100-continue"))
+            .respond_with(ResponseTemplate::new(417))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/upload", mock_server.uri());
+        let request = RequestSpec::new(&url)
+            .method(reqwest::Method::PUT)
+            .body(vec![b'x'; 64 * 1024])
+            .expect_continue(true);
+        let mut vu = VirtualUser::new(&url, Duration::from_secs(1)).set_request(request);
+        vu.start();
+
+        sleep(Duration::from_millis(200)).await;
+        vu.stop().await;
+
+        let metrics = vu.metrics();
+        let m = metrics.lock().await;
+        let rejected = m.status_code_counts.get(&417).copied().unwrap_or(0);
+        assert!(rejected > 0);
+        assert_eq!(m.expect_continue_rejected, rejected);
+    }
+
+    #[tokio::test]
+    async fn test_virtual_user_success_predicate_flags_embedded_errors() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"ok":false}"#))
+            .mount(&mock_server)
+            .await;
+
+        let predicate = SuccessPredicate::new(|status, body| {
+            status == 200 && !body.windows(10).any(|w| w == br#""ok":false"#)
+        });
+        let mut vu = VirtualUser::new(&mock_server.uri(), Duration::from_secs(1))
+            .set_success_predicate(Some(predicate));
+        vu.start();
+
+        sleep(Duration::from_millis(100)).await;
+        vu.stop().await;
+
+        let metrics = vu.metrics();
+        let m = metrics.lock().await;
+        let ok_count = m.status_code_counts.get(&200).copied().unwrap_or(0);
+        assert!(ok_count > 0);
+        assert_eq!(m.assertion_failures(), ok_count);
+        assert_eq!(m.total_errors(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_virtual_user_stop_joins_aborted_task() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(100)))
+            .mount(&mock_server)
+            .await;
+
+        let mut vu = VirtualUser::new(&mock_server.uri(), Duration::from_secs(1))
+            .set_graceful_shutdown(Duration::from_millis(10));
+        vu.start();
+
+        sleep(Duration::from_millis(250)).await;
+        vu.stop().await;
+
+        let metrics = vu.metrics();
+        let count_at_stop = metrics.lock().await.total_requests();
+        sleep(Duration::from_millis(200)).await;
+        assert_eq!(metrics.lock().await.total_requests(), count_at_stop);
+        assert_eq!(Arc::strong_count(&metrics), 2);
+    }
+
+    #[tokio::test]
+    async fn test_virtual_user_failure() {
+        let invalid_url = "http://127.0.0.1:12345";
+        let mut vu = VirtualUser::new(invalid_url, Duration::from_secs(1))
+            .set_graceful_shutdown(Duration::from_millis(50));
+        vu.start();
+
+        sleep(Duration::from_millis(200)).await;
+        vu.stop().await;
+
+        let metrics = vu.metrics();
+        let m = metrics.lock().await;
+        assert!(m.total_errors() > 0);
+        assert!(m.status_code_counts.is_empty());
+    }
+
+    async fn start_delayed_body_server(body_delay: Duration) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    loop {
+                        match stream.read(&mut buf).await {
+                            Ok(0) | Err(_) => break,
+                            Ok(_) => {}
+                        }
+                        let headers = "HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\n";
+                        if stream.write_all(headers.as_bytes()).await.is_err() {
+                            break;
+                        }
+                        let _ = stream.flush().await;
+                        sleep(body_delay).await;
+                        if stream.write_all(b"hello").await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_virtual_user_records_ttfb_before_body() {
+        let url = start_delayed_body_server(Duration::from_millis(50)).await;
+        let mut vu = VirtualUser::new(&url, Duration::from_secs(1))
+            .set_graceful_shutdown(Duration::from_millis(200));
+        vu.start();
+
+        sleep(Duration::from_millis(300)).await;
+        vu.stop().await;
+
+        let metrics = vu.metrics();
+        let m = metrics.lock().await;
+        assert!(m.ttfb.count() > 0);
+        assert!(m.ttfb.average().unwrap() < m.total_latency.average().unwrap());
+        assert!(m.total_latency.min().unwrap() >= 0.05);
+    }
+
+    #[tokio::test]
+    async fn test_virtual_user_steps_pass_extracted_token() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/login"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"token": "abc123"}"#)
+                    .append_header("set-cookie", "session=s1; Path=/"),
+            )
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/orders"))
+            .and(header("authorization", "Bearer abc123"))
+            .and(header("cookie", "session=s1"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1..)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/orders"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&mock_server)
+            .await;
+
+        let url = mock_server.uri();
+        let mut vu = VirtualUser::new(&url, Duration::from_secs(1)).set_steps(vec![
+            Step::new(RequestSpec::new(&format!("{url}/login")).method(reqwest::Method::POST))
+                .extract_json("token", "/token"),
+            Step::new(
+                RequestSpec::new(&format!("{url}/orders"))
+                    .header("authorization", "Bearer {{token}}"),
+            ),
+        ]);
+        vu.start();
+        sleep(Duration::from_millis(200)).await;
+        vu.stop().await;
+
+        let metrics = vu.metrics();
+        let m = metrics.lock().await;
+        assert!(m.status_code_counts.get(&204).copied().unwrap_or(0) > 0);
+        assert!(!m.status_code_counts.contains_key(&401));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn test_virtual_user_emits_one_span_per_request() {
+        use tracing_subscriber::fmt::format::FmtSpan;
+
+        #[derive(Clone, Default)]
+        struct Capture(Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Capture {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_span_events(FmtSpan::CLOSE)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let url = mock_server.uri();
+        let mut vu = VirtualUser::new(&url, Duration::from_secs(1))
+            .set_graceful_shutdown(Duration::from_millis(200));
+        vu.start();
+        sleep(Duration::from_millis(100)).await;
+        vu.stop().await;
+
+        let output = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        let spans: Vec<_> = output
+            .lines()
+            .filter(|line| line.contains("close"))
+            .collect();
+        assert_eq!(spans.len(), vu.counters().snapshot().requests, "{output}");
+        for span in spans {
+            assert!(
+                span.contains(&format!("request{{method=GET url={url}")),
+                "{span}"
+            );
+            assert!(span.contains("status=200 latency="), "{span}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_pacing_slows_vu_when_latency_rises() {
+        // Fast for the first 400ms, then every response takes 50ms.
+        struct SlowsDown {
+            started: Instant,
+            arrivals: Arc<std::sync::Mutex<Vec<Duration>>>,
+        }
+
+        impl Respond for SlowsDown {
+            fn respond(&self, _: &wiremock::Request) -> ResponseTemplate {
+                let at = self.started.elapsed();
+                self.arrivals.lock().unwrap().push(at);
+                let delay = if at < Duration::from_millis(400) {
+                    2
+                } else {
+                    50
+                };
+                ResponseTemplate::new(200).set_delay(Duration::from_millis(delay))
+            }
+        }
+
+        let arrivals = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(SlowsDown {
+                started: Instant::now(),
+                arrivals: arrivals.clone(),
+            })
+            .mount(&mock_server)
+            .await;
+
+        let mut vu = VirtualUser::new(&mock_server.uri(), Duration::from_secs(1))
+            .set_adaptive_pacing(Some(AdaptivePacing::new(Duration::from_millis(10))));
+        vu.start();
+        sleep(Duration::from_millis(1200)).await;
+        vu.stop().await;
+
+        let arrivals = arrivals.lock().unwrap();
+        let in_window = |from: u64, to: u64| {
+            arrivals
+                .iter()
+                .filter(|at| (Duration::from_millis(from)..Duration::from_millis(to)).contains(at))
+                .count()
+        };
+        let fast = in_window(0, 400);
+        let slow = in_window(500, 1200);
+        assert!(fast >= 20, "{fast} fast requests");
+        // Unpaced, 50ms responses would allow 14 requests in 700ms; paced they wait up to 100ms
+        // more each.
+        assert!(slow <= 8, "{slow} slow requests");
     }
 }
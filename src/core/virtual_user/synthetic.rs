@@ -0,0 +1,104 @@
+use std::time::{Duration, Instant};
+
+use super::{CompletedRequest, RequestOutcome, ResponseInfo, Sample, Validators};
+use crate::core::metrics::Metrics;
+
+// Pushes made-up requests through the same counter and sample updates a VU makes, with no
+// HTTP and a virtual clock, so the aggregation can be checked against known inputs.
+pub(crate) struct SyntheticDriver {
+    metrics: Metrics,
+    started_at: Instant,
+    interval: Duration,
+    error_rate: f64,
+    sent: usize,
+}
+
+impl SyntheticDriver {
+    // Requests finish `1 / rps` apart, the first one at the start of the run.
+    pub(crate) fn new(mut metrics: Metrics, rps: f64) -> Self {
+        metrics.rps_summary.start();
+        Self {
+            metrics,
+            started_at: Instant::now(),
+            interval: Duration::from_secs_f64(1.0 / rps),
+            error_rate: 0.0,
+            sent: 0,
+        }
+    }
+
+    // Timeouts are spread evenly rather than at random, so the count is exact.
+    pub(crate) fn error_rate(mut self, error_rate: f64) -> Self {
+        self.error_rate = error_rate;
+        self
+    }
+
+    pub(crate) fn drive(&mut self, latencies: impl IntoIterator<Item = f64>) {
+        for latency in latencies {
+            let index = self.sent as f64;
+            let errors_before = (index * self.error_rate).floor();
+            let outcome = if ((index + 1.0) * self.error_rate).floor() > errors_before {
+                RequestOutcome::TimedOut
+            } else {
+                RequestOutcome::Response(Box::new(ResponseInfo {
+                    status: 200,
+                    remote_addr: None,
+                    ttfb: latency,
+                    breakdown: None,
+                    body_hash: None,
+                    negotiation_failed: false,
+                    connection_close: false,
+                    assertion_failed: false,
+                    upload_bytes: 0,
+                    download_bytes: 0,
+                    validators: Validators::default(),
+                    step_response: None,
+                }))
+            };
+            outcome.count(&self.metrics.counters);
+            Sample::Request(Box::new(CompletedRequest {
+                finished_at: self.started_at + self.interval.mul_f64(index),
+                url: "synthetic".to_string(),
+                latency,
+                recycled: false,
+                repeated_body: false,
+                expect_continue: false,
+                uncompressed_len: None,
+                tag: None,
+                outcome,
+            }))
+            .apply(&mut self.metrics);
+            self.sent += 1;
+        }
+    }
+
+    pub(crate) fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregates_known_distribution() {
+        let mut driver =
+            SyntheticDriver::new(Metrics::new(Duration::from_secs(1)), 50.0).error_rate(0.1);
+        driver.drive((1..=100).map(|ms| ms as f64 / 1000.0));
+        let metrics = driver.metrics();
+
+        assert_eq!(metrics.total_requests(), 100);
+        assert_eq!(metrics.timeouts(), 10);
+        assert_eq!(metrics.error_rate(), Some(0.1));
+        assert_eq!(metrics.status_code_counts.get(&200), Some(&90));
+        assert_eq!(metrics.rps_summary.window_counts(), &[50, 50]);
+        assert_eq!(metrics.latency_percentile(0.0), Some(0.001));
+        assert_eq!(metrics.latency_percentile(1.0), Some(0.1));
+        // The histogram buckets grow by 2%, so interior percentiles are exact to within that.
+        for (q, expected) in [(0.5, 0.05), (0.9, 0.09), (0.99, 0.099)] {
+            let actual = metrics.latency_percentile(q).unwrap();
+            assert!((actual / expected - 1.0).abs() < 0.02, "p{q}: {actual}");
+        }
+        assert!((metrics.total_latency.average().unwrap() - 0.0505).abs() < 1e-12);
+    }
+}
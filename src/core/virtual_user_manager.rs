@@ -1,23 +1,261 @@
-use std::time::{Duration, Instant};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use std::{fs, io};
+
+use bytes::Bytes;
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::{Method, Url};
+use thiserror::Error;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinError;
 use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
 
-use crate::core::metrics::Metrics;
+#[cfg(feature = "alarm")]
+use crate::core::alarm::LatencyAlarm;
+use crate::core::batch::MetricsBatching;
+use crate::core::breakdown::{TimedConnectLayer, TimedResolver};
+use crate::core::byte_budget::ByteBudget;
+use crate::core::circuit_breaker::{BreakerTransition, CircuitBreaker};
+use crate::core::dns::{DnsResolver, Lookup};
+use crate::core::env;
+use crate::core::fail_fast::FailFast;
+use crate::core::feedback_ramp::{FeedbackRamp, RampPacer};
+use crate::core::hook::RequestHook;
+use crate::core::iteration::IterationBudget;
+use crate::core::metrics::{
+    Counters, LatencyResolution, Metrics, MetricsSnapshot, PercentileBackend,
+};
+use crate::core::predicate::SuccessPredicate;
+use crate::core::progress::RunProgress;
+use crate::core::ramp_fidelity::{RampFidelity, RampSample};
+use crate::core::recycle::ConnectionRecycle;
+use crate::core::replay::{ReplayLog, ReplayPacing, ReplayQueue};
+use crate::core::reporter::{self, IntervalReporter, MetricsSample, MetricsStream};
+use crate::core::request::{MultipartPart, RequestBody, RequestSpec};
+use crate::core::retry::RetryBudget;
+use crate::core::slowest::DEFAULT_SLOWEST_CAPACITY;
+use crate::core::steps::Step;
 use crate::core::summary::Summary;
-use crate::core::virtual_user::VirtualUser;
+use crate::core::think_time::{AdaptivePacing, DelayInjection, ThinkTime};
+use crate::core::threshold::{EffectiveConcurrency, RunResult, Threshold, ThresholdResult};
+#[cfg(feature = "rustls")]
+use crate::core::tls::{self, TlsSessionStats};
+use crate::core::virtual_user::{VirtualUser, GLOBAL_CLIENT};
+use crate::core::vu_rate::VuRate;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HttpVersion {
+    #[default]
+    Auto,
+    Http1,
+    Http2,
+    #[cfg(feature = "http3")]
+    Http3,
+}
+
+#[derive(Debug, Clone)]
+pub enum ClientIdentity {
+    Pem { cert: Vec<u8>, key: Vec<u8> },
+    Pkcs12 { der: Vec<u8>, password: String },
+}
 
 #[derive(Debug, Clone)]
 pub struct VirtualUserConfig {
     pub url: String,
+    pub base_url: Option<String>,
+    pub tags: HashMap<String, String>,
+    pub method: Method,
+    pub headers: Vec<(String, String)>,
+    pub body: RequestBody,
+    pub expect_continue: bool,
+    pub gzip_body: bool,
     pub rps_window_size: Duration,
     pub graceful_shutdown: Duration,
+    pub final_graceful_shutdown: Option<Duration>,
+    pub max_vus: usize,
+    pub think_time: ThinkTime,
+    pub adaptive_pacing: Option<AdaptivePacing>,
+    pub delay_injection: Option<DelayInjection>,
+    pub percentile_backend: PercentileBackend,
+    pub latency_resolution: LatencyResolution,
+    pub slowest_capacity: usize,
+    pub latency_timestamps: bool,
+    pub raw_sample_capacity: Option<usize>,
+    pub regions: Vec<Region>,
+    pub prewarm_connections: usize,
+    pub fail_fast: bool,
+    pub max_bytes: Option<u64>,
+    pub max_duration: Option<Duration>,
+    pub record_connection_probe: bool,
+    pub fake_data: bool,
+    pub report_interval: Option<IntervalReporter>,
+    pub request_deadline: Option<Duration>,
+    pub align_windows: bool,
+    pub proxy: Option<String>,
+    pub http_version: HttpVersion,
+    pub resolves: Vec<(String, SocketAddr)>,
+    pub dns_resolver: Option<DnsResolver>,
+    pub shared_client: bool,
+    pub latency_breakdown: bool,
+    pub client: Option<reqwest::Client>,
+    pub success_predicate: Option<SuccessPredicate>,
+    pub request_hooks: Vec<Arc<dyn RequestHook>>,
+    pub endpoints: Vec<(RequestSpec, u32)>,
+    pub steps: Vec<Step>,
+    pub seed: Option<u64>,
+    pub conditional_requests: bool,
+    pub validate_no_store: bool,
+    pub accept: Option<String>,
+    pub strict_negotiation: bool,
+    pub connection_recycle: Option<ConnectionRecycle>,
+    pub metrics_batching: Option<MetricsBatching>,
+    pub expected_interval: Option<Duration>,
+    pub max_retries: usize,
+    pub retry_non_idempotent: bool,
+    pub retry_budget: Option<Arc<RetryBudget>>,
+    pub circuit_breaker: Option<Arc<CircuitBreaker>>,
+    pub max_in_flight: Option<usize>,
+    #[cfg(feature = "alarm")]
+    pub latency_alarm: Option<Arc<LatencyAlarm>>,
+    pub output_file: Option<PathBuf>,
+    pub thresholds: Vec<Threshold>,
+    pub final_ramp_down: Option<Duration>,
+    pub feedback_ramp: Option<FeedbackRamp>,
+    pub root_certificates: Vec<Vec<u8>>,
+    pub client_identity: Option<ClientIdentity>,
+    #[cfg(feature = "rustls")]
+    pub tls_session_tracking: bool,
 }
 
 impl VirtualUserConfig {
     pub fn new(url: &str) -> Self {
         Self {
             url: url.to_string(),
+            base_url: None,
+            tags: HashMap::new(),
+            method: Method::GET,
+            headers: Vec::new(),
+            body: RequestBody::Empty,
+            expect_continue: false,
+            gzip_body: false,
             rps_window_size: Duration::from_secs(1),
             graceful_shutdown: Duration::from_secs(0),
+            final_graceful_shutdown: None,
+            max_vus: 1000,
+            think_time: ThinkTime::None,
+            adaptive_pacing: None,
+            delay_injection: None,
+            percentile_backend: PercentileBackend::Histogram,
+            latency_resolution: LatencyResolution::Normal,
+            slowest_capacity: DEFAULT_SLOWEST_CAPACITY,
+            latency_timestamps: false,
+            raw_sample_capacity: None,
+            regions: Vec::new(),
+            prewarm_connections: 0,
+            fail_fast: false,
+            max_bytes: None,
+            max_duration: None,
+            record_connection_probe: false,
+            fake_data: false,
+            report_interval: None,
+            request_deadline: None,
+            align_windows: false,
+            proxy: None,
+            http_version: HttpVersion::Auto,
+            resolves: Vec::new(),
+            dns_resolver: None,
+            shared_client: false,
+            latency_breakdown: false,
+            client: None,
+            success_predicate: None,
+            request_hooks: Vec::new(),
+            endpoints: Vec::new(),
+            steps: Vec::new(),
+            seed: None,
+            conditional_requests: false,
+            validate_no_store: false,
+            accept: None,
+            strict_negotiation: false,
+            connection_recycle: None,
+            metrics_batching: None,
+            expected_interval: None,
+            max_retries: 0,
+            retry_non_idempotent: false,
+            retry_budget: None,
+            circuit_breaker: None,
+            max_in_flight: None,
+            #[cfg(feature = "alarm")]
+            latency_alarm: None,
+            output_file: None,
+            thresholds: Vec::new(),
+            final_ramp_down: None,
+            feedback_ramp: None,
+            root_certificates: Vec::new(),
+            client_identity: None,
+            #[cfg(feature = "rustls")]
+            tls_session_tracking: false,
+        }
+    }
+
+    pub fn method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = RequestBody::Bytes(body.into());
+        self
+    }
+
+    pub fn body_file(self, path: impl AsRef<Path>) -> io::Result<Self> {
+        let contents = fs::read(path)?;
+        Ok(self.body(contents))
+    }
+
+    pub fn form(mut self, fields: Vec<(String, String)>) -> Self {
+        self.body = RequestBody::Form(fields);
+        self
+    }
+
+    pub fn multipart(mut self, parts: Vec<MultipartPart>) -> Self {
+        self.body = RequestBody::Multipart(parts);
+        self
+    }
+
+    pub fn expect_continue(mut self, enabled: bool) -> Self {
+        self.expect_continue = enabled;
+        self
+    }
+
+    pub fn gzip_body(mut self, enabled: bool) -> Self {
+        self.gzip_body = enabled;
+        self
+    }
+
+    pub fn request_spec(&self) -> RequestSpec {
+        let spec = RequestSpec {
+            method: self.method.clone(),
+            url: self.url.clone(),
+            path: None,
+            headers: self.headers.clone(),
+            body: self.body.clone(),
+            expect_continue: self.expect_continue,
+            uncompressed_len: None,
+            tag: None,
+        };
+        if self.gzip_body {
+            spec.gzip()
+        } else {
+            spec
         }
     }
 
@@ -30,144 +268,4731 @@ impl VirtualUserConfig {
         self.graceful_shutdown = shutdown;
         self
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct PlanSegment {
-    pub duration: Duration,
-    pub target: usize,
-}
+    pub fn final_graceful_shutdown(mut self, shutdown: Duration) -> Self {
+        self.final_graceful_shutdown = Some(shutdown);
+        self
+    }
 
-impl PlanSegment {
-    pub fn new(duration: Duration, target: usize) -> Self {
-        Self { duration, target }
+    pub fn max_vus(mut self, max_vus: usize) -> Self {
+        self.max_vus = max_vus;
+        self
     }
-}
 
-pub struct VirtualUserManager {
-    config: VirtualUserConfig,
-    plans: Vec<PlanSegment>,
-    running_vus: Vec<VirtualUser>,
-    overall_metrics: Metrics,
-}
+    pub fn think_time(mut self, think_time: ThinkTime) -> Self {
+        self.think_time = think_time;
+        self
+    }
 
-impl VirtualUserManager {
-    pub fn new(config: VirtualUserConfig) -> Self {
-        let overall_metrics = Metrics::new(config.rps_window_size);
-        Self {
-            config,
-            plans: Vec::new(),
-            running_vus: Vec::new(),
-            overall_metrics,
+    // Adds think time while requests run slower than `target_latency`; see `AdaptivePacing`.
+    pub fn adaptive_pacing(mut self, target_latency: Duration) -> Self {
+        self.adaptive_pacing = Some(AdaptivePacing::new(target_latency));
+        self
+    }
+
+    pub fn inject_delay(mut self, probability: f64, delay: ThinkTime) -> Self {
+        self.delay_injection = Some(DelayInjection::new(probability, delay));
+        self
+    }
+
+    pub fn percentile_backend(mut self, backend: PercentileBackend) -> Self {
+        self.percentile_backend = backend;
+        self
+    }
+
+    pub fn latency_resolution(mut self, resolution: LatencyResolution) -> Self {
+        self.latency_resolution = resolution;
+        self
+    }
+
+    // How many of the slowest requests to keep, with their URL and status, for `Metrics::slowest`.
+    pub fn track_slowest(mut self, capacity: usize) -> Self {
+        self.slowest_capacity = capacity;
+        self
+    }
+
+    // Records when the fastest and slowest requests finished, see `Metrics::latency_max_at`.
+    pub fn track_latency_timestamps(mut self, enabled: bool) -> Self {
+        self.latency_timestamps = enabled;
+        self
+    }
+
+    // Keeps the latencies of the last `capacity` requests across all VUs, see
+    // `Metrics::raw_latencies`.
+    pub fn track_raw_samples(mut self, capacity: usize) -> Self {
+        self.raw_sample_capacity = Some(capacity);
+        self
+    }
+
+    pub fn final_ramp_down(mut self, duration: Duration) -> Self {
+        self.final_ramp_down = Some(duration);
+        self
+    }
+
+    // Applies to segments that add VUs; ramp-downs and holds keep to the plan.
+    pub fn feedback_ramp(mut self, feedback: FeedbackRamp) -> Self {
+        self.feedback_ramp = Some(feedback);
+        self
+    }
+
+    pub fn request_deadline(mut self, deadline: Duration) -> Self {
+        self.request_deadline = Some(deadline);
+        self
+    }
+
+    pub fn align_windows(mut self, align: bool) -> Self {
+        self.align_windows = align;
+        self
+    }
+
+    pub fn proxy(mut self, proxy_url: &str) -> Self {
+        self.proxy = Some(proxy_url.to_string());
+        self
+    }
+
+    // Times DNS and connection setup through a custom resolver and connector layer.
+    pub fn latency_breakdown(mut self, enabled: bool) -> Self {
+        self.latency_breakdown = enabled;
+        self
+    }
+
+    pub fn shared_client(mut self, shared: bool) -> Self {
+        self.shared_client = shared;
+        self
+    }
+
+    // Opens this many connections to each target before the run starts and leaves them idle
+    // in the pool. Only a client shared by all VUs has a pool to warm.
+    pub fn prewarm_connections(mut self, connections: usize) -> Self {
+        self.prewarm_connections = connections;
+        self
+    }
+
+    // Ends the run at the first transport error, timeout, 4xx/5xx or failed assertion, and
+    // returns it as `RunError::FailFast`.
+    pub fn fail_fast(mut self, enabled: bool) -> Self {
+        self.fail_fast = enabled;
+        self
+    }
+
+    // Counts each VU's unmeasured warm-up request in `Metrics::connection_probes`, and how many
+    // of them failed. A failed probe stops a fail-fast run either way.
+    pub fn record_connection_probe(mut self, enabled: bool) -> Self {
+        self.record_connection_probe = enabled;
+        self
+    }
+
+    // Fills `{random_name}`, `{random_email}` and `{random_int:MIN:MAX}` in request bodies with
+    // fresh values on every request; see `fake::render`.
+    pub fn fake_data(mut self, enabled: bool) -> Self {
+        self.fake_data = enabled;
+        self
+    }
+
+    // Calls `callback` with everything recorded so far about every `interval` while a run is
+    // in progress. Checked on the manager's tick, so intervals shorter than that are rounded
+    // up to it.
+    pub fn report_interval<F>(mut self, interval: Duration, callback: F) -> Self
+    where
+        F: Fn(&Metrics) + Send + Sync + 'static,
+    {
+        self.report_interval = Some(IntervalReporter::new(interval, callback));
+        self
+    }
+
+    // Ends the run once request and response bodies add up to `max_bytes`.
+    pub fn max_bytes(mut self, max_bytes: Option<u64>) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    // A wall-clock cap on the run, whatever the plan says. VUs still get their graceful
+    // shutdown once it is reached.
+    pub fn max_duration(mut self, max_duration: Option<Duration>) -> Self {
+        self.max_duration = max_duration;
+        self
+    }
+
+    // Every VU shares this client as-is. Options that only affect how rperf builds a client
+    // (proxy, resolve, HTTP version, root certificates, client identity and TLS session
+    // tracking) are ignored.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    pub fn success_predicate<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(u16, &[u8]) -> bool + Send + Sync + 'static,
+    {
+        self.success_predicate = Some(SuccessPredicate::new(predicate));
+        self
+    }
+
+    pub fn request_hook(mut self, hook: impl RequestHook + 'static) -> Self {
+        self.request_hooks.push(Arc::new(hook));
+        self
+    }
+
+    // Attached to every exported report: JSON `tags` and OpenMetrics labels.
+    pub fn tag(mut self, name: &str, value: &str) -> Self {
+        self.tags.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = Some(base_url.to_string());
+        self
+    }
+
+    // Each VU is pinned to one region, picked by weight, and sends all its traffic to the
+    // region's URL. Metrics are also kept per region, see `VirtualUserManager::region_metrics`.
+    pub fn region(mut self, region: Region) -> Self {
+        self.regions.push(region);
+        self
+    }
+
+    // The config a VU in `region` runs with: the region's URL stands in for the target and,
+    // when one is set, the base URL.
+    fn for_region(&self, region: &Region) -> VirtualUserConfig {
+        let mut config = self.clone();
+        config.url = region.url.clone();
+        if config.base_url.is_some() {
+            config.base_url = Some(region.url.clone());
         }
+        config
+            .tags
+            .insert("region".to_string(), region.name.clone());
+        config
     }
 
-    pub fn add_plan(&mut self, duration: Duration, target: usize) {
-        self.plans.push(PlanSegment::new(duration, target));
+    pub fn resolved_endpoints(&self) -> Vec<(RequestSpec, u32)> {
+        self.endpoints
+            .iter()
+            .map(|(spec, weight)| (spec.clone().resolve(self.base_url.as_deref()), *weight))
+            .collect()
     }
 
-    pub async fn run(&mut self) {
-        let tick_interval = Duration::from_millis(100);
-        let mut current_count = self.running_vus.len();
+    pub fn endpoint(mut self, request: RequestSpec, weight: u32) -> Self {
+        self.endpoints.push((request, weight));
+        self
+    }
 
-        for plan in &self.plans {
-            let segment_start_count = current_count;
-            let target_count = plan.target;
-            let change = target_count as isize - segment_start_count as isize;
-            let segment_duration = plan.duration;
-            let start_time = Instant::now();
+    pub fn resolved_steps(&self) -> Vec<Step> {
+        self.steps
+            .iter()
+            .map(|step| Step {
+                request: step.request.clone().resolve(self.base_url.as_deref()),
+                ..step.clone()
+            })
+            .collect()
+    }
 
-            while start_time.elapsed() < segment_duration {
-                let elapsed = start_time.elapsed();
-                let ratio = elapsed.as_secs_f64() / segment_duration.as_secs_f64();
-                let ideal_count = segment_start_count as f64 + (change as f64 * ratio);
-                let diff = ideal_count - current_count as f64;
-                let delta_int: isize = if diff >= 1.0 {
-                    diff.floor() as isize
-                } else if diff <= -1.0 {
-                    diff.ceil() as isize
-                } else {
-                    0
-                };
-
-                use std::cmp::Ordering;
-
-                match delta_int.cmp(&0) {
-                    Ordering::Greater => {
-                        for _ in 0..delta_int {
-                            let mut vu =
-                                VirtualUser::new(&self.config.url, self.config.rps_window_size)
-                                    .set_graceful_shutdown(self.config.graceful_shutdown);
-                            vu.start();
-                            self.running_vus.push(vu);
-                            current_count += 1;
-                        }
-                    }
-                    Ordering::Less => {
-                        let num_to_remove = (-delta_int) as usize;
-                        for _ in 0..num_to_remove {
-                            if let Some(mut vu) = self.running_vus.pop() {
-                                vu.stop().await;
-                                let metrics = vu.metrics();
-                                let m = metrics.lock().await;
-                                Self::merge_metrics(&mut self.overall_metrics, &m);
-                                current_count -= 1;
-                            }
-                        }
-                    }
-                    Ordering::Equal => {}
-                }
+    // Each VU runs the steps in order instead of repeating one request; see `Steps`.
+    pub fn step(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
 
-                sleep(tick_interval).await;
-            }
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn conditional_requests(mut self, enabled: bool) -> Self {
+        self.conditional_requests = enabled;
+        self
+    }
+
+    // Sends `Cache-Control: no-store` and counts responses whose body is identical to the
+    // previous one for the same URL, which suggests an intermediary served a cached copy.
+    pub fn validate_no_store(mut self, enabled: bool) -> Self {
+        self.validate_no_store = enabled;
+        self
+    }
+
+    // Default `Accept` header for requests that don't set their own.
+    pub fn accept(mut self, accept: &str) -> Self {
+        self.accept = Some(accept.to_string());
+        self
+    }
+
+    // Counts 2xx responses whose `Content-Type` doesn't satisfy the `Accept` header sent.
+    pub fn strict_negotiation(mut self, enabled: bool) -> Self {
+        self.strict_negotiation = enabled;
+        self
+    }
+
+    pub fn recycle_connections(mut self, recycle: ConnectionRecycle) -> Self {
+        self.connection_recycle = Some(recycle);
+        self
+    }
+
+    // Buffers each VU's samples and writes them to its metrics in batches, so readers of the
+    // metrics lock don't stall request progress. Samples still buffered when a VU is aborted
+    // past its graceful shutdown are lost.
+    pub fn batch_metrics(mut self, batching: MetricsBatching) -> Self {
+        self.metrics_batching = Some(batching);
+        self
+    }
+
+    // Caps requests in flight across all VUs; time spent waiting for a slot is recorded as
+    // `queue_wait` instead of latency.
+    pub fn max_in_flight(mut self, limit: usize) -> Self {
+        self.max_in_flight = Some(limit);
+        self
+    }
+
+    pub fn coordinated_omission_correction(mut self, expected_interval: Duration) -> Self {
+        self.expected_interval = Some(expected_interval);
+        self
+    }
+
+    // Only idempotent methods, and requests that never connected, are retried unless
+    // `retry_non_idempotent` is set.
+    pub fn retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn retry_non_idempotent(mut self, enabled: bool) -> Self {
+        self.retry_non_idempotent = enabled;
+        self
+    }
+
+    pub fn retry_budget(mut self, capacity: usize, refill_per_sec: f64) -> Self {
+        self.retry_budget = Some(Arc::new(RetryBudget::new(capacity, refill_per_sec)));
+        self
+    }
+
+    #[cfg(feature = "alarm")]
+    pub fn p99_alarm(mut self, budget: Duration, window: Duration) -> Self {
+        self.latency_alarm = Some(Arc::new(LatencyAlarm::new(budget, window)));
+        self
+    }
+
+    pub fn circuit_breaker(
+        mut self,
+        window: Duration,
+        failure_rate: f64,
+        min_requests: usize,
+        cooldown: Duration,
+    ) -> Self {
+        self.circuit_breaker = Some(Arc::new(CircuitBreaker::new(
+            window,
+            failure_rate,
+            min_requests,
+            cooldown,
+        )));
+        self
+    }
+
+    pub fn threshold(mut self, threshold: Threshold) -> Self {
+        self.thresholds.push(threshold);
+        self
+    }
+
+    pub fn output_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.output_file = Some(path.into());
+        self
+    }
+
+    pub fn resolve(mut self, host: &str, addr: SocketAddr) -> Self {
+        self.resolves.push((host.to_string(), addr));
+        self
+    }
 
-            while current_count < target_count {
-                let mut vu = VirtualUser::new(&self.config.url, self.config.rps_window_size)
-                    .set_graceful_shutdown(self.config.graceful_shutdown);
-                vu.start();
-                self.running_vus.push(vu);
-                current_count += 1;
+    // Used for every host without a `resolve` override.
+    pub fn dns_resolver(mut self, resolver: DnsResolver) -> Self {
+        self.dns_resolver = Some(resolver);
+        self
+    }
+
+    pub fn http_version(mut self, version: HttpVersion) -> Self {
+        self.http_version = version;
+        self
+    }
+
+    // Resolves `${NAME}` references to environment variables in every URL, header value and
+    // text body, endpoints, steps, regions and the proxy included. Call it once the config is
+    // built.
+    pub fn interpolate_env(mut self) -> Result<Self, ConfigError> {
+        self.resolve_env_vars()
+            .map_err(ConfigError::MissingEnvVar)?;
+        Ok(self)
+    }
+
+    fn resolve_env_vars(&mut self) -> Result<(), String> {
+        env::resolve(&mut self.url)?;
+        if let Some(base_url) = &mut self.base_url {
+            env::resolve(base_url)?;
+        }
+        env::resolve_headers(&mut self.headers)?;
+        env::resolve_body(&mut self.body)?;
+        for (request, _) in &mut self.endpoints {
+            env::resolve_request(request)?;
+        }
+        for step in &mut self.steps {
+            env::resolve_request(&mut step.request)?;
+        }
+        if let Some(proxy) = &mut self.proxy {
+            env::resolve(proxy)?;
+        }
+        for region in &mut self.regions {
+            env::resolve(&mut region.url)?;
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.rps_window_size < Duration::from_secs(1) {
+            return Err(ConfigError::WindowTooSmall(self.rps_window_size));
+        }
+        if self.max_vus == 0 {
+            return Err(ConfigError::ZeroMaxVus);
+        }
+        if self.max_in_flight == Some(0) {
+            return Err(ConfigError::ZeroInFlightLimit);
+        }
+        if self.gzip_body && matches!(self.body, RequestBody::Form(_) | RequestBody::Multipart(_)) {
+            return Err(ConfigError::GzipNeedsBytesBody);
+        }
+        #[cfg(feature = "http3")]
+        if matches!(self.client_identity, Some(ClientIdentity::Pkcs12 { .. })) {
+            return Err(ConfigError::Pkcs12NeedsNativeTls);
+        }
+        for (index, region) in self.regions.iter().enumerate() {
+            let duplicate = self.regions[..index]
+                .iter()
+                .any(|other| other.name == region.name);
+            if region.name.is_empty() || region.weight == 0 || duplicate {
+                return Err(ConfigError::InvalidRegion(region.name.clone()));
             }
-            while current_count > target_count {
-                if let Some(mut vu) = self.running_vus.pop() {
-                    vu.stop().await;
-                    let metrics = vu.metrics();
-                    let m = metrics.lock().await;
-                    Self::merge_metrics(&mut self.overall_metrics, &m);
-                    current_count -= 1;
-                }
+        }
+        let urls = std::iter::once(self.url.clone())
+            .chain(
+                self.resolved_endpoints()
+                    .into_iter()
+                    .map(|(spec, _)| spec.url),
+            )
+            .chain(
+                self.resolved_steps()
+                    .into_iter()
+                    .filter(|step| !step.request.url.contains("{{"))
+                    .map(|step| step.request.url),
+            )
+            .chain(self.regions.iter().map(|region| region.url.clone()));
+        for url in urls {
+            if let Err(e) = Url::parse(&url) {
+                return Err(ConfigError::InvalidUrl {
+                    url,
+                    reason: e.to_string(),
+                });
             }
         }
-
-        while let Some(mut vu) = self.running_vus.pop() {
-            vu.stop().await;
-            let metrics = vu.metrics();
-            let m = metrics.lock().await;
-            Self::merge_metrics(&mut self.overall_metrics, &m);
+        for (name, value) in &self.headers {
+            if HeaderName::from_bytes(name.as_bytes()).is_err()
+                || HeaderValue::from_str(value).is_err()
+            {
+                return Err(ConfigError::InvalidHeader(name.clone()));
+            }
+        }
+        if let Some(accept) = &self.accept {
+            if HeaderValue::from_str(accept).is_err() {
+                return Err(ConfigError::InvalidHeader("accept".to_string()));
+            }
         }
+        for name in self.tags.keys() {
+            let mut chars = name.chars();
+            let valid = chars
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && name != "le";
+            if !valid {
+                return Err(ConfigError::InvalidTag(name.clone()));
+            }
+        }
+        Ok(())
     }
 
-    pub fn get_overall_metrics(&self) -> &Metrics {
-        &self.overall_metrics
+    pub fn uses_default_client(&self) -> bool {
+        self.proxy.is_none()
+            && self.resolves.is_empty()
+            && self.dns_resolver.is_none()
+            && self.http_version == HttpVersion::Auto
+            && self.root_certificates.is_empty()
+            && self.client_identity.is_none()
+            && !self.latency_breakdown
     }
 
-    fn merge_metrics(dest: &mut Metrics, src: &Metrics) {
-        Self::merge_summary(&mut dest.total_latency, &src.total_latency);
-        Self::merge_summary(&mut dest.tcp_connect_time, &src.tcp_connect_time);
-        Self::merge_summary(&mut dest.tls_handshake_time, &src.tls_handshake_time);
-        Self::merge_summary(&mut dest.http_request_time, &src.http_request_time);
-        dest.total_errors += src.total_errors;
-        Self::merge_summary(&mut dest.error_rates_per_sec, &src.error_rates_per_sec);
-        for (code, count) in &src.status_code_counts {
-            *dest.status_code_counts.entry(*code).or_insert(0) += count;
+    pub fn client_builder(&self) -> reqwest::Result<reqwest::ClientBuilder> {
+        let mut builder = reqwest::Client::builder();
+        let lookup = self.dns_resolver.as_ref().map(Lookup::new);
+        if self.latency_breakdown {
+            builder = builder
+                .dns_resolver(Arc::new(TimedResolver(lookup)))
+                .connector_layer(TimedConnectLayer);
+        } else if let Some(lookup) = lookup {
+            builder = builder.dns_resolver(Arc::new(lookup));
         }
-        dest.other_errors.extend(src.other_errors.iter().cloned());
+        if let Some(proxy_url) = &self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy_url)?);
+        }
+        for (host, addr) in &self.resolves {
+            builder = builder.resolve(host, *addr);
+        }
+        for pem in &self.root_certificates {
+            builder = builder.add_root_certificate(reqwest::Certificate::from_pem(pem)?);
+        }
+        builder = match &self.client_identity {
+            // http3 makes rustls reqwest's default backend, which takes the certificate and
+            // key as a single PEM.
+            #[cfg(feature = "http3")]
+            Some(ClientIdentity::Pem { cert, key }) => {
+                let pem = [cert.as_slice(), b"\n", key.as_slice()].concat();
+                builder.identity(reqwest::Identity::from_pem(&pem)?)
+            }
+            #[cfg(not(feature = "http3"))]
+            Some(ClientIdentity::Pem { cert, key }) => {
+                builder.identity(reqwest::Identity::from_pkcs8_pem(cert, key)?)
+            }
+            Some(ClientIdentity::Pkcs12 { der, password }) => {
+                builder.identity(reqwest::Identity::from_pkcs12_der(der, password)?)
+            }
+            None => builder,
+        };
+        builder = match self.http_version {
+            HttpVersion::Auto => builder,
+            HttpVersion::Http1 => builder.http1_only(),
+            HttpVersion::Http2 => builder.http2_prior_knowledge(),
+            #[cfg(feature = "http3")]
+            HttpVersion::Http3 => builder.http3_prior_knowledge(),
+        };
+        Ok(builder)
     }
 
-    fn merge_summary(dest: &mut Summary, src: &Summary) {
+    pub fn build_client(&self) -> reqwest::Result<reqwest::Client> {
+        self.client_builder()?.build()
+    }
+
+    #[cfg(feature = "rustls")]
+    pub fn tls_session_tracking(mut self, enabled: bool) -> Self {
+        self.tls_session_tracking = enabled;
+        self
+    }
+
+    pub fn add_root_certificate(mut self, pem: &[u8]) -> Self {
+        self.root_certificates.push(pem.to_vec());
+        self
+    }
+
+    pub fn client_identity(mut self, identity: ClientIdentity) -> Self {
+        self.client_identity = Some(identity);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub name: String,
+    pub url: String,
+    pub weight: u32,
+}
+
+impl Region {
+    pub fn new(name: &str, url: &str, weight: u32) -> Self {
+        Self {
+            name: name.to_string(),
+            url: url.to_string(),
+            weight,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlanSegment {
+    pub duration: Duration,
+    pub target: usize,
+    pub graceful_shutdown: Option<Duration>,
+    pub immediate: bool,
+}
+
+impl PlanSegment {
+    pub fn new(duration: Duration, target: usize) -> Self {
+        Self {
+            duration,
+            target,
+            graceful_shutdown: None,
+            immediate: false,
+        }
+    }
+
+    // A rest period: every VU is stopped at the start and none run for `duration`.
+    pub fn idle(duration: Duration) -> Self {
+        Self::new(duration, 0).immediate()
+    }
+
+    // Jumps straight to the target at the start of the segment and holds it, instead of
+    // ramping linearly over the duration.
+    pub fn immediate(mut self) -> Self {
+        self.immediate = true;
+        self
+    }
+
+    pub fn graceful_shutdown(mut self, shutdown: Duration) -> Self {
+        self.graceful_shutdown = Some(shutdown);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RampDirection {
+    RampUp,
+    RampDown,
+    Hold,
+    Idle,
+}
+
+#[derive(Debug, Clone)]
+pub struct SegmentTiming {
+    pub target: usize,
+    pub direction: RampDirection,
+    pub started_at: Duration,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct RpsPlanSegment {
+    pub duration: Duration,
+    pub target_rps: f64,
+}
+
+impl RpsPlanSegment {
+    pub fn new(duration: Duration, target_rps: f64) -> Self {
+        Self {
+            duration,
+            target_rps,
+        }
+    }
+}
+
+// Ramps the request rate of every VU, linearly from the previous segment's rate, while the
+// VU count stays put. The first segment holds its own rate throughout.
+#[derive(Debug, Clone, Copy)]
+pub struct VuRatePlanSegment {
+    pub duration: Duration,
+    pub max_rps_per_vu: f64,
+}
+
+impl VuRatePlanSegment {
+    pub fn new(duration: Duration, max_rps_per_vu: f64) -> Self {
+        if max_rps_per_vu <= 0.0 {
+            panic!("per-VU rate must be greater than 0, got {max_rps_per_vu}");
+        }
+
+        Self {
+            duration,
+            max_rps_per_vu,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CapacitySearch {
+    pub sla: Vec<Threshold>,
+    pub min_rps: f64,
+    pub max_rps: f64,
+    pub probe_duration: Duration,
+    pub tolerance: f64,
+}
+
+impl CapacitySearch {
+    pub fn new(min_rps: f64, max_rps: f64) -> Self {
+        if min_rps <= 0.0 || max_rps <= min_rps {
+            panic!("capacity search needs 0 < min_rps < max_rps");
+        }
+
+        Self {
+            sla: Vec::new(),
+            min_rps,
+            max_rps,
+            probe_duration: Duration::from_secs(10),
+            tolerance: max_rps * 0.05,
+        }
+    }
+
+    pub fn threshold(mut self, threshold: Threshold) -> Self {
+        self.sla.push(threshold);
+        self
+    }
+
+    pub fn probe_duration(mut self, duration: Duration) -> Self {
+        self.probe_duration = duration;
+        self
+    }
+
+    pub fn tolerance(mut self, rps: f64) -> Self {
+        self.tolerance = rps;
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CapacityProbe {
+    pub target_rps: f64,
+    pub threshold_results: Vec<ThresholdResult>,
+    pub passed: bool,
+}
+
+#[derive(Debug)]
+pub struct CapacityResult {
+    pub max_rps: Option<f64>,
+    pub metrics: Option<Metrics>,
+    pub probes: Vec<CapacityProbe>,
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("rps window size must be at least 1s, got {0:?}")]
+    WindowTooSmall(Duration),
+    #[error("max_vus must be greater than 0")]
+    ZeroMaxVus,
+    #[error("max_in_flight must be greater than 0")]
+    ZeroInFlightLimit,
+    #[error("invalid url {url:?}: {reason}")]
+    InvalidUrl { url: String, reason: String },
+    #[error("invalid header {0:?}")]
+    InvalidHeader(String),
+    #[error("invalid tag name {0:?}: must be a valid metric label name other than `le`")]
+    InvalidTag(String),
+    #[error("invalid region {0:?}: names must be unique and non-empty, weights positive")]
+    InvalidRegion(String),
+    #[error("environment variable {0:?} is not set")]
+    MissingEnvVar(String),
+    #[error("gzip_body needs a raw byte body, not a form or multipart one")]
+    GzipNeedsBytesBody,
+    #[cfg(feature = "http3")]
+    #[error("PKCS#12 client identities need native-tls, which http3 builds replace with rustls")]
+    Pkcs12NeedsNativeTls,
+}
+
+#[derive(Debug, Error)]
+pub enum RunError {
+    #[error("invalid config: {0}")]
+    Config(#[from] ConfigError),
+    #[error("failed to write summary to {path}: {source}")]
+    Output { path: PathBuf, source: io::Error },
+    #[error("failed to read replay log {path}: {source}")]
+    ReplayLog { path: PathBuf, source: io::Error },
+    #[error("failed to build HTTP client: {0}")]
+    Client(#[from] reqwest::Error),
+    #[error("virtual user task failed: {0}")]
+    Join(#[from] JoinError),
+    #[error("stopped on first failure: {0}")]
+    FailFast(String),
+    #[error("{url} did not become healthy within {timeout:?}")]
+    Unhealthy { url: String, timeout: Duration },
+    #[cfg(feature = "rustls")]
+    #[error(transparent)]
+    Tls(#[from] tls::TlsError),
+}
+
+#[derive(Debug, Clone)]
+pub struct DryRunReport {
+    pub status: Option<u16>,
+    pub latency: Duration,
+    pub error: Option<String>,
+    pub peak_vus: usize,
+    pub total_duration: Duration,
+    pub estimated_requests: Option<u64>,
+}
+
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+struct SnapshotSender {
+    interval: Duration,
+    next_due: Instant,
+    run_start: Instant,
+    sender: mpsc::UnboundedSender<MetricsSample>,
+}
+
+impl SnapshotSender {
+    // False once the stream has been dropped.
+    fn send(&self, metrics: Metrics) -> bool {
+        let sample = MetricsSample {
+            taken_at: SystemTime::now(),
+            elapsed: self.run_start.elapsed(),
+            metrics,
+        };
+        self.sender.send(sample).is_ok()
+    }
+}
+
+type VuStartCallback = Box<dyn Fn(u64) + Send + Sync>;
+type VuStopCallback = Box<dyn Fn(u64, &Metrics) + Send + Sync>;
+
+pub struct VirtualUserManager {
+    config: VirtualUserConfig,
+    plans: Vec<PlanSegment>,
+    rps_plans: Vec<RpsPlanSegment>,
+    vu_rate_plans: Vec<VuRatePlanSegment>,
+    running_vus: Vec<VirtualUser>,
+    overall_metrics: Metrics,
+    measured_rps: Option<f64>,
+    segment_timings: Vec<SegmentTiming>,
+    // Only collected by `run_per_segment_metrics`.
+    open_segment: Option<Metrics>,
+    closed_segments: Vec<Metrics>,
+    ramp_samples: Vec<RampSample>,
+    iteration_budget: Option<Arc<IterationBudget>>,
+    byte_budget: Option<Arc<ByteBudget>>,
+    in_flight_limit: Option<Arc<Semaphore>>,
+    vu_rate: Option<Arc<VuRate>>,
+    next_report: Option<Instant>,
+    snapshots: Option<SnapshotSender>,
+    progress: Arc<RunProgress>,
+    run_deadline: Option<Instant>,
+    duration_capped: bool,
+    cancellation: Option<CancellationToken>,
+    replay: Option<Arc<ReplayQueue>>,
+    fail_fast: Option<Arc<FailFast>>,
+    vu_seconds: f64,
+    vus_changed_at: Option<Instant>,
+    peak_vus: usize,
+    spawned_vus: u64,
+    running_vu_ids: Vec<u64>,
+    running_vu_regions: Vec<Option<usize>>,
+    region_metrics: HashMap<String, Metrics>,
+    on_vu_start: Option<VuStartCallback>,
+    on_vu_stop: Option<VuStopCallback>,
+    client: Option<reqwest::Client>,
+    join_error: Option<JoinError>,
+    #[cfg(feature = "rustls")]
+    tls_stats: Option<Arc<TlsSessionStats>>,
+}
+
+impl VirtualUserManager {
+    pub fn new(config: VirtualUserConfig) -> Self {
+        let overall_metrics = Self::fresh_metrics(&config);
+        #[cfg(feature = "rustls")]
+        let tls_stats = config
+            .tls_session_tracking
+            .then(|| Arc::new(TlsSessionStats::default()));
+        let fail_fast = config.fail_fast.then(|| Arc::new(FailFast::default()));
+
+        Self {
+            config,
+            plans: Vec::new(),
+            rps_plans: Vec::new(),
+            vu_rate_plans: Vec::new(),
+            running_vus: Vec::new(),
+            overall_metrics,
+            measured_rps: None,
+            segment_timings: Vec::new(),
+            open_segment: None,
+            closed_segments: Vec::new(),
+            ramp_samples: Vec::new(),
+            iteration_budget: None,
+            byte_budget: None,
+            in_flight_limit: None,
+            vu_rate: None,
+            next_report: None,
+            snapshots: None,
+            progress: Arc::default(),
+            run_deadline: None,
+            duration_capped: false,
+            cancellation: None,
+            replay: None,
+            fail_fast,
+            vu_seconds: 0.0,
+            vus_changed_at: None,
+            peak_vus: 0,
+            spawned_vus: 0,
+            running_vu_ids: Vec::new(),
+            running_vu_regions: Vec::new(),
+            region_metrics: HashMap::new(),
+            on_vu_start: None,
+            on_vu_stop: None,
+            client: None,
+            join_error: None,
+            #[cfg(feature = "rustls")]
+            tls_stats,
+        }
+    }
+
+    pub fn add_plan(&mut self, duration: Duration, target: usize) {
+        self.plans.push(PlanSegment::new(duration, target));
+    }
+
+    // Streams the metrics collected so far every `interval` during the next run, ending with
+    // its overall metrics once it completes.
+    pub fn metrics_snapshots(&mut self, interval: Duration) -> MetricsStream {
+        if interval.is_zero() {
+            panic!("snapshot interval must be greater than 0");
+        }
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let now = Instant::now();
+        self.snapshots = Some(SnapshotSender {
+            interval,
+            next_due: now + interval,
+            run_start: now,
+            sender,
+        });
+        MetricsStream(receiver)
+    }
+
+    // Only plan-driven runs (`run` and `run_rps`) have a duration to measure against.
+    pub fn progress(&self) -> f64 {
+        self.progress.fraction()
+    }
+
+    // For following progress from another task while `run` holds the manager.
+    pub fn progress_handle(&self) -> Arc<RunProgress> {
+        self.progress.clone()
+    }
+
+    pub fn on_vu_start<F>(&mut self, callback: F)
+    where
+        F: Fn(u64) + Send + Sync + 'static,
+    {
+        self.on_vu_start = Some(Box::new(callback));
+    }
+
+    pub fn on_vu_stop<F>(&mut self, callback: F)
+    where
+        F: Fn(u64, &Metrics) + Send + Sync + 'static,
+    {
+        self.on_vu_stop = Some(Box::new(callback));
+    }
+
+    pub fn add_segment(&mut self, segment: PlanSegment) {
+        self.plans.push(segment);
+    }
+
+    pub fn add_rps_plan(&mut self, duration: Duration, target_rps: f64) {
+        self.rps_plans
+            .push(RpsPlanSegment::new(duration, target_rps));
+    }
+
+    pub fn add_vu_rate_plan(&mut self, duration: Duration, max_rps_per_vu: f64) {
+        self.vu_rate_plans
+            .push(VuRatePlanSegment::new(duration, max_rps_per_vu));
+    }
+
+    pub async fn run(&mut self) -> Result<RunResult, RunError> {
+        self.prepare_run().await?;
+        let run_start = Instant::now();
+        self.segment_timings.clear();
+        self.ramp_samples.clear();
+        self.reset_vu_time();
+        let planned = self
+            .plans
+            .iter()
+            .map(|plan| plan.duration)
+            .sum::<Duration>()
+            + self.config.final_ramp_down.unwrap_or_default();
+        self.start_progress(planned);
+
+        let final_grace = self
+            .config
+            .final_graceful_shutdown
+            .unwrap_or(self.config.graceful_shutdown);
+        let driven = self.drive_plans(run_start, final_grace).await;
+        while self.stop_last_vu_with_grace(final_grace).await {}
+        self.progress.finish();
+        self.finish_run(run_start);
+        self.write_output()?;
+        driven?;
+        self.check_vu_panics()?;
+        Ok(self.run_result())
+    }
+
+    async fn drive_plans(
+        &mut self,
+        run_start: Instant,
+        final_grace: Duration,
+    ) -> Result<(), RunError> {
+        let mut current_count = self.running_vus.len();
+        let plans = self.plans.clone();
+
+        for plan in &plans {
+            if self.should_stop() {
+                break;
+            }
+            let change = plan.target as isize - current_count as isize;
+            let direction = match change.cmp(&0) {
+                _ if plan.target == 0 && (plan.immediate || current_count == 0) => {
+                    RampDirection::Idle
+                }
+                std::cmp::Ordering::Greater => RampDirection::RampUp,
+                std::cmp::Ordering::Less => RampDirection::RampDown,
+                std::cmp::Ordering::Equal => RampDirection::Hold,
+            };
+            let start_time = Instant::now();
+            #[cfg(feature = "tracing")]
+            tracing::info!(
+                segment = self.segment_timings.len(),
+                from = current_count,
+                target = plan.target,
+                ?direction,
+                elapsed = ?start_time.duration_since(run_start),
+                "plan transition"
+            );
+
+            let grace = plan
+                .graceful_shutdown
+                .unwrap_or(self.config.graceful_shutdown);
+            if plan.immediate {
+                // Signal every surplus VU up front so they drain in parallel rather than one
+                // at a time while the rest keep sending.
+                for vu in self.running_vus.iter_mut().skip(plan.target) {
+                    vu.signal_stop();
+                }
+                self.ramp(&mut current_count, plan.target, Duration::ZERO, grace)
+                    .await?;
+            }
+            self.ramp(&mut current_count, plan.target, plan.duration, grace)
+                .await?;
+            self.close_segment(start_time).await;
+
+            self.segment_timings.push(SegmentTiming {
+                target: plan.target,
+                direction,
+                started_at: start_time.duration_since(run_start),
+                elapsed: start_time.elapsed(),
+            });
+        }
+
+        if let Some(ramp_down) = self.config.final_ramp_down {
+            self.ramp(&mut current_count, 0, ramp_down, final_grace)
+                .await?;
+        }
+        Ok(())
+    }
+
+    // Like `run`, but stops early once `token` is cancelled: VUs get their usual graceful
+    // shutdown and the result, marked as aborted, covers what was sent until then.
+    pub async fn run_cancellable(
+        &mut self,
+        token: CancellationToken,
+    ) -> Result<RunResult, RunError> {
+        self.cancellation = Some(token);
+        let result = self.run().await;
+        self.cancellation = None;
+        result
+    }
+
+    // Like `run`, but also returns metrics for each plan segment on its own; the overall
+    // metrics still cover the whole run.
+    pub async fn run_per_segment_metrics(&mut self) -> Result<(RunResult, Vec<Metrics>), RunError> {
+        self.open_segment = Some(Self::fresh_metrics(&self.config));
+        self.closed_segments.clear();
+        let result = self.run().await;
+        self.open_segment = None;
+        let segments = std::mem::take(&mut self.closed_segments);
+        Ok((result?, segments))
+    }
+
+    // VUs outlive segment boundaries, so whatever the running ones have recorded is moved out
+    // of them first; VUs stopped during the segment were merged in as they stopped.
+    async fn close_segment(&mut self, start_time: Instant) {
+        let Some(mut segment) = self.open_segment.take() else {
+            return;
+        };
+        let running: Vec<_> = self
+            .running_vus
+            .iter()
+            .map(VirtualUser::metrics)
+            .zip(self.running_vu_regions.clone())
+            .collect();
+        for (metrics, region) in running {
+            let drained = {
+                let mut m = metrics.lock().await;
+                let mut fresh = Self::fresh_metrics(&self.config);
+                // The VU keeps counting into its own counters and RPS windows.
+                std::mem::swap(&mut fresh.rps_summary, &mut m.rps_summary);
+                fresh.counters = m.counters.clone();
+                let mut drained = std::mem::replace(&mut *m, fresh);
+                let counters = Counters::default();
+                counters.add(&drained.counters.take());
+                drained.counters = Arc::new(counters);
+                drained
+            };
+            Self::merge_metrics(&mut segment, &drained);
+            Self::merge_metrics(&mut self.overall_metrics, &drained);
+            if let Some(region) = region.map(|index| &self.config.regions[index]) {
+                let region_metrics = self
+                    .region_metrics
+                    .entry(region.name.clone())
+                    .or_insert_with(|| Self::fresh_metrics(&self.config.for_region(region)));
+                Self::merge_metrics(region_metrics, &drained);
+            }
+        }
+        segment.started_at = Some(start_time);
+        segment.run_duration = Some(start_time.elapsed());
+        self.closed_segments.push(segment);
+        self.open_segment = Some(Self::fresh_metrics(&self.config));
+    }
+
+    // Replaces any configured plan: all VUs start at once, hold, then tear down.
+    pub async fn run_constant(
+        &mut self,
+        vus: usize,
+        duration: Duration,
+    ) -> Result<RunResult, RunError> {
+        self.plans = vec![
+            PlanSegment::new(Duration::ZERO, vus),
+            PlanSegment::new(duration, vus),
+        ];
+        self.run().await
+    }
+
+    // Spreads exactly `iterations` iterations over `workers` VUs and returns once they are
+    // all done, however long that takes.
+    pub async fn run_iterations(
+        &mut self,
+        iterations: usize,
+        workers: usize,
+    ) -> Result<RunResult, RunError> {
+        self.prepare_run().await?;
+        let run_start = Instant::now();
+        self.ramp_samples.clear();
+        self.reset_vu_time();
+        let budget = Arc::new(IterationBudget::new(iterations));
+        self.iteration_budget = Some(budget.clone());
+
+        let mut driven = Ok(());
+        for _ in 0..workers.min(iterations).max(1) {
+            driven = self.spawn_vu();
+            if driven.is_err() {
+                break;
+            }
+        }
+        while driven.is_ok()
+            && !self.duration_capped
+            && !self.running_vus.iter().all(VirtualUser::is_finished)
+        {
+            driven = self.tick(Duration::from_millis(10)).await;
+        }
+        while self.stop_last_vu().await {}
+        self.iteration_budget = None;
+        self.finish_run(run_start);
+        self.write_output()?;
+        driven?;
+        self.check_vu_panics()?;
+        let mut result = self.run_result();
+        result.iterations_completed = Some(budget.completed());
+        Ok(result)
+    }
+
+    // Sends every request in a JSONL log once, spread over up to `max_vus` VUs. Lines that
+    // fail to parse are skipped and reported in `RunResult::skipped_log_lines`.
+    pub async fn replay(
+        &mut self,
+        log_path: impl AsRef<Path>,
+        pacing: ReplayPacing,
+    ) -> Result<RunResult, RunError> {
+        let path = log_path.as_ref();
+        let log = ReplayLog::load(path).map_err(|source| RunError::ReplayLog {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        self.prepare_run().await?;
+        let run_start = Instant::now();
+        self.ramp_samples.clear();
+        self.reset_vu_time();
+        let workers = self.config.max_vus.min(log.requests.len());
+        self.replay = Some(Arc::new(ReplayQueue::new(log.requests, pacing)));
+
+        let mut driven = Ok(());
+        for _ in 0..workers {
+            driven = self.spawn_vu();
+            if driven.is_err() {
+                break;
+            }
+        }
+        while driven.is_ok()
+            && !self.duration_capped
+            && !self.running_vus.iter().all(VirtualUser::is_finished)
+        {
+            driven = self.tick(Duration::from_millis(10)).await;
+        }
+        while self.stop_last_vu().await {}
+        self.replay = None;
+        self.finish_run(run_start);
+        self.write_output()?;
+        driven?;
+        self.check_vu_panics()?;
+        let mut result = self.run_result();
+        result.skipped_log_lines = Some(log.skipped_lines);
+        Ok(result)
+    }
+
+    pub async fn run_rps(&mut self) -> Result<RunResult, RunError> {
+        self.prepare_run().await?;
+        let run_start = Instant::now();
+        self.ramp_samples.clear();
+        self.reset_vu_time();
+        let rps_plans = self.rps_plans.clone();
+        self.start_progress(rps_plans.iter().map(|plan| plan.duration).sum());
+
+        let mut driven = Ok(());
+        for plan in &rps_plans {
+            driven = self.hold_rps(plan.target_rps, plan.duration).await;
+            if driven.is_err() {
+                break;
+            }
+        }
+        while self.stop_last_vu().await {}
+        self.progress.finish();
+        self.finish_run(run_start);
+        self.write_output()?;
+        driven?;
+        self.check_vu_panics()?;
+        Ok(self.run_result())
+    }
+
+    // Runs `vus` VUs for the whole of the per-VU rate plans, see `VuRatePlanSegment`.
+    pub async fn run_vu_rate(&mut self, vus: usize) -> Result<RunResult, RunError> {
+        self.prepare_run().await?;
+        let run_start = Instant::now();
+        self.ramp_samples.clear();
+        self.reset_vu_time();
+        let plans = self.vu_rate_plans.clone();
+        self.start_progress(plans.iter().map(|plan| plan.duration).sum());
+        let mut previous = plans
+            .first()
+            .map_or(f64::INFINITY, |plan| plan.max_rps_per_vu);
+        let rate = Arc::new(VuRate::new(previous));
+        self.vu_rate = Some(rate.clone());
+
+        let mut driven = Ok(());
+        for _ in 0..vus {
+            driven = self.spawn_vu();
+            if driven.is_err() {
+                break;
+            }
+        }
+        for plan in &plans {
+            if driven.is_err() || self.should_stop() {
+                break;
+            }
+            driven = self.ramp_vu_rate(&rate, previous, plan).await;
+            previous = plan.max_rps_per_vu;
+        }
+        while self.stop_last_vu().await {}
+        self.vu_rate = None;
+        self.progress.finish();
+        self.finish_run(run_start);
+        self.write_output()?;
+        driven?;
+        self.check_vu_panics()?;
+        Ok(self.run_result())
+    }
+
+    async fn ramp_vu_rate(
+        &mut self,
+        rate: &VuRate,
+        from: f64,
+        plan: &VuRatePlanSegment,
+    ) -> Result<(), RunError> {
+        let tick_interval = Duration::from_millis(100);
+        let start_time = Instant::now();
+        while start_time.elapsed() < plan.duration && !self.should_stop() {
+            let fraction = start_time.elapsed().as_secs_f64() / plan.duration.as_secs_f64();
+            rate.set(from + (plan.max_rps_per_vu - from) * fraction.min(1.0));
+            self.tick(tick_interval).await?;
+        }
+        rate.set(plan.max_rps_per_vu);
+        Ok(())
+    }
+
+    // Binary search between `min_rps` and `max_rps`; every probe is a fresh RPS-targeted run
+    // judged against the SLA on its own metrics.
+    pub async fn find_capacity(
+        &mut self,
+        search: &CapacitySearch,
+    ) -> Result<CapacityResult, RunError> {
+        self.prepare_run().await?;
+        let mut result = CapacityResult {
+            max_rps: None,
+            metrics: None,
+            probes: Vec::new(),
+        };
+        let (mut low, mut high) = (search.min_rps, search.max_rps);
+        let mut target = search.min_rps;
+        let mut per_vu_rps: Option<f64> = None;
+
+        loop {
+            // Start near the right VU count so the probe spends its time at the target rate.
+            let initial_vus = per_vu_rps.map_or(1, |rps| (target / rps).round() as usize);
+            self.measured_rps = None;
+            let probe_start = Instant::now();
+            let probed = self
+                .probe_rps(initial_vus, target, search.probe_duration)
+                .await;
+            let probe_per_vu_rps = self
+                .measured_rps
+                .map(|rps| rps / self.running_vus.len().max(1) as f64);
+            while self.stop_last_vu().await {}
+            probed?;
+            self.check_vu_panics()?;
+
+            let fresh = Self::fresh_metrics(&self.config);
+            let mut metrics = std::mem::replace(&mut self.overall_metrics, fresh);
+            self.region_metrics.clear();
+            metrics.started_at = Some(probe_start);
+            metrics.run_duration = Some(probe_start.elapsed());
+            let threshold_results: Vec<_> = search
+                .sla
+                .iter()
+                .map(|threshold| threshold.check(&metrics))
+                .collect();
+            let passed = threshold_results.iter().all(|result| result.passed);
+            result.probes.push(CapacityProbe {
+                target_rps: target,
+                threshold_results,
+                passed,
+            });
+
+            if passed {
+                // A saturated probe's per-VU rate would overshoot the next, lower target.
+                per_vu_rps = probe_per_vu_rps.or(per_vu_rps);
+                result.max_rps = Some(target);
+                result.metrics = Some(metrics);
+                low = target;
+            } else if result.max_rps.is_none() {
+                break;
+            } else {
+                high = target;
+            }
+            if high - low <= search.tolerance {
+                break;
+            }
+            target = (low + high) / 2.0;
+        }
+
+        Ok(result)
+    }
+
+    // The probe goes through a client built the way a run builds its own, so it reaches the
+    // target over the same proxy, resolver, TLS and HTTP settings.
+    pub async fn dry_run(&self) -> DryRunReport {
+        let client = match &self.config.client {
+            Some(client) => Ok(client.clone()),
+            None if self.config.uses_default_client() => Ok(GLOBAL_CLIENT.clone()),
+            None => self.config.build_client(),
+        };
+        let probe_start = Instant::now();
+        let probe_result = match client {
+            Ok(client) => client
+                .get(&self.config.url)
+                .send()
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(RunError::Client(e).to_string()),
+        };
+        let latency = probe_start.elapsed();
+
+        let (status, error) = match probe_result {
+            Ok(resp) => (Some(resp.status().as_u16()), None),
+            Err(e) => (None, Some(e)),
+        };
+
+        let mut peak_vus = 0;
+        let mut previous_target = 0;
+        let mut total_duration = Duration::from_secs(0);
+        let mut vu_seconds = 0.0;
+        for plan in &self.plans {
+            peak_vus = peak_vus.max(plan.target);
+            total_duration += plan.duration;
+            vu_seconds +=
+                (previous_target + plan.target) as f64 / 2.0 * plan.duration.as_secs_f64();
+            previous_target = plan.target;
+        }
+        for plan in &self.rps_plans {
+            total_duration += plan.duration;
+        }
+        for plan in &self.vu_rate_plans {
+            total_duration += plan.duration;
+        }
+
+        let estimated_requests = match status {
+            Some(_) if latency.as_secs_f64() > 0.0 => {
+                Some((vu_seconds / latency.as_secs_f64()) as u64)
+            }
+            _ => None,
+        };
+
+        DryRunReport {
+            status,
+            latency,
+            error,
+            peak_vus,
+            total_duration,
+            estimated_requests,
+        }
+    }
+
+    // Polls `url` until a response passes `predicate`, so a run can wait for its target to come
+    // up. Call it before `run`; a target still failing after `timeout` is an error.
+    pub async fn wait_for_healthy(
+        &mut self,
+        url: &str,
+        predicate: SuccessPredicate,
+        timeout: Duration,
+    ) -> Result<(), RunError> {
+        self.prepare_client()?;
+        let client = match &self.client {
+            Some(client) => client.clone(),
+            None if self.config.uses_default_client() => GLOBAL_CLIENT.clone(),
+            None => self.config.build_client()?,
+        };
+        let deadline = Instant::now() + timeout;
+        loop {
+            let check = async {
+                let response = client.get(url).send().await.ok()?;
+                let status = response.status().as_u16();
+                let body = response.bytes().await.ok()?;
+                Some(predicate.check(status, &body))
+            };
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if let Ok(Some(true)) = tokio::time::timeout(remaining, check).await {
+                return Ok(());
+            }
+            if Instant::now() + HEALTH_CHECK_INTERVAL >= deadline {
+                return Err(RunError::Unhealthy {
+                    url: url.to_string(),
+                    timeout,
+                });
+            }
+            sleep(HEALTH_CHECK_INTERVAL).await;
+        }
+    }
+
+    pub fn get_measured_rps(&self) -> Option<f64> {
+        self.measured_rps
+    }
+
+    pub fn circuit_breaker_transitions(&self) -> Vec<BreakerTransition> {
+        self.config
+            .circuit_breaker
+            .as_ref()
+            .map_or_else(Vec::new, |breaker| breaker.transitions())
+    }
+
+    pub fn segment_timings(&self) -> &[SegmentTiming] {
+        &self.segment_timings
+    }
+
+    pub fn get_overall_metrics(&self) -> &Metrics {
+        &self.overall_metrics
+    }
+
+    async fn ramp(
+        &mut self,
+        current_count: &mut usize,
+        target_count: usize,
+        duration: Duration,
+        grace: Duration,
+    ) -> Result<(), RunError> {
+        let tick_interval = Duration::from_millis(100);
+        let segment_start_count = *current_count;
+        let change = target_count as isize - segment_start_count as isize;
+        let start_time = Instant::now();
+        let mut pacer = self
+            .config
+            .feedback_ramp
+            .filter(|_| change > 0)
+            .map(|feedback| RampPacer::new(feedback, start_time));
+        // How much of the planned duration has been covered; a slowed ramp covers it late.
+        let mut progress = Duration::ZERO;
+        let mut ticked_at = start_time;
+
+        while progress < duration && !self.should_stop() {
+            let ratio = (progress.as_secs_f64() / duration.as_secs_f64()).min(1.0);
+            let ideal_count = segment_start_count as f64 + (change as f64 * ratio);
+            let diff = ideal_count - *current_count as f64;
+            let delta_int: isize = if diff >= 1.0 {
+                diff.floor() as isize
+            } else if diff <= -1.0 {
+                diff.ceil() as isize
+            } else {
+                0
+            };
+
+            use std::cmp::Ordering;
+
+            match delta_int.cmp(&0) {
+                Ordering::Greater => {
+                    for _ in 0..delta_int {
+                        self.spawn_vu()?;
+                        *current_count += 1;
+                    }
+                }
+                Ordering::Less => {
+                    let num_to_remove = (-delta_int) as usize;
+                    for _ in 0..num_to_remove {
+                        if self.stop_last_vu_with_grace(grace).await {
+                            *current_count -= 1;
+                        }
+                    }
+                }
+                Ordering::Equal => {}
+            }
+
+            self.ramp_samples.push(RampSample {
+                at: Instant::now(),
+                requests: self.snapshot().requests,
+                ideal_vus: ideal_count,
+                active_vus: self.running_vus.len(),
+            });
+            self.tick(tick_interval).await?;
+
+            let now = Instant::now();
+            let pace = match &mut pacer {
+                Some(pacer) => {
+                    if pacer.is_due(now) {
+                        pacer.update(now, self.latency_totals().await);
+                    }
+                    pacer.pace()
+                }
+                None => 1.0,
+            };
+            progress += now.duration_since(ticked_at).mul_f64(pace);
+            ticked_at = now;
+        }
+
+        // Starting more VUs would be pointless once the run is over budget or past its cap.
+        while *current_count < target_count && !self.should_stop() {
+            self.spawn_vu()?;
+            *current_count += 1;
+        }
+        while *current_count > target_count {
+            if self.stop_last_vu_with_grace(grace).await {
+                *current_count -= 1;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let mut snapshot = self.overall_metrics.counters.snapshot();
+        for vu in &self.running_vus {
+            snapshot.merge(&vu.counters().snapshot());
+        }
+        snapshot.active_vus = self.running_vus.len();
+        snapshot
+    }
+
+    fn vu_series(&self) -> Vec<(Duration, usize)> {
+        let Some(run_start) = self.overall_metrics.started_at else {
+            return Vec::new();
+        };
+        self.ramp_samples
+            .iter()
+            .map(|sample| (sample.at.duration_since(run_start), sample.active_vus))
+            .collect()
+    }
+
+    fn run_result(&self) -> RunResult {
+        let total_requests = self.overall_metrics.total_requests();
+        let successful_requests = self.overall_metrics.successful_responses;
+        RunResult {
+            threshold_results: self
+                .config
+                .thresholds
+                .iter()
+                .map(|threshold| threshold.check(&self.overall_metrics))
+                .collect(),
+            ramp_fidelity: RampFidelity::from_samples(
+                &self.ramp_samples,
+                self.config.rps_window_size,
+            ),
+            vu_series: self.vu_series(),
+            effective_concurrency: self.effective_concurrency(),
+            duration_cap_reached: self.duration_capped,
+            iterations_completed: None,
+            skipped_log_lines: None,
+            aborted: self.is_cancelled(),
+            total_requests,
+            successful_requests,
+            failed_requests: total_requests - successful_requests,
+        }
+    }
+
+    fn effective_concurrency(&self) -> Option<EffectiveConcurrency> {
+        let duration = self.overall_metrics.run_duration?;
+        Some(EffectiveConcurrency {
+            achieved: self.overall_metrics.effective_concurrency()?,
+            average_vus: self.vu_seconds / duration.as_secs_f64(),
+            peak_vus: self.peak_vus,
+        })
+    }
+
+    fn reset_vu_time(&mut self) {
+        self.vu_seconds = 0.0;
+        self.vus_changed_at = None;
+        self.peak_vus = 0;
+    }
+
+    // Integrates the running VU count over time; call before every change to it.
+    fn track_vu_time(&mut self) {
+        let now = Instant::now();
+        if let Some(at) = self.vus_changed_at {
+            self.vu_seconds += self.running_vus.len() as f64 * now.duration_since(at).as_secs_f64();
+        }
+        self.vus_changed_at = Some(now);
+    }
+
+    fn write_output(&self) -> Result<(), RunError> {
+        let Some(path) = &self.config.output_file else {
+            return Ok(());
+        };
+
+        let write = || -> io::Result<()> {
+            if let Some(parent) = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+            {
+                fs::create_dir_all(parent)?;
+            }
+            let report = serde_json::to_vec_pretty(&self.overall_metrics.json_report())?;
+            fs::write(path, report)
+        };
+
+        write().map_err(|source| RunError::Output {
+            path: path.clone(),
+            source,
+        })
+    }
+
+    // Builds the client up front so a bad config fails the run instead of panicking in
+    // spawn_vu; per-VU clients are built from the same config, so they succeed as well.
+    fn prepare_client(&mut self) -> Result<(), RunError> {
+        if self.client.is_some() {
+            return Ok(());
+        }
+        if let Some(client) = &self.config.client {
+            self.client = Some(client.clone());
+            return Ok(());
+        }
+        #[cfg(feature = "rustls")]
+        if let Some(stats) = &self.tls_stats {
+            let builder = self.config.client_builder()?;
+            let identity = match &self.config.client_identity {
+                Some(ClientIdentity::Pem { cert, key }) => Some((cert.as_slice(), key.as_slice())),
+                Some(ClientIdentity::Pkcs12 { .. }) => return Err(tls::TlsError::Pkcs12.into()),
+                None => None,
+            };
+            self.client = Some(tls::session_tracking_client(
+                builder,
+                &self.config.root_certificates,
+                identity,
+                stats.clone(),
+            )?);
+            return Ok(());
+        }
+        if !self.config.uses_default_client() {
+            let client = self.config.build_client()?;
+            if self.config.shared_client {
+                self.client = Some(client);
+            }
+        }
+        Ok(())
+    }
+
+    async fn prepare_run(&mut self) -> Result<(), RunError> {
+        self.config.validate()?;
+        self.prepare_client()?;
+        self.prewarm().await;
+        if let Some(fail_fast) = &self.fail_fast {
+            fail_fast.reset();
+        }
+        self.byte_budget = self
+            .config
+            .max_bytes
+            .map(|limit| Arc::new(ByteBudget::new(limit)));
+        self.in_flight_limit = self
+            .config
+            .max_in_flight
+            .map(|limit| Arc::new(Semaphore::new(limit)));
+        self.next_report = self
+            .config
+            .report_interval
+            .as_ref()
+            .map(|reporter| Instant::now() + reporter.interval);
+        if let Some(snapshots) = &mut self.snapshots {
+            snapshots.run_start = Instant::now();
+            snapshots.next_due = snapshots.run_start + snapshots.interval;
+        }
+        self.run_deadline = self
+            .config
+            .max_duration
+            .map(|max_duration| Instant::now() + max_duration);
+        self.duration_capped = false;
+        Ok(())
+    }
+
+    fn byte_budget_spent(&self) -> bool {
+        self.byte_budget
+            .as_ref()
+            .is_some_and(|budget| budget.is_spent())
+    }
+
+    fn start_progress(&self, planned: Duration) {
+        let planned = self
+            .config
+            .max_duration
+            .map_or(planned, |max_duration| planned.min(max_duration));
+        self.progress.start(planned);
+    }
+
+    fn should_stop(&self) -> bool {
+        self.duration_capped || self.byte_budget_spent() || self.is_cancelled()
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+    }
+
+    // Sleeps for `interval`, cut short by the duration cap or cancellation, or with the failure
+    // if fail-fast trips meanwhile.
+    async fn tick(&mut self, interval: Duration) -> Result<(), RunError> {
+        self.on_tick();
+        self.report_if_due().await;
+        self.send_snapshot_if_due().await;
+        let deadline = self.run_deadline;
+        let interval = deadline.map_or(interval, |deadline| {
+            interval.min(deadline.saturating_duration_since(Instant::now()))
+        });
+        self.wait(interval).await?;
+        self.duration_capped = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        Ok(())
+    }
+
+    async fn wait(&self, interval: Duration) -> Result<(), RunError> {
+        let cancelled = async {
+            match &self.cancellation {
+                Some(token) => token.cancelled().await,
+                None => std::future::pending().await,
+            }
+        };
+        let Some(fail_fast) = &self.fail_fast else {
+            tokio::select! {
+                _ = sleep(interval) => {},
+                _ = cancelled => {},
+            }
+            return Ok(());
+        };
+        let mut failure = fail_fast.subscribe();
+        if failure.borrow().is_none() {
+            tokio::select! {
+                _ = sleep(interval) => {},
+                _ = failure.changed() => {},
+                _ = cancelled => {},
+            }
+        }
+        match fail_fast.failure() {
+            Some(failure) => Err(RunError::FailFast(failure)),
+            None => Ok(()),
+        }
+    }
+
+    async fn report_if_due(&mut self) {
+        let (Some(reporter), Some(due)) = (&self.config.report_interval, self.next_report) else {
+            return;
+        };
+        let now = Instant::now();
+        if now < due {
+            return;
+        }
+        self.next_report = Some(reporter.next_due(due, now));
+        reporter.report(&self.current_metrics().await);
+    }
+
+    async fn send_snapshot_if_due(&mut self) {
+        let Some(snapshots) = &self.snapshots else {
+            return;
+        };
+        let now = Instant::now();
+        if now < snapshots.next_due {
+            return;
+        }
+        let metrics = self.current_metrics().await;
+        let Some(snapshots) = &mut self.snapshots else {
+            return;
+        };
+        snapshots.next_due = reporter::next_due(snapshots.interval, snapshots.next_due, now);
+        if !snapshots.send(metrics) {
+            self.snapshots = None;
+        }
+    }
+
+    // What stopped VUs left behind plus what the running ones hold right now.
+    async fn current_metrics(&self) -> Metrics {
+        let mut metrics = Self::fresh_metrics(&self.config);
+        Self::merge_metrics(&mut metrics, &self.overall_metrics);
+        for vu in &self.running_vus {
+            let vu_metrics = vu.metrics();
+            Self::merge_metrics(&mut metrics, &*vu_metrics.lock().await);
+        }
+        metrics
+    }
+
+    // Sends one request per connection to every target at once, so each one has to open a
+    // connection of its own. Failures are ignored like a VU's warm-up request.
+    async fn prewarm(&self) {
+        let connections = self.config.prewarm_connections;
+        let client = match &self.client {
+            Some(client) => client.clone(),
+            None if self.config.uses_default_client() => GLOBAL_CLIENT.clone(),
+            None => return,
+        };
+        let urls: Vec<String> = if self.config.regions.is_empty() {
+            vec![self.config.url.clone()]
+        } else {
+            self.config
+                .regions
+                .iter()
+                .map(|region| region.url.clone())
+                .collect()
+        };
+
+        let mut warming = tokio::task::JoinSet::new();
+        for url in &urls {
+            for _ in 0..connections {
+                let request = client.get(url).send();
+                let deadline = self.config.request_deadline;
+                warming.spawn(async move {
+                    match deadline {
+                        Some(deadline) => {
+                            let _ = tokio::time::timeout(deadline, request).await;
+                        }
+                        None => {
+                            let _ = request.await;
+                        }
+                    }
+                });
+            }
+        }
+        while warming.join_next().await.is_some() {}
+    }
+
+    fn spawn_vu(&mut self) -> Result<(), RunError> {
+        let region = self.next_region();
+        let region_config = region.map(|index| self.config.for_region(&self.config.regions[index]));
+        let config = region_config.as_ref().unwrap_or(&self.config);
+        let vu = match &self.client {
+            Some(client) => VirtualUser::with_client(config, client.clone()),
+            None => VirtualUser::from_config(config)?,
+        };
+        let vu_id = self.spawned_vus;
+        self.spawned_vus += 1;
+        let mut vu = vu
+            .set_seed(self.config.seed.map(|seed| seed.wrapping_add(vu_id)))
+            .set_iteration_budget(self.iteration_budget.clone())
+            .set_byte_budget(self.byte_budget.clone())
+            .set_in_flight_limit(self.in_flight_limit.clone())
+            .set_vu_rate(self.vu_rate.clone())
+            .set_replay(self.replay.clone())
+            .set_fail_fast(self.fail_fast.clone());
+        vu.start();
+        self.track_vu_time();
+        self.running_vus.push(vu);
+        self.peak_vus = self.peak_vus.max(self.running_vus.len());
+        self.running_vu_ids.push(vu_id);
+        self.running_vu_regions.push(region);
+        if let Some(callback) = &self.on_vu_start {
+            callback(vu_id);
+        }
+        Ok(())
+    }
+
+    // The region furthest below its weighted share of the running VUs.
+    fn next_region(&self) -> Option<usize> {
+        let regions = &self.config.regions;
+        let load = |index: usize| {
+            let running = self
+                .running_vu_regions
+                .iter()
+                .filter(|region| **region == Some(index))
+                .count();
+            (running + 1) as f64 / regions[index].weight as f64
+        };
+        (0..regions.len()).min_by(|a, b| load(*a).total_cmp(&load(*b)))
+    }
+
+    pub fn region_metrics(&self, name: &str) -> Option<&Metrics> {
+        self.region_metrics.get(name)
+    }
+
+    // Also runs when a run stops early with an error, so a snapshot stream still gets the
+    // final metrics before it closes.
+    fn finish_run(&mut self, run_start: Instant) {
+        let duration = run_start.elapsed();
+        for metrics in
+            std::iter::once(&mut self.overall_metrics).chain(self.region_metrics.values_mut())
+        {
+            metrics.started_at = Some(run_start);
+            metrics.run_duration = Some(duration);
+        }
+        if let Some(snapshots) = self.snapshots.take() {
+            let mut metrics = Self::fresh_metrics(&self.config);
+            Self::merge_metrics(&mut metrics, &self.overall_metrics);
+            metrics.started_at = Some(run_start);
+            metrics.run_duration = Some(duration);
+            snapshots.send(metrics);
+        }
+    }
+
+    // Reports the first VU task that panicked since the last check.
+    fn check_vu_panics(&mut self) -> Result<(), RunError> {
+        match self.join_error.take() {
+            Some(e) => Err(RunError::Join(e)),
+            None => Ok(()),
+        }
+    }
+
+    async fn stop_last_vu(&mut self) -> bool {
+        self.stop_last_vu_with_grace(self.config.graceful_shutdown)
+            .await
+    }
+
+    async fn stop_last_vu_with_grace(&mut self, grace: Duration) -> bool {
+        self.track_vu_time();
+        match self.running_vus.pop() {
+            Some(mut vu) => {
+                let vu_id = self.running_vu_ids.pop().unwrap_or_default();
+                let region = self.running_vu_regions.pop().flatten();
+                vu.stop_with_grace(grace).await;
+                if let Some(e) = vu.take_join_error() {
+                    self.join_error.get_or_insert(e);
+                }
+                let metrics = vu.metrics();
+                // The VU task has been joined, so nothing else can hold this lock.
+                let m = metrics.lock().await;
+                Self::merge_metrics(&mut self.overall_metrics, &m);
+                if let Some(segment) = &mut self.open_segment {
+                    Self::merge_metrics(segment, &m);
+                }
+                if let Some(region) = region.map(|index| &self.config.regions[index]) {
+                    let region_metrics = self
+                        .region_metrics
+                        .entry(region.name.clone())
+                        .or_insert_with(|| Self::fresh_metrics(&self.config.for_region(region)));
+                    Self::merge_metrics(region_metrics, &m);
+                }
+                if let Some(callback) = &self.on_vu_stop {
+                    callback(vu_id, &m);
+                }
+                #[cfg(feature = "rustls")]
+                if let Some(stats) = &self.tls_stats {
+                    self.overall_metrics.tls_resumed_handshakes = stats.resumed();
+                    self.overall_metrics.tls_full_handshakes = stats.full();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    async fn probe_rps(
+        &mut self,
+        initial_vus: usize,
+        target_rps: f64,
+        duration: Duration,
+    ) -> Result<(), RunError> {
+        for _ in 0..initial_vus.clamp(1, self.config.max_vus.max(1)) {
+            self.spawn_vu()?;
+        }
+        self.hold_rps(target_rps, duration).await
+    }
+
+    async fn hold_rps(&mut self, target_rps: f64, duration: Duration) -> Result<(), RunError> {
+        let tick_interval = Duration::from_millis(100);
+        let start_time = Instant::now();
+        let mut last_adjustment: Option<Instant> = None;
+
+        while start_time.elapsed() < duration && !self.should_stop() {
+            let due = last_adjustment.is_none_or(|at| at.elapsed() >= self.config.rps_window_size);
+
+            if due {
+                let desired = self.desired_vu_count(target_rps).await;
+                while self.running_vus.len() < desired {
+                    self.spawn_vu()?;
+                }
+                while self.running_vus.len() > desired {
+                    self.stop_last_vu().await;
+                }
+                last_adjustment = Some(Instant::now());
+            }
+
+            self.tick(tick_interval).await?;
+        }
+        Ok(())
+    }
+
+    fn on_tick(&self) {
+        #[cfg(feature = "alarm")]
+        {
+            tracing::trace!(active_vus = self.running_vus.len(), "tick");
+            if let Some(alarm) = &self.config.latency_alarm {
+                alarm.check();
+            }
+        }
+    }
+
+    fn fresh_metrics(config: &VirtualUserConfig) -> Metrics {
+        let metrics = Metrics::new(config.rps_window_size)
+            .with_tags(config.tags.clone())
+            .with_percentile_backend(config.percentile_backend)
+            .with_latency_resolution(config.latency_resolution)
+            .with_slowest_capacity(config.slowest_capacity);
+        let metrics = match config.expected_interval {
+            Some(interval) => metrics.with_coordinated_omission_correction(interval),
+            None => metrics,
+        };
+        let metrics = match config.raw_sample_capacity {
+            Some(capacity) => metrics.with_raw_samples(capacity),
+            None => metrics,
+        };
+        if config.latency_timestamps {
+            metrics.with_latency_timestamps()
+        } else {
+            metrics
+        }
+    }
+
+    // Latency `(sum, count)` over the whole run so far.
+    async fn latency_totals(&self) -> (f64, usize) {
+        let overall = &self.overall_metrics.http_request_time;
+        let mut totals = (overall.sum, overall.count);
+        for vu in &self.running_vus {
+            let metrics = vu.metrics();
+            let m = metrics.lock().await;
+            totals.0 += m.http_request_time.sum;
+            totals.1 += m.http_request_time.count;
+        }
+        totals
+    }
+
+    async fn desired_vu_count(&mut self, target_rps: f64) -> usize {
+        let current_count = self.running_vus.len();
+        if target_rps <= 0.0 {
+            return 0;
+        }
+        if current_count == 0 {
+            return 1;
+        }
+
+        let mut complete_rps = Vec::new();
+        for vu in &self.running_vus {
+            let metrics = vu.metrics();
+            let m = metrics.lock().await;
+            if let Ok(Some(rps)) = m.rps_summary.get_previous_rps() {
+                complete_rps.push(rps);
+            }
+        }
+
+        if complete_rps.is_empty() {
+            return current_count;
+        }
+
+        let per_vu_rps = complete_rps.iter().sum::<f64>() / complete_rps.len() as f64;
+        let measured_rps = per_vu_rps * current_count as f64;
+        self.measured_rps = Some(measured_rps);
+
+        let desired = if per_vu_rps > 0.0 {
+            (target_rps / per_vu_rps).round() as usize
+        } else {
+            current_count * 2
+        };
+
+        desired.clamp(1, self.config.max_vus.max(1))
+    }
+
+    fn merge_metrics(dest: &mut Metrics, src: &Metrics) {
+        let _ = dest.rps_summary.merge(&src.rps_summary);
+        Self::merge_summary(&mut dest.total_latency, &src.total_latency);
+        if let (Some(dest_extremes), Some(src_extremes)) = (
+            dest.latency_extremes.as_mut(),
+            src.latency_extremes.as_ref(),
+        ) {
+            dest_extremes.merge(src_extremes);
+        }
+        if let (Some(dest_raw), Some(src_raw)) =
+            (dest.raw_samples.as_mut(), src.raw_samples.as_ref())
+        {
+            dest_raw.merge(src_raw);
+        }
+        dest.latency_histogram.merge(&src.latency_histogram);
+        if let (Some(dest_digest), Some(src_digest)) =
+            (dest.latency_digest.as_mut(), src.latency_digest.as_ref())
+        {
+            dest_digest.merge(src_digest);
+        }
+        if let (Some(dest_histogram), Some(src_histogram)) = (
+            dest.corrected_latency_histogram.as_mut(),
+            src.corrected_latency_histogram.as_ref(),
+        ) {
+            dest_histogram.merge(src_histogram);
+        }
+        Self::merge_summary(&mut dest.tcp_connect_time, &src.tcp_connect_time);
+        Self::merge_summary(&mut dest.tls_handshake_time, &src.tls_handshake_time);
+        Self::merge_summary(&mut dest.http_request_time, &src.http_request_time);
+        Self::merge_summary(&mut dest.ttfb, &src.ttfb);
+        Self::merge_summary(&mut dest.injected_delay, &src.injected_delay);
+        Self::merge_summary(&mut dest.queue_wait, &src.queue_wait);
+        let (dest_phases, src_phases) = (&mut dest.latency_breakdown, &src.latency_breakdown);
+        Self::merge_summary(&mut dest_phases.dns, &src_phases.dns);
+        Self::merge_summary(&mut dest_phases.connect, &src_phases.connect);
+        Self::merge_summary(&mut dest_phases.tls, &src_phases.tls);
+        Self::merge_summary(&mut dest_phases.ttfb, &src_phases.ttfb);
+        Self::merge_summary(&mut dest_phases.transfer, &src_phases.transfer);
+        dest.counters.add(&src.counters.snapshot());
+        Self::merge_summary(&mut dest.error_rates_per_sec, &src.error_rates_per_sec);
+        for (code, count) in &src.status_code_counts {
+            *dest.status_code_counts.entry(*code).or_insert(0) += count;
+        }
+        for (addr, count) in &src.remote_addr_counts {
+            *dest.remote_addr_counts.entry(*addr).or_insert(0) += count;
+        }
+        for (class, count) in &src.error_classes {
+            *dest.error_classes.entry(*class).or_insert(0) += count;
+        }
+        dest.successful_responses += src.successful_responses;
+        dest.connect_failures += src.connect_failures;
+        dest.request_failures += src.request_failures;
+        dest.connection_close_count += src.connection_close_count;
+        dest.connection_recycles += src.connection_recycles;
+        dest.expect_continue_rejected += src.expect_continue_rejected;
+        dest.upload_bytes += src.upload_bytes;
+        dest.uncompressed_upload_bytes += src.uncompressed_upload_bytes;
+        dest.download_bytes += src.download_bytes;
+        Self::merge_summary(&mut dest.response_size, &src.response_size);
+        for (tag, summary) in &src.per_tag {
+            Self::merge_summary(dest.per_tag.entry(tag.clone()).or_default(), summary);
+        }
+        dest.not_modified += src.not_modified;
+        dest.repeated_responses += src.repeated_responses;
+        dest.negotiation_failures += src.negotiation_failures;
+        dest.connection_probes += src.connection_probes;
+        dest.failed_connection_probes += src.failed_connection_probes;
+        dest.slowest_requests.merge(&src.slowest_requests);
+        dest.other_errors.extend(src.other_errors.iter().cloned());
+    }
+
+    fn merge_summary(dest: &mut Summary, src: &Summary) {
         dest.min = dest.min.min(src.min);
         dest.max = dest.max.max(src.max);
         dest.sum += src.sum;
         dest.count += src.count;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::circuit_breaker::BreakerState;
+    use wiremock::matchers::{body_string, header, method, path};
+    use wiremock::{Mock, MockServer, Respond, ResponseTemplate};
+
+    struct ArrivalRecorder(Arc<std::sync::Mutex<Vec<Instant>>>);
+
+    impl Respond for ArrivalRecorder {
+        fn respond(&self, _: &wiremock::Request) -> ResponseTemplate {
+            self.0.lock().unwrap().push(Instant::now());
+            ResponseTemplate::new(200).set_delay(Duration::from_millis(20))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_probes_once() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri());
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_secs(10), 50);
+        manager.add_plan(Duration::from_secs(20), 120);
+        manager.add_plan(Duration::from_secs(10), 0);
+
+        let report = manager.dry_run().await;
+        assert_eq!(report.status, Some(200));
+        assert!(report.error.is_none());
+        assert_eq!(report.peak_vus, 120);
+        assert_eq!(report.total_duration, Duration::from_secs(40));
+        assert!(report.estimated_requests.is_some());
+        assert_eq!(manager.get_overall_metrics().http_request_time.count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_uses_the_run_client_settings() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let addr = *mock_server.address();
+        let config = VirtualUserConfig::new(&format!("http://rperf.test:{}/", addr.port()))
+            .resolve("rperf.test", addr);
+        let report = VirtualUserManager::new(config).dry_run().await;
+        assert_eq!(report.status, Some(200), "{:?}", report.error);
+
+        let config = VirtualUserConfig::new(&mock_server.uri()).proxy("http://[::1");
+        let report = VirtualUserManager::new(config).dry_run().await;
+        assert_eq!(report.status, None);
+        let error = report.error.unwrap();
+        assert!(error.starts_with("failed to build HTTP client"), "{error}");
+        assert!(report.estimated_requests.is_none());
+    }
+
+    async fn start_tls_close_server(require_client_cert: bool) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+        use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+        use tokio_rustls::rustls::server::WebPkiClientVerifier;
+        use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+        use tokio_rustls::TlsAcceptor;
+
+        let cert_pem = include_bytes!("../../tests/fixtures/localhost.pem");
+        let key_pem = include_bytes!("../../tests/fixtures/localhost.key");
+        let certs: Vec<CertificateDer> = rustls_pemfile::certs(&mut cert_pem.as_slice())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let key: PrivateKeyDer = rustls_pemfile::private_key(&mut key_pem.as_slice())
+            .unwrap()
+            .unwrap();
+        let server_config = ServerConfig::builder();
+        let server_config = if require_client_cert {
+            let mut roots = RootCertStore::empty();
+            for cert in
+                rustls_pemfile::certs(&mut &include_bytes!("../../tests/fixtures/ca.pem")[..])
+            {
+                roots.add(cert.unwrap()).unwrap();
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                .build()
+                .unwrap();
+            server_config.with_client_cert_verifier(verifier)
+        } else {
+            server_config.with_no_client_auth()
+        }
+        .with_single_cert(certs, key)
+        .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(server_config));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(mut stream) = acceptor.accept(stream).await else {
+                        return;
+                    };
+                    let mut buf = [0u8; 1024];
+                    if stream.read(&mut buf).await.unwrap_or(0) == 0 {
+                        return;
+                    }
+                    let response =
+                        "HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok";
+                    let _ = stream.write_all(response.as_bytes()).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+
+        format!("https://localhost:{}", port)
+    }
+
+    #[tokio::test]
+    async fn test_latency_breakdown_sums_to_total() {
+        let url = start_tls_close_server(false).await;
+        let config = VirtualUserConfig::new(&url)
+            .add_root_certificate(include_bytes!("../../tests/fixtures/ca.pem"))
+            .latency_breakdown(true);
+        let mut manager = VirtualUserManager::new(config);
+        manager
+            .run_constant(2, Duration::from_millis(400))
+            .await
+            .unwrap();
+
+        let metrics = manager.get_overall_metrics();
+        let phases = &metrics.latency_breakdown;
+        assert!(metrics.total_requests() > 0);
+        assert_eq!(phases.ttfb.count(), metrics.total_requests());
+        // The server closes every connection, so each request resolves and connects afresh.
+        assert!(phases.dns.min().unwrap() > 0.0);
+        assert!(phases.connect.min().unwrap() > 0.0);
+        let average = |summary: &Summary| summary.average().unwrap();
+        let sum = average(&phases.dns)
+            + average(&phases.connect)
+            + average(&phases.tls)
+            + average(&phases.ttfb)
+            + average(&phases.transfer);
+        let total = metrics.total_latency.average().unwrap();
+        assert!(sum <= total, "phases {sum} > total {total}");
+        assert!(
+            total - sum < 0.001 + total * 0.1,
+            "phases {sum}, total {total}"
+        );
+    }
+
+    #[cfg(feature = "rustls")]
+    #[tokio::test]
+    async fn test_tls_sessions_are_resumed() {
+        let url = start_tls_close_server(false).await;
+        let config = VirtualUserConfig::new(&url)
+            .tls_session_tracking(true)
+            .add_root_certificate(include_bytes!("../../tests/fixtures/ca.pem"));
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(200), 1);
+        manager.add_plan(Duration::from_millis(500), 1);
+        manager.run().await.unwrap();
+
+        let metrics = manager.get_overall_metrics();
+        let ok_count = metrics.status_code_counts.get(&200).copied().unwrap_or(0);
+        assert!(ok_count > 1);
+        assert_eq!(metrics.total_errors(), 0);
+        assert!(metrics.tls_full_handshakes >= 1);
+        assert!(metrics.tls_resumed_handshakes >= ok_count - 1);
+    }
+
+    async fn run_against_mtls(identity: Option<ClientIdentity>) -> VirtualUserManager {
+        run_against_mtls_with(identity, VirtualUserConfig::new).await
+    }
+
+    async fn run_against_mtls_with(
+        identity: Option<ClientIdentity>,
+        config: impl FnOnce(&str) -> VirtualUserConfig,
+    ) -> VirtualUserManager {
+        let url = start_tls_close_server(true).await;
+        let mut config =
+            config(&url).add_root_certificate(include_bytes!("../../tests/fixtures/ca.pem"));
+        if let Some(identity) = identity {
+            config = config.client_identity(identity);
+        }
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 1);
+        manager.add_plan(Duration::from_millis(200), 1);
+        manager.run().await.unwrap();
+        manager
+    }
+
+    #[tokio::test]
+    async fn test_mtls_requires_client_identity() {
+        let identity = ClientIdentity::Pem {
+            cert: include_bytes!("../../tests/fixtures/client.pem").to_vec(),
+            key: include_bytes!("../../tests/fixtures/client.key").to_vec(),
+        };
+        let manager = run_against_mtls(Some(identity)).await;
+        let metrics = manager.get_overall_metrics();
+        assert!(metrics.status_code_counts.get(&200).copied().unwrap_or(0) > 0);
+        assert_eq!(metrics.total_errors(), 0);
+
+        let manager = run_against_mtls(None).await;
+        let metrics = manager.get_overall_metrics();
+        assert_eq!(metrics.status_code_counts.get(&200), None);
+        assert!(metrics.total_errors() > 0);
+    }
+
+    #[cfg(feature = "rustls")]
+    #[tokio::test]
+    async fn test_session_tracking_presents_client_identity() {
+        let identity = ClientIdentity::Pem {
+            cert: include_bytes!("../../tests/fixtures/client.pem").to_vec(),
+            key: include_bytes!("../../tests/fixtures/client.key").to_vec(),
+        };
+        let manager = run_against_mtls_with(Some(identity), |url| {
+            VirtualUserConfig::new(url).tls_session_tracking(true)
+        })
+        .await;
+        let metrics = manager.get_overall_metrics();
+        assert!(metrics.status_code_counts.get(&200).copied().unwrap_or(0) > 0);
+        assert_eq!(metrics.total_errors(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_client_identity_fails_at_build_time() {
+        let config =
+            VirtualUserConfig::new("https://localhost:1/").client_identity(ClientIdentity::Pem {
+                cert: b"not a certificate".to_vec(),
+                key: b"not a key".to_vec(),
+            });
+        assert!(config.build_client().is_err());
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 1);
+        assert!(matches!(manager.run().await, Err(RunError::Client(_))));
+    }
+
+    #[cfg(feature = "http3")]
+    #[test]
+    fn test_pkcs12_identity_is_rejected_with_http3() {
+        let config = VirtualUserConfig::new("https://localhost:1/").client_identity(
+            ClientIdentity::Pkcs12 {
+                der: Vec::new(),
+                password: String::new(),
+            },
+        );
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::Pkcs12NeedsNativeTls)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_http_version_is_pinned() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri()).http_version(HttpVersion::Http2);
+        assert!(!config.uses_default_client());
+        let resp = config
+            .build_client()
+            .unwrap()
+            .get(&config.url)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.version(), reqwest::Version::HTTP_2);
+
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 1);
+        manager.add_plan(Duration::from_millis(100), 1);
+        manager.run().await.unwrap();
+        assert!(manager.get_overall_metrics().status_code_counts[&200] > 0);
+    }
+
+    // Needs outbound UDP to a public HTTP/3 endpoint, so it only runs on request:
+    // cargo test --features http3 -- --ignored http3
+    #[cfg(feature = "http3")]
+    #[tokio::test]
+    #[ignore]
+    async fn test_http3_requests_complete_over_quic() {
+        let config =
+            VirtualUserConfig::new("https://cloudflare-quic.com/").http_version(HttpVersion::Http3);
+        let resp = config
+            .build_client()
+            .unwrap()
+            .get(&config.url)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.version(), reqwest::Version::HTTP_3);
+
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 1);
+        manager.add_plan(Duration::from_millis(500), 1);
+        manager.run().await.unwrap();
+        assert_eq!(manager.get_overall_metrics().total_errors(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_config_errors_are_returned_before_spawning() {
+        let config = VirtualUserConfig::new("http://127.0.0.1:1/")
+            .rps_window_size(Duration::from_millis(500));
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 1);
+        let err = manager.run().await.unwrap_err();
+        assert!(
+            matches!(err, RunError::Config(ConfigError::WindowTooSmall(_))),
+            "{err:?}"
+        );
+        assert_eq!(manager.spawned_vus, 0);
+
+        let config = VirtualUserConfig::new("not a url").header("bad header", "x");
+        let err = VirtualUserManager::new(config).run_rps().await.unwrap_err();
+        assert!(
+            matches!(err, RunError::Config(ConfigError::InvalidUrl { .. })),
+            "{err:?}"
+        );
+        let config = VirtualUserConfig::new("http://127.0.0.1:1/").header("bad header", "x");
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::InvalidHeader(_))
+        ));
+        let config = VirtualUserConfig::new("http://127.0.0.1:1/").tag("le", "x");
+        assert!(matches!(config.validate(), Err(ConfigError::InvalidTag(_))));
+
+        let config = VirtualUserConfig::new("http://127.0.0.1:1/")
+            .form(vec![("name".to_string(), "value".to_string())])
+            .gzip_body(true);
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 1);
+        let err = manager.run().await.unwrap_err();
+        assert!(
+            matches!(err, RunError::Config(ConfigError::GzipNeedsBytesBody)),
+            "{err:?}"
+        );
+        assert_eq!(manager.spawned_vus, 0);
+
+        let config = VirtualUserConfig::new("http://127.0.0.1:1/").max_in_flight(0);
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 1);
+        let err = manager.run().await.unwrap_err();
+        assert!(
+            matches!(err, RunError::Config(ConfigError::ZeroInFlightLimit)),
+            "{err:?}"
+        );
+        assert_eq!(manager.spawned_vus, 0);
+    }
+
+    #[test]
+    fn test_interpolate_env_resolves_config_strings() {
+        std::env::set_var("RPERF_TEST_HOST", "127.0.0.1:8080");
+        std::env::set_var("RPERF_TEST_TOKEN", "secret");
+        let config = VirtualUserConfig::new("http://${RPERF_TEST_HOST}/api")
+            .header("authorization", "Bearer ${RPERF_TEST_TOKEN}")
+            .body(r#"{"token": "${RPERF_TEST_TOKEN}"}"#)
+            .endpoint(
+                RequestSpec::new("http://${RPERF_TEST_HOST}").path("/items/${RPERF_TEST_TOKEN}"),
+                1,
+            )
+            .interpolate_env()
+            .unwrap();
+
+        assert_eq!(config.url, "http://127.0.0.1:8080/api");
+        assert_eq!(config.headers[0].1, "Bearer secret");
+        assert!(
+            matches!(&config.body, RequestBody::Bytes(body) if body == r#"{"token": "secret"}"#)
+        );
+        assert_eq!(
+            config.resolved_endpoints()[0].0.url,
+            "http://127.0.0.1:8080/items/secret"
+        );
+        assert!(config.validate().is_ok());
+
+        let config = VirtualUserConfig::new("http://127.0.0.1:1/")
+            .step(Step::new(
+                RequestSpec::new("http://${RPERF_TEST_HOST}/login")
+                    .header("x-token", "${RPERF_TEST_TOKEN}"),
+            ))
+            .proxy("http://${RPERF_TEST_HOST}")
+            .interpolate_env()
+            .unwrap();
+        let step = &config.resolved_steps()[0].request;
+        assert_eq!(step.url, "http://127.0.0.1:8080/login");
+        assert_eq!(step.headers[0].1, "secret");
+        assert_eq!(config.proxy.as_deref(), Some("http://127.0.0.1:8080"));
+
+        let err = VirtualUserConfig::new("http://127.0.0.1:1/")
+            .header("x-api-key", "${RPERF_TEST_UNSET}")
+            .interpolate_env()
+            .unwrap_err();
+        assert!(
+            matches!(&err, ConfigError::MissingEnvVar(name) if name == "RPERF_TEST_UNSET"),
+            "{err:?}"
+        );
+    }
+
+    #[derive(Debug)]
+    struct PanickingHook;
+
+    impl RequestHook for PanickingHook {
+        fn before_send(&self, _: &mut reqwest::Request) {
+            panic!("hook failed");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_vu_panic_is_reported_as_join_error() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri()).request_hook(PanickingHook);
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 1);
+        manager.add_plan(Duration::from_millis(100), 1);
+        let err = manager.run().await.unwrap_err();
+        assert!(matches!(err, RunError::Join(_)), "{err:?}");
+        assert!(manager.running_vus.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_proxy_is_reported_as_error() {
+        let config = VirtualUserConfig::new("http://127.0.0.1:1/").proxy("http://[::1");
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 1);
+
+        let err = manager.run().await.unwrap_err();
+        assert!(matches!(err, RunError::Client(_)), "{err:?}");
+        assert!(
+            err.to_string().starts_with("failed to build HTTP client:"),
+            "{err}"
+        );
+        assert!(manager.running_vus.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_proxy_setting_takes_effect() {
+        let proxy_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("host", "rperf.invalid"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&proxy_server)
+            .await;
+
+        for shared in [false, true] {
+            let config = VirtualUserConfig::new("http://rperf.invalid/")
+                .proxy(&proxy_server.uri())
+                .shared_client(shared);
+            assert!(!config.uses_default_client());
+
+            let mut manager = VirtualUserManager::new(config);
+            manager.add_plan(Duration::from_millis(200), 2);
+            manager.run().await.unwrap();
+
+            let metrics = manager.get_overall_metrics();
+            assert!(metrics.status_code_counts.get(&200).copied().unwrap_or(0) > 0);
+            assert_eq!(metrics.total_errors(), 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_output_file_is_written() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let dir = std::env::temp_dir().join(format!("rperf-output-{}", std::process::id()));
+        let path = dir.join("nested").join("summary.json");
+        let config = VirtualUserConfig::new(&mock_server.uri()).output_file(&path);
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 1);
+        manager.add_plan(Duration::from_millis(200), 1);
+        manager.run().await.unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let summary: serde_json::Value = serde_json::from_str(&contents).unwrap();
+        assert!(summary["requests"].as_u64().unwrap() > 0);
+        assert_eq!(
+            summary["requests"],
+            manager.get_overall_metrics().total_requests()
+        );
+
+        let blocked = VirtualUserConfig::new(&mock_server.uri()).output_file(path.join("x.json"));
+        let mut manager = VirtualUserManager::new(blocked);
+        assert!(matches!(manager.run().await, Err(RunError::Output { .. })));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_sends_logged_requests() {
+        let mock_server = MockServer::start().await;
+        Mock::given(wiremock::matchers::any())
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let uri = mock_server.uri();
+        let log = [
+            format!(r#"{{"offset_ms": 0, "url": "{uri}/a"}}"#),
+            "{\"offset_ms\": oops}".to_string(),
+            format!(
+                concat!(
+                    r#"{{"offset_ms": 150, "method": "POST", "url": "{}/b", "#,
+                    r#""headers": {{"x-replay": "1"}}, "body": "payload"}}"#
+                ),
+                uri
+            ),
+            format!(r#"{{"offset_ms": 300, "method": "DELETE", "url": "{uri}/c"}}"#),
+        ];
+        let path = std::env::temp_dir().join(format!("rperf-replay-{}.jsonl", std::process::id()));
+        fs::write(&path, log.join("\n")).unwrap();
+
+        let mut manager = VirtualUserManager::new(VirtualUserConfig::new(&uri));
+        let result = manager.replay(&path, ReplayPacing::Original).await.unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.skipped_log_lines, Some(1));
+        let metrics = manager.get_overall_metrics();
+        assert_eq!(metrics.total_requests(), 3);
+        assert!(metrics.run_duration.unwrap() >= Duration::from_millis(300));
+
+        let received = mock_server.received_requests().await.unwrap();
+        let sent: Vec<_> = received
+            .iter()
+            .map(|request| (request.method.to_string(), request.url.path().to_string()))
+            .collect();
+        assert_eq!(
+            sent,
+            vec![
+                ("GET".to_string(), "/a".to_string()),
+                ("POST".to_string(), "/b".to_string()),
+                ("DELETE".to_string(), "/c".to_string()),
+            ]
+        );
+        assert_eq!(received[1].headers["x-replay"], "1");
+        assert_eq!(received[1].body, b"payload");
+
+        let missing = manager
+            .replay(
+                path.with_extension("missing"),
+                ReplayPacing::AsFastAsPossible,
+            )
+            .await;
+        assert!(matches!(missing, Err(RunError::ReplayLog { .. })));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_snapshot_under_many_vus_is_fast() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri());
+        let mut manager = VirtualUserManager::new(config);
+        manager.prepare_client().unwrap();
+        for _ in 0..500 {
+            manager.spawn_vu().unwrap();
+        }
+        sleep(Duration::from_millis(300)).await;
+
+        let snapshot_start = Instant::now();
+        let snapshot = manager.snapshot();
+        let snapshot_time = snapshot_start.elapsed();
+
+        assert_eq!(snapshot.active_vus, 500);
+        assert!(snapshot.requests > 0);
+        assert!(
+            snapshot_time < Duration::from_millis(5),
+            "snapshot took {snapshot_time:?}"
+        );
+
+        while manager.stop_last_vu().await {}
+        assert!(manager.snapshot().requests >= snapshot.requests);
+        assert_eq!(manager.snapshot().active_vus, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_final_ramp_down_is_gradual() {
+        let arrivals = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ArrivalRecorder(arrivals.clone()))
+            .mount(&mock_server)
+            .await;
+
+        let config =
+            VirtualUserConfig::new(&mock_server.uri()).final_ramp_down(Duration::from_secs(1));
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(100), 20);
+        manager.add_plan(Duration::from_millis(300), 20);
+        let run_start = Instant::now();
+        manager.run().await.unwrap();
+
+        let timings = manager.segment_timings();
+        let plan_end = timings[1].started_at + timings[1].elapsed;
+        let mut quarters = [0usize; 4];
+        for arrival in arrivals.lock().unwrap().iter() {
+            let offset = arrival.duration_since(run_start);
+            if offset >= plan_end {
+                let quarter = ((offset - plan_end).as_millis() / 250) as usize;
+                quarters[quarter.min(3)] += 1;
+            }
+        }
+
+        assert!(quarters[0] > quarters[3], "quarters {quarters:?}");
+        assert!(quarters[1] > 0 && quarters[2] > 0, "quarters {quarters:?}");
+    }
+
+    #[tokio::test]
+    async fn test_retry_budget_caps_retries_fleet_wide() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .retries(3)
+            .retry_budget(5, 0.0);
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 4);
+        manager.add_plan(Duration::from_millis(300), 4);
+        manager.run().await.unwrap();
+
+        let metrics = manager.get_overall_metrics();
+        assert_eq!(metrics.retries(), 5);
+        assert!(metrics.retries_dropped_by_budget() > 0);
+        assert!(metrics.status_code_counts[&503] > 5);
+    }
+
+    #[tokio::test]
+    async fn test_only_idempotent_requests_are_retried() {
+        let mock_server = MockServer::start().await;
+        Mock::given(wiremock::matchers::any())
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&mock_server)
+            .await;
+
+        let mut retried = Vec::new();
+        for (method, override_) in [
+            (Method::GET, false),
+            (Method::POST, false),
+            (Method::POST, true),
+        ] {
+            let config = VirtualUserConfig::new(&mock_server.uri())
+                .method(method)
+                .retries(2)
+                .retry_non_idempotent(override_);
+            let mut manager = VirtualUserManager::new(config);
+            manager
+                .run_constant(1, Duration::from_millis(200))
+                .await
+                .unwrap();
+            let metrics = manager.get_overall_metrics();
+            assert!(metrics.total_requests() > 0);
+            retried.push((metrics.retries(), metrics.retries_skipped_non_idempotent()));
+        }
+
+        let [(get_retries, get_skipped), (post_retries, post_skipped), (forced_retries, _)] =
+            retried[..]
+        else {
+            unreachable!()
+        };
+        assert!(get_retries > 0 && get_skipped == 0);
+        assert!(post_retries == 0 && post_skipped > 0);
+        assert!(forced_retries > 0);
+    }
+
+    struct FailUntil(Instant);
+
+    impl Respond for FailUntil {
+        fn respond(&self, _: &wiremock::Request) -> ResponseTemplate {
+            if Instant::now() < self.0 {
+                ResponseTemplate::new(503)
+            } else {
+                ResponseTemplate::new(200)
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_opens_and_recovers() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(FailUntil(Instant::now() + Duration::from_millis(300)))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri()).circuit_breaker(
+            Duration::from_secs(1),
+            0.5,
+            5,
+            Duration::from_millis(100),
+        );
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 2);
+        manager.add_plan(Duration::from_millis(700), 2);
+        manager.run().await.unwrap();
+
+        let transitions = manager.circuit_breaker_transitions();
+        let states: Vec<_> = transitions.iter().map(|t| t.to).collect();
+        assert_eq!(states.first(), Some(&BreakerState::Open), "{states:?}");
+        assert!(states.contains(&BreakerState::HalfOpen), "{states:?}");
+        assert_eq!(states.last(), Some(&BreakerState::Closed), "{states:?}");
+
+        // While open, VUs stay idle instead of hammering the failing backend.
+        let metrics = manager.get_overall_metrics();
+        assert!(
+            metrics.status_code_counts[&503] < 50,
+            "{:?}",
+            metrics.status_code_counts
+        );
+        assert!(metrics.status_code_counts[&200] > 0);
+    }
+
+    #[tokio::test]
+    async fn test_response_size_summary_covers_mixed_bodies() {
+        let mock_server = MockServer::start().await;
+        for (name, size) in [("/small", 100), ("/large", 10_000)] {
+            Mock::given(path(name))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0u8; size]))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let endpoint = |name: &str| RequestSpec::new(&format!("{}{}", mock_server.uri(), name));
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .endpoint(endpoint("/small"), 1)
+            .endpoint(endpoint("/large"), 1)
+            .seed(1);
+        let mut manager = VirtualUserManager::new(config);
+        manager
+            .run_constant(2, Duration::from_millis(200))
+            .await
+            .unwrap();
+
+        let metrics = manager.get_overall_metrics();
+        let sizes = &metrics.response_size;
+        assert_eq!(sizes.count, metrics.total_requests());
+        assert_eq!(sizes.min(), Some(100.0));
+        assert_eq!(sizes.max(), Some(10_000.0));
+        let average = metrics.download_bytes as f64 / metrics.total_requests() as f64;
+        assert!((sizes.average().unwrap() - average).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_tagged_requests_roll_up_per_tag() {
+        let mock_server = MockServer::start().await;
+        for name in ["/write/a", "/write/b"] {
+            Mock::given(path(name))
+                .respond_with(ResponseTemplate::new(201).set_delay(Duration::from_millis(40)))
+                .mount(&mock_server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let endpoint = |name: &str, tag: &str| {
+            RequestSpec::new(&format!("{}{}", mock_server.uri(), name)).tag(tag)
+        };
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .endpoint(endpoint("/read/a", "read"), 1)
+            .endpoint(endpoint("/read/b", "read"), 1)
+            .endpoint(endpoint("/read/c", "read"), 1)
+            .endpoint(endpoint("/write/a", "write"), 1)
+            .endpoint(endpoint("/write/b", "write"), 1);
+        let mut manager = VirtualUserManager::new(config);
+        manager
+            .run_constant(2, Duration::from_millis(400))
+            .await
+            .unwrap();
+
+        let metrics = manager.get_overall_metrics();
+        assert_eq!(metrics.per_tag.len(), 2);
+        let (read, write) = (&metrics.per_tag["read"], &metrics.per_tag["write"]);
+        assert!(read.count > 0 && write.count > 0);
+        assert_eq!(read.count + write.count, metrics.total_requests());
+        assert!(read.max().unwrap() < 0.04, "{read:?}");
+        assert!(write.min().unwrap() >= 0.04, "{write:?}");
+        assert_eq!(write.count, metrics.status_code_counts[&201]);
+    }
+
+    async fn weighted_selection_sequence(seed: u64) -> Vec<String> {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let endpoint = |name: &str| RequestSpec::new(&format!("{}/{}", mock_server.uri(), name));
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .endpoint(endpoint("a"), 6)
+            .endpoint(endpoint("b"), 3)
+            .endpoint(endpoint("c"), 1)
+            .seed(seed);
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 1);
+        manager.add_plan(Duration::from_millis(200), 1);
+        manager.run().await.unwrap();
+
+        mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|request| request.url.path().to_string())
+            .filter(|path| path != "/")
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_seed_makes_weighted_selection_reproducible() {
+        let first = weighted_selection_sequence(42).await;
+        let second = weighted_selection_sequence(42).await;
+        let other = weighted_selection_sequence(7).await;
+
+        let len = first.len().min(second.len()).min(other.len());
+        assert!(len >= 20, "only {len} requests");
+        assert_eq!(first[..len], second[..len]);
+        assert_ne!(first[..len], other[..len]);
+    }
+
+    #[tokio::test]
+    async fn test_ramp_down_and_teardown_use_their_own_grace() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .graceful_shutdown(Duration::from_secs(3))
+            .final_graceful_shutdown(Duration::from_millis(600));
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 2);
+        manager.add_segment(
+            PlanSegment::new(Duration::from_millis(100), 1)
+                .graceful_shutdown(Duration::from_millis(100)),
+        );
+        manager.run().await.unwrap();
+
+        let ramp_down = &manager.segment_timings()[1];
+        assert!(ramp_down.elapsed >= Duration::from_millis(200));
+        assert!(ramp_down.elapsed < Duration::from_millis(600));
+
+        let plan_end = ramp_down.started_at + ramp_down.elapsed;
+        let teardown = manager.get_overall_metrics().run_duration.unwrap() - plan_end;
+        assert!(teardown >= Duration::from_millis(600));
+        assert!(teardown < Duration::from_secs(3));
+    }
+
+    #[tokio::test]
+    async fn test_vu_lifecycle_callbacks() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let started = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let stopped = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let config = VirtualUserConfig::new(&mock_server.uri());
+        let mut manager = VirtualUserManager::new(config);
+        let events = started.clone();
+        manager.on_vu_start(move |id| events.lock().unwrap().push(id));
+        let events = stopped.clone();
+        manager.on_vu_stop(move |id, metrics| {
+            events.lock().unwrap().push((id, metrics.total_requests()));
+        });
+        manager.add_plan(Duration::from_millis(200), 5);
+        manager.add_plan(Duration::from_millis(200), 2);
+        manager.add_plan(Duration::from_millis(100), 4);
+        manager.run().await.unwrap();
+
+        let started = started.lock().unwrap();
+        let stopped = stopped.lock().unwrap();
+        assert_eq!(*started, (0..7).collect::<Vec<u64>>());
+        assert_eq!(stopped.len(), 7);
+        assert_eq!(
+            stopped[..3].iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![4, 3, 2]
+        );
+        let requests: usize = stopped.iter().map(|(_, requests)| requests).sum();
+        assert_eq!(requests, manager.get_overall_metrics().total_requests());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_steep_ramp_reports_fidelity() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(5)))
+            .mount(&mock_server)
+            .await;
+
+        let mut manager = VirtualUserManager::new(VirtualUserConfig::new(&mock_server.uri()));
+        manager.add_plan(Duration::from_secs(2), 20);
+        manager.add_plan(Duration::from_secs(1), 20);
+        let result = manager.run().await.unwrap();
+
+        let fidelity = result.ramp_fidelity.unwrap();
+        assert!(fidelity.windows.len() >= 2, "{fidelity:?}");
+        assert!(fidelity.max_overshoot.is_finite() && fidelity.max_overshoot >= 0.0);
+        assert!(fidelity.max_undershoot.is_finite() && fidelity.max_undershoot >= 0.0);
+        assert!(fidelity
+            .windows
+            .iter()
+            .all(|window| window.achieved_rps > 0.0));
+    }
+
+    #[tokio::test]
+    async fn test_vu_series_tracks_plan_targets() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(5)))
+            .mount(&mock_server)
+            .await;
+
+        let mut manager = VirtualUserManager::new(VirtualUserConfig::new(&mock_server.uri()));
+        manager.add_plan(Duration::from_secs(1), 5);
+        manager.add_plan(Duration::from_millis(500), 5);
+        let result = manager.run().await.unwrap();
+
+        // One sample per 100ms tick across the 1.5s of plans.
+        let series = &result.vu_series;
+        assert!((13..=16).contains(&series.len()), "{series:?}");
+        assert!(series.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        for &(elapsed, active) in series {
+            let ideal = (elapsed.as_secs_f64() * 5.0).min(5.0);
+            assert!(
+                (active as f64 - ideal).abs() <= 1.5,
+                "{elapsed:?}: {active}"
+            );
+        }
+        assert_eq!(series.last().unwrap().1, 5);
+    }
+
+    // Each request takes `per_request` longer for every other request in flight.
+    struct LoadDependentLatency {
+        per_request: Duration,
+        in_flight_until: std::sync::Mutex<Vec<Instant>>,
+    }
+
+    impl Respond for LoadDependentLatency {
+        fn respond(&self, _: &wiremock::Request) -> ResponseTemplate {
+            let now = Instant::now();
+            let mut in_flight = self.in_flight_until.lock().unwrap();
+            in_flight.retain(|until| *until > now);
+            let delay = self.per_request * (in_flight.len() as u32 + 1);
+            in_flight.push(now + delay);
+            ResponseTemplate::new(200).set_delay(delay)
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_feedback_ramp_decelerates_as_latency_rises() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(LoadDependentLatency {
+                per_request: Duration::from_millis(5),
+                in_flight_until: std::sync::Mutex::new(Vec::new()),
+            })
+            .mount(&mock_server)
+            .await;
+
+        let mut runs = Vec::new();
+        for feedback in [
+            None,
+            Some(FeedbackRamp::new(0.5).window(Duration::from_millis(200))),
+        ] {
+            let mut config = VirtualUserConfig::new(&mock_server.uri());
+            if let Some(feedback) = feedback {
+                config = config.feedback_ramp(feedback.min_pace(0.25));
+            }
+            let mut manager = VirtualUserManager::new(config);
+            manager.add_plan(Duration::from_secs(1), 12);
+            let result = manager.run().await.unwrap();
+            let at_one_second = result
+                .vu_series
+                .iter()
+                .take_while(|(elapsed, _)| *elapsed <= Duration::from_secs(1))
+                .last()
+                .unwrap()
+                .1;
+            runs.push((manager.segment_timings()[0].elapsed, at_one_second));
+        }
+
+        let [(linear_elapsed, linear_vus), (feedback_elapsed, feedback_vus)] = runs[..] else {
+            unreachable!()
+        };
+        assert!(
+            linear_elapsed < Duration::from_millis(1200),
+            "{linear_elapsed:?}"
+        );
+        assert!(linear_vus >= 10, "{linear_vus}");
+        assert!(
+            feedback_elapsed > Duration::from_millis(1300),
+            "{feedback_elapsed:?}"
+        );
+        assert!(
+            feedback_vus + 2 <= linear_vus,
+            "{feedback_vus} vs {linear_vus}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_constant_starts_all_vus_immediately() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let started = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut manager = VirtualUserManager::new(VirtualUserConfig::new(&mock_server.uri()));
+        let events = started.clone();
+        manager.on_vu_start(move |_| events.lock().unwrap().push(Instant::now()));
+        let run_start = Instant::now();
+        manager
+            .run_constant(8, Duration::from_millis(300))
+            .await
+            .unwrap();
+
+        let started = started.lock().unwrap();
+        assert_eq!(started.len(), 8);
+        // A linear ramp would spread these over the whole 300ms; one tick is 100ms.
+        assert!(started
+            .iter()
+            .all(|at| at.duration_since(run_start) < Duration::from_millis(100)));
+        assert!(run_start.elapsed() >= Duration::from_millis(300));
+        let metrics = manager.get_overall_metrics();
+        assert!(metrics.total_requests() > 0);
+        let windowed: usize = metrics.rps_summary.window_counts().iter().sum();
+        assert_eq!(windowed, metrics.total_requests());
+        assert!(manager.running_vus.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_segment_directions() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(20)))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri());
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(100), 100);
+        manager.add_plan(Duration::from_millis(100), 50);
+        manager.add_plan(Duration::from_millis(100), 50);
+        manager.run().await.unwrap();
+
+        let timings = manager.segment_timings();
+        let directions: Vec<_> = timings.iter().map(|timing| timing.direction).collect();
+        assert_eq!(
+            directions,
+            vec![
+                RampDirection::RampUp,
+                RampDirection::RampDown,
+                RampDirection::Hold
+            ]
+        );
+        assert_eq!(timings[1].target, 50);
+        assert!(timings[2].started_at >= timings[1].started_at + timings[1].elapsed);
+    }
+
+    #[tokio::test]
+    async fn test_injected_client_is_used_by_every_vu() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("x-injected", "yes"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            "x-injected",
+            reqwest::header::HeaderValue::from_static("yes"),
+        );
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()
+            .unwrap();
+        // The bogus proxy would fail every request if rperf built its own client.
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .proxy("http://127.0.0.1:1")
+            .with_client(client);
+        let mut manager = VirtualUserManager::new(config);
+        manager
+            .run_constant(3, Duration::from_millis(200))
+            .await
+            .unwrap();
+
+        let metrics = manager.get_overall_metrics();
+        assert!(metrics.total_requests() > 0);
+        assert_eq!(
+            metrics.status_code_counts.get(&200),
+            Some(&metrics.total_requests())
+        );
+        let received = mock_server.received_requests().await.unwrap();
+        assert!(received
+            .iter()
+            .all(|request| request.headers.get("x-injected").is_some()));
+    }
+
+    #[tokio::test]
+    async fn test_run_iterations_executes_exactly_n() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(5)))
+            .mount(&mock_server)
+            .await;
+
+        let started = Arc::new(std::sync::Mutex::new(0));
+        let mut manager = VirtualUserManager::new(VirtualUserConfig::new(&mock_server.uri()));
+        let workers = started.clone();
+        manager.on_vu_start(move |_| *workers.lock().unwrap() += 1);
+        let result = manager.run_iterations(50, 5).await.unwrap();
+
+        assert_eq!(result.iterations_completed, Some(50));
+        assert_eq!(*started.lock().unwrap(), 5);
+        assert_eq!(manager.get_overall_metrics().total_requests(), 50);
+        // One uncounted warm-up request per worker on top of the iterations.
+        assert_eq!(mock_server.received_requests().await.unwrap().len(), 55);
+        assert!(manager.running_vus.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_queue_wait_is_separate_from_latency() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(20)))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri()).max_in_flight(1);
+        let mut manager = VirtualUserManager::new(config);
+        manager
+            .run_constant(4, Duration::from_millis(500))
+            .await
+            .unwrap();
+
+        let metrics = manager.get_overall_metrics();
+        assert!(metrics.queue_wait.count >= metrics.total_requests());
+        // Four VUs share one slot, so each waits for roughly three requests ahead of it.
+        let queue_wait = metrics.queue_wait.average().unwrap();
+        assert!(queue_wait > 0.03, "queue wait {queue_wait}");
+        let latency = metrics.total_latency.average().unwrap();
+        assert!((0.02..0.035).contains(&latency), "latency {latency}");
+    }
+
+    #[tokio::test]
+    async fn test_effective_concurrency_follows_littles_law() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(20)))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .think_time(ThinkTime::Constant(Duration::from_millis(20)));
+        let mut manager = VirtualUserManager::new(config);
+        let result = manager
+            .run_constant(4, Duration::from_millis(600))
+            .await
+            .unwrap();
+
+        let concurrency = result.effective_concurrency.unwrap();
+        let metrics = manager.get_overall_metrics();
+        let expected = metrics.throughput().unwrap() * metrics.total_latency.average().unwrap();
+        assert!((concurrency.achieved - expected).abs() < 1e-9);
+        assert_eq!(concurrency.peak_vus, 4);
+        assert!(
+            (3.5..=4.0).contains(&concurrency.average_vus),
+            "{concurrency:?}"
+        );
+        // Half of every VU's cycle is think time, so only about half of them are in flight.
+        let utilization = concurrency.utilization().unwrap();
+        assert!((0.3..0.7).contains(&utilization), "{concurrency:?}");
+    }
+
+    #[tokio::test]
+    async fn test_idle_segment_sends_no_requests() {
+        struct Timestamps(Arc<std::sync::Mutex<Vec<Instant>>>);
+
+        impl Respond for Timestamps {
+            fn respond(&self, _: &wiremock::Request) -> ResponseTemplate {
+                self.0.lock().unwrap().push(Instant::now());
+                ResponseTemplate::new(200).set_delay(Duration::from_millis(10))
+            }
+        }
+
+        let mock_server = MockServer::start().await;
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        Mock::given(method("GET"))
+            .respond_with(Timestamps(received.clone()))
+            .mount(&mock_server)
+            .await;
+
+        let config =
+            VirtualUserConfig::new(&mock_server.uri()).graceful_shutdown(Duration::from_secs(1));
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(300), 50);
+        manager.add_segment(PlanSegment::idle(Duration::from_millis(400)));
+        manager.add_plan(Duration::from_millis(300), 50);
+        let test_start = Instant::now();
+        manager.run().await.unwrap();
+
+        let timings = manager.segment_timings();
+        let directions: Vec<_> = timings.iter().map(|timing| timing.direction).collect();
+        assert_eq!(
+            directions,
+            vec![
+                RampDirection::RampUp,
+                RampDirection::Idle,
+                RampDirection::RampUp
+            ]
+        );
+
+        // The first 100ms of the idle segment covers VUs draining their last request.
+        let idle = &timings[1];
+        let quiet =
+            (idle.started_at + Duration::from_millis(100))..(idle.started_at + idle.elapsed);
+        let offsets: Vec<Duration> = received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|at| at.duration_since(test_start))
+            .collect();
+        assert!(offsets.iter().all(|at| !quiet.contains(at)), "{quiet:?}");
+        assert!(offsets.iter().any(|at| *at < idle.started_at));
+        assert!(offsets
+            .iter()
+            .any(|at| *at > idle.started_at + idle.elapsed));
+        // Both ramps spawn 50 VUs, each sending one uncounted warm-up request.
+        assert_eq!(
+            manager.get_overall_metrics().total_requests() + 100,
+            offsets.len()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_overrides_address_but_keeps_host() {
+        let mock_server = MockServer::start().await;
+        let addr = *mock_server.address();
+        let logical_host = format!("rperf.invalid:{}", addr.port());
+        Mock::given(method("GET"))
+            .and(header("host", logical_host.as_str()))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&format!("http://{}/", logical_host))
+            .resolve("rperf.invalid", addr);
+        assert!(!config.uses_default_client());
+
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 1);
+        manager.add_plan(Duration::from_millis(150), 1);
+        manager.run().await.unwrap();
+
+        let metrics = manager.get_overall_metrics();
+        assert!(metrics.status_code_counts.get(&200).copied().unwrap_or(0) > 0);
+        assert_eq!(metrics.status_code_counts.get(&404), None);
+        assert_eq!(metrics.total_errors(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_hosts_resolver_reaches_unlisted_hostname() {
+        let mock_server = MockServer::start().await;
+        let addr = *mock_server.address();
+        Mock::given(method("GET"))
+            .and(header(
+                "host",
+                format!("api.rperf.test:{}", addr.port()).as_str(),
+            ))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        for latency_breakdown in [false, true] {
+            let config = VirtualUserConfig::new(&format!("http://API.rperf.test:{}/", addr.port()))
+                .dns_resolver(DnsResolver::hosts([("api.rperf.test", addr.ip())]))
+                .latency_breakdown(latency_breakdown);
+            let mut manager = VirtualUserManager::new(config);
+            manager
+                .run_constant(1, Duration::from_millis(100))
+                .await
+                .unwrap();
+
+            let metrics = manager.get_overall_metrics();
+            assert!(metrics.status_code_counts.get(&200).copied().unwrap_or(0) > 0);
+            assert_eq!(metrics.total_errors(), 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remote_addr_is_recorded_per_backend() {
+        let (first, second) = (MockServer::start().await, MockServer::start().await);
+        for server in [&first, &second] {
+            Mock::given(method("GET"))
+                .respond_with(ResponseTemplate::new(200))
+                .mount(server)
+                .await;
+        }
+
+        let config = VirtualUserConfig::new(&first.uri())
+            .endpoint(RequestSpec::new(&first.uri()), 1)
+            .endpoint(RequestSpec::new(&second.uri()), 1);
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 1);
+        manager.add_plan(Duration::from_millis(150), 1);
+        manager.run().await.unwrap();
+
+        let metrics = manager.get_overall_metrics();
+        let counts = &metrics.remote_addr_counts;
+        assert!(
+            counts.get(first.address()).is_some_and(|count| *count > 0),
+            "{counts:?}"
+        );
+        assert!(
+            counts.get(second.address()).is_some_and(|count| *count > 0),
+            "{counts:?}"
+        );
+        assert_eq!(
+            counts.values().sum::<usize>(),
+            metrics.status_code_counts[&200]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_injected_delay_is_kept_out_of_request_time() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .inject_delay(1.0, ThinkTime::Constant(Duration::from_millis(40)));
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 1);
+        manager.add_plan(Duration::from_millis(400), 1);
+        manager.run().await.unwrap();
+
+        let metrics = manager.get_overall_metrics();
+        let requests = metrics.total_requests();
+        assert!(requests > 2);
+        assert!(metrics.injected_delay.count >= requests);
+        assert!((metrics.injected_delay.average().unwrap() - 0.04).abs() < 1e-9);
+        assert!(metrics.http_request_time.max().unwrap() < 0.03);
+    }
+
+    #[tokio::test]
+    async fn test_gzip_body_is_sent_compressed() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("content-encoding", "gzip"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let payload = r#"{"items":[1,2,3,4,5,6,7,8]}"#.repeat(50);
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .method(Method::POST)
+            .body(payload.clone())
+            .gzip_body(true);
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 1);
+        manager.add_plan(Duration::from_millis(100), 1);
+        manager.run().await.unwrap();
+
+        let metrics = manager.get_overall_metrics();
+        let requests = metrics.status_code_counts[&200];
+        assert!(requests > 0);
+        assert_eq!(metrics.uncompressed_upload_bytes, requests * payload.len());
+        assert!(metrics.upload_bytes < metrics.uncompressed_upload_bytes / 4);
+
+        let received = mock_server.received_requests().await.unwrap();
+        let body = &received
+            .iter()
+            .find(|r| r.method.as_str() == "POST")
+            .unwrap()
+            .body;
+        let mut decoded = String::new();
+        GzDecoder::new(body.as_slice())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, payload);
+    }
+
+    #[tokio::test]
+    async fn test_body_file_is_sent() {
+        let path = std::env::temp_dir().join(format!("rperf-body-{}.json", std::process::id()));
+        fs::write(&path, r#"{"payload":"from file"}"#).unwrap();
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string(r#"{"payload":"from file"}"#))
+            .respond_with(ResponseTemplate::new(201))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .method(Method::POST)
+            .body_file(&path)
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 1);
+        manager.add_plan(Duration::from_millis(150), 1);
+        manager.run().await.unwrap();
+
+        let metrics = manager.get_overall_metrics();
+        assert!(metrics.status_code_counts.get(&201).copied().unwrap_or(0) > 0);
+        assert_eq!(metrics.status_code_counts.get(&404), None);
+
+        let missing = VirtualUserConfig::new(&mock_server.uri()).body_file(&path);
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_run_result_evaluates_thresholds() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(30)))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .threshold(Threshold::ErrorRate { max: 0.0 })
+            .threshold(Threshold::LatencyPercentile {
+                q: 0.95,
+                max: Duration::from_millis(10),
+            });
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(50), 1);
+        manager.add_plan(Duration::from_millis(200), 1);
+        let result = manager.run().await.unwrap();
+
+        assert!(result.threshold_results[0].passed);
+        assert!(!result.threshold_results[1].passed);
+        assert_eq!(result.exit_code(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_run_without_successes_fails() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .threshold(Threshold::ErrorRate { max: 0.0 })
+            .threshold(Threshold::MinSuccesses { count: 1 });
+        let mut manager = VirtualUserManager::new(config);
+        let result = manager
+            .run_constant(1, Duration::from_millis(100))
+            .await
+            .unwrap();
+
+        assert!(!result.aborted);
+        assert!(manager.get_overall_metrics().total_requests() > 0);
+        assert!(result.threshold_results[0].passed);
+        assert_eq!(result.threshold_results[1].actual, Some(0.0));
+        assert!(!result.passed());
+        assert_eq!(result.exit_code(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_min_successes_counts_what_the_run_result_does() {
+        let mock_server = MockServer::start().await;
+        Mock::given(path("/cached"))
+            .respond_with(ResponseTemplate::new(304))
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/embedded-error"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("error"))
+            .mount(&mock_server)
+            .await;
+
+        for (route, should_pass) in [("/cached", true), ("/embedded-error", false)] {
+            let config = VirtualUserConfig::new(&format!("{}{}", mock_server.uri(), route))
+                .success_predicate(|_, body| body != b"error")
+                .threshold(Threshold::MinSuccesses { count: 1 });
+            let mut manager = VirtualUserManager::new(config);
+            let result = manager
+                .run_constant(1, Duration::from_millis(100))
+                .await
+                .unwrap();
+
+            assert!(manager.get_overall_metrics().total_requests() > 0);
+            assert_eq!(
+                result.threshold_results[0].actual,
+                Some(result.successful_requests as f64)
+            );
+            assert_eq!(result.threshold_results[0].passed, should_pass, "{route}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_strict_negotiation_counts_mismatched_content_type() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(wiremock::matchers::path("/json"))
+            .and(header("accept", "application/json"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "application/json; charset=utf-8"),
+            )
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(wiremock::matchers::path("/html"))
+            .and(header("accept", "application/json"))
+            .respond_with(ResponseTemplate::new(200).insert_header("content-type", "text/html"))
+            .mount(&mock_server)
+            .await;
+
+        for (path, mismatched) in [("/json", false), ("/html", true)] {
+            let config = VirtualUserConfig::new(&format!("{}{path}", mock_server.uri()))
+                .accept("application/json")
+                .strict_negotiation(true);
+            let mut manager = VirtualUserManager::new(config);
+            manager
+                .run_constant(1, Duration::from_millis(200))
+                .await
+                .unwrap();
+
+            let metrics = manager.get_overall_metrics();
+            let total = metrics.total_requests();
+            assert!(total > 0);
+            assert_eq!(metrics.status_code_counts.get(&200), Some(&total));
+            let expected = if mismatched { total } else { 0 };
+            assert_eq!(metrics.negotiation_failures, expected, "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prewarmed_connections_skip_connect_during_run() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(5)))
+            .mount(&mock_server)
+            .await;
+
+        // A replay sends no warm-up requests, so only prewarming can have opened connections.
+        let line = format!(r#"{{"url": "{}"}}"#, mock_server.uri());
+        let path = std::env::temp_dir().join(format!("rperf-prewarm-{}.jsonl", std::process::id()));
+        fs::write(&path, vec![line; 8].join("\n")).unwrap();
+
+        for prewarm in [0, 8] {
+            let config = VirtualUserConfig::new(&mock_server.uri())
+                .latency_breakdown(true)
+                .shared_client(true)
+                .prewarm_connections(prewarm);
+            let mut manager = VirtualUserManager::new(config);
+            manager
+                .replay(&path, ReplayPacing::AsFastAsPossible)
+                .await
+                .unwrap();
+
+            let phases = &manager.get_overall_metrics().latency_breakdown;
+            assert_eq!(phases.connect.count(), 8);
+            let slowest_connect = phases.connect.max().unwrap();
+            if prewarm == 0 {
+                assert!(slowest_connect > 0.0);
+            } else {
+                assert_eq!(slowest_connect, 0.0);
+                assert_eq!(phases.dns.max(), Some(0.0));
+            }
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_regions_split_vus_by_weight() {
+        let (east, west) = (MockServer::start().await, MockServer::start().await);
+        for server in [&east, &west] {
+            Mock::given(method("GET"))
+                .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(5)))
+                .mount(server)
+                .await;
+        }
+
+        let config = VirtualUserConfig::new("http://127.0.0.1:1/")
+            .region(Region::new("east", &east.uri(), 70))
+            .region(Region::new("west", &west.uri(), 30));
+        let mut manager = VirtualUserManager::new(config);
+        manager
+            .run_constant(10, Duration::from_millis(500))
+            .await
+            .unwrap();
+
+        let east_metrics = manager.region_metrics("east").unwrap();
+        let west_metrics = manager.region_metrics("west").unwrap();
+        assert_eq!(east_metrics.tags["region"], "east");
+        assert_eq!(west_metrics.tags["region"], "west");
+        // Each VU sends an uncounted warm-up and may have one request cut off at stop, so a
+        // region with n VUs sees between n and 2n more requests than it counted.
+        for (server, metrics, vus) in [(&east, east_metrics, 7), (&west, west_metrics, 3)] {
+            let extra = server.received_requests().await.unwrap().len() - metrics.total_requests();
+            assert!(
+                (vus..=2 * vus).contains(&extra),
+                "{extra} extra for {vus} VUs"
+            );
+        }
+
+        let overall = manager.get_overall_metrics().total_requests();
+        assert_eq!(
+            overall,
+            east_metrics.total_requests() + west_metrics.total_requests()
+        );
+        let share = east_metrics.total_requests() as f64 / overall as f64;
+        assert!((0.6..0.8).contains(&share), "east share {share}");
+    }
+
+    // The tenth request stalls; records when it arrived.
+    struct StallTenth(
+        std::sync::atomic::AtomicUsize,
+        Arc<std::sync::Mutex<Option<Instant>>>,
+    );
+
+    impl Respond for StallTenth {
+        fn respond(&self, _: &wiremock::Request) -> ResponseTemplate {
+            let seen = self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            if seen == 10 {
+                *self.1.lock().unwrap() = Some(Instant::now());
+                ResponseTemplate::new(200).set_delay(Duration::from_millis(200))
+            } else {
+                ResponseTemplate::new(200)
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_latency_max_timestamp_points_at_slow_request() {
+        let mock_server = MockServer::start().await;
+        let stalled_at = Arc::new(std::sync::Mutex::new(None));
+        Mock::given(method("GET"))
+            .respond_with(StallTenth(Default::default(), stalled_at.clone()))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri()).track_latency_timestamps(true);
+        let mut manager = VirtualUserManager::new(config);
+        manager
+            .run_constant(1, Duration::from_millis(500))
+            .await
+            .unwrap();
+
+        let metrics = manager.get_overall_metrics();
+        let (max, _) = metrics.latency_extremes.unwrap().max.unwrap();
+        assert!(max >= 0.2);
+        let max_at = metrics.latency_max_at().unwrap();
+        assert!(max_at <= metrics.run_duration.unwrap());
+        assert!(metrics.latency_min_at().unwrap() <= metrics.run_duration.unwrap());
+
+        let stalled_at = stalled_at.lock().unwrap().unwrap();
+        let expected =
+            stalled_at.duration_since(metrics.started_at.unwrap()) + Duration::from_millis(200);
+        let drift = max_at.abs_diff(expected);
+        assert!(
+            drift < Duration::from_millis(50),
+            "max at {max_at:?}, expected {expected:?}"
+        );
+    }
+
+    // Every eighth request stalls, each stall a little longer than the one before.
+    struct StallingTail(std::sync::atomic::AtomicUsize);
+
+    impl Respond for StallingTail {
+        fn respond(&self, _: &wiremock::Request) -> ResponseTemplate {
+            let seen = self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let template = ResponseTemplate::new(200);
+            if seen % 8 == 7 {
+                template.set_delay(Duration::from_millis(80 + seen as u64))
+            } else {
+                template
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slowest_requests_are_the_highest_latencies() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(StallingTail(Default::default()))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/tail", mock_server.uri());
+        let config = VirtualUserConfig::new(&url).track_slowest(3);
+        let mut manager = VirtualUserManager::new(config);
+        manager
+            .run_constant(4, Duration::from_millis(600))
+            .await
+            .unwrap();
+
+        let metrics = manager.get_overall_metrics();
+        let slowest = metrics.slowest(5);
+        assert_eq!(slowest.len(), 3);
+        assert_eq!(slowest[0].latency, metrics.total_latency.max);
+        assert!(slowest
+            .windows(2)
+            .all(|pair| pair[0].latency >= pair[1].latency));
+        // Only stalled requests can make the cut once at least three have stalled.
+        for request in &slowest {
+            assert!(request.latency >= 0.08, "{request:?}");
+            assert_eq!(request.url, url);
+            assert_eq!(request.status, Some(200));
+        }
+    }
+
+    // Latency jumps once more than `limit` requests are in flight, like a saturated backend.
+    struct ConcurrencyKnee {
+        limit: usize,
+        in_flight_until: std::sync::Mutex<Vec<Instant>>,
+    }
+
+    impl Respond for ConcurrencyKnee {
+        fn respond(&self, _: &wiremock::Request) -> ResponseTemplate {
+            let now = Instant::now();
+            let mut in_flight = self.in_flight_until.lock().unwrap();
+            in_flight.retain(|until| *until > now);
+            let delay = if in_flight.len() >= self.limit {
+                Duration::from_millis(100)
+            } else {
+                Duration::from_millis(10)
+            };
+            in_flight.push(now + delay);
+            ResponseTemplate::new(200).set_delay(delay)
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_find_capacity_lands_near_knee() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ConcurrencyKnee {
+                limit: 4,
+                in_flight_until: std::sync::Mutex::new(Vec::new()),
+            })
+            .mount(&mock_server)
+            .await;
+
+        let search = CapacitySearch::new(100.0, 800.0)
+            .threshold(Threshold::LatencyPercentile {
+                q: 0.95,
+                max: Duration::from_millis(50),
+            })
+            .probe_duration(Duration::from_secs(3))
+            .tolerance(100.0);
+        let mut manager = VirtualUserManager::new(VirtualUserConfig::new(&mock_server.uri()));
+        let result = manager.find_capacity(&search).await.unwrap();
+
+        // Four in-flight requests of ~10ms each sustain roughly 300-400 rps.
+        let max_rps = result.max_rps.unwrap();
+        assert!(
+            (200.0..=500.0).contains(&max_rps),
+            "{max_rps} from {:?}",
+            result.probes
+        );
+        assert!(result.probes.iter().any(|probe| !probe.passed));
+        assert!(result
+            .probes
+            .iter()
+            .filter(|probe| !probe.passed)
+            .all(|probe| probe.target_rps > max_rps));
+        let metrics = result.metrics.unwrap();
+        assert!(metrics.latency_percentile(0.95).unwrap() <= 0.05);
+        assert!(manager.running_vus.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_run_rps_converges_to_target() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(20)))
+            .mount(&mock_server)
+            .await;
+
+        let target_rps = 200.0;
+        let config = VirtualUserConfig::new(&mock_server.uri());
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_rps_plan(Duration::from_secs(5), target_rps);
+        manager.run_rps().await.unwrap();
+
+        let measured_rps = manager.get_measured_rps().unwrap();
+        assert!(
+            (measured_rps - target_rps).abs() / target_rps < 0.3,
+            "measured {measured_rps} rps, target {target_rps} rps"
+        );
+        assert!(manager.get_overall_metrics().http_request_time.count() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_connections_are_recycled_at_configured_cadence() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        // Keep-alive server that closes only when asked, so every accept is a client reconnect.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let new_connections = Arc::new(AtomicUsize::new(0));
+        let accepted = new_connections.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                accepted.fetch_add(1, Ordering::Relaxed);
+                tokio::spawn(async move {
+                    let mut pending = Vec::new();
+                    let mut buf = [0u8; 1024];
+                    loop {
+                        let Ok(n) = stream.read(&mut buf).await else {
+                            return;
+                        };
+                        if n == 0 {
+                            return;
+                        }
+                        pending.extend_from_slice(&buf[..n]);
+                        while let Some(end) = pending.windows(4).position(|w| w == b"\r\n\r\n") {
+                            let head = String::from_utf8_lossy(&pending[..end]).to_lowercase();
+                            pending.drain(..end + 4);
+                            let close = head.contains("connection: close");
+                            let response = if close {
+                                "HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok"
+                            } else {
+                                "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok"
+                            };
+                            if stream.write_all(response.as_bytes()).await.is_err() || close {
+                                return;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        let config = VirtualUserConfig::new(&format!("http://127.0.0.1:{}", port))
+            .recycle_connections(ConnectionRecycle::after_requests(10).jitter(0.2));
+        let mut manager = VirtualUserManager::new(config);
+        manager
+            .run_constant(1, Duration::from_millis(400))
+            .await
+            .unwrap();
+
+        let metrics = manager.get_overall_metrics();
+        let requests = metrics.total_requests();
+        let recycles = metrics.connection_recycles;
+        assert!(
+            recycles >= 5,
+            "only {recycles} recycles over {requests} requests"
+        );
+        let per_connection = requests as f64 / recycles as f64;
+        assert!(
+            (8.0..=12.5).contains(&per_connection),
+            "{per_connection} per connection"
+        );
+        let connections = new_connections.load(Ordering::Relaxed);
+        assert!(
+            (recycles..=recycles + 2).contains(&connections),
+            "{connections} connections for {recycles} recycles"
+        );
+    }
+
+    #[cfg(feature = "alarm")]
+    #[tokio::test]
+    async fn test_p99_alarm_is_logged_when_over_budget() {
+        #[derive(Clone, Default)]
+        struct Capture(Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Capture {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(40)))
+            .mount(&mock_server)
+            .await;
+
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .p99_alarm(Duration::from_millis(10), Duration::from_secs(1));
+        let alarm = config.latency_alarm.clone().unwrap();
+        let mut manager = VirtualUserManager::new(config);
+        manager
+            .run_constant(2, Duration::from_millis(600))
+            .await
+            .unwrap();
+
+        let output = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            output.matches("p99 latency over budget").count(),
+            1,
+            "{output}"
+        );
+        assert!(output.contains("WARN"));
+        assert_eq!(alarm.alarms(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_fail_fast_stops_on_first_error() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri()).fail_fast(true);
+        let mut manager = VirtualUserManager::new(config);
+        let started = Instant::now();
+        let result = manager.run_constant(4, Duration::from_secs(5)).await;
+
+        assert!(started.elapsed() < Duration::from_secs(1));
+        match result {
+            Err(RunError::FailFast(failure)) => assert!(failure.contains("HTTP 500"), "{failure}"),
+            other => panic!("expected fail-fast stop, got {other:?}"),
+        }
+        assert!(manager.get_overall_metrics().total_requests() >= 1);
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_once_byte_budget_is_spent() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0u8; 1000]))
+            .mount(&mock_server)
+            .await;
+
+        let vus = 2;
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .think_time(ThinkTime::Constant(Duration::from_millis(5)))
+            .max_bytes(Some(20_000));
+        let mut manager = VirtualUserManager::new(config);
+        let started = Instant::now();
+        manager
+            .run_constant(vus, Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let metrics = manager.get_overall_metrics();
+        // Each VU may finish one request it started just before the budget ran out.
+        assert!(
+            (20_000..=20_000 + vus * 1000).contains(&metrics.download_bytes),
+            "{} bytes",
+            metrics.download_bytes
+        );
+        assert_eq!(metrics.download_bytes, metrics.total_requests() * 1000);
+    }
+
+    #[tokio::test]
+    async fn test_max_duration_cuts_long_plan_short() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .max_duration(Some(Duration::from_millis(300)));
+        let mut manager = VirtualUserManager::new(config.clone());
+        manager.add_plan(Duration::from_millis(50), 2);
+        manager.add_plan(Duration::from_secs(30), 2);
+        let started = Instant::now();
+        let result = manager.run().await.unwrap();
+
+        let elapsed = started.elapsed();
+        assert!(result.duration_cap_reached);
+        assert!(
+            (Duration::from_millis(300)..Duration::from_millis(600)).contains(&elapsed),
+            "{elapsed:?}"
+        );
+        assert!(manager.get_overall_metrics().total_requests() > 0);
+
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::from_millis(100), 1);
+        assert!(!manager.run().await.unwrap().duration_cap_reached);
+    }
+
+    #[tokio::test]
+    async fn test_per_segment_metrics_split_the_run() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(20)))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri());
+        let mut manager = VirtualUserManager::new(config);
+        manager.add_plan(Duration::ZERO, 1);
+        manager.add_plan(Duration::from_millis(400), 1);
+        manager.add_plan(Duration::ZERO, 4);
+        manager.add_plan(Duration::from_millis(400), 4);
+        let (_, segments) = manager.run_per_segment_metrics().await.unwrap();
+
+        assert_eq!(segments.len(), 4);
+        let (one_vu, four_vus) = (&segments[1], &segments[3]);
+        assert!(one_vu.total_requests() > 0);
+        assert!(
+            four_vus.total_requests() > 2 * one_vu.total_requests(),
+            "{} vs {}",
+            four_vus.total_requests(),
+            one_vu.total_requests()
+        );
+        assert!(one_vu.run_duration.unwrap() >= Duration::from_millis(400));
+        // Nothing is counted twice; only requests finishing after the last boundary are extra.
+        let in_segments: usize = segments.iter().map(Metrics::total_requests).sum();
+        let overall = manager.get_overall_metrics().total_requests();
+        assert!(
+            (in_segments..=in_segments + 4).contains(&overall),
+            "{overall} overall, {in_segments} in segments"
+        );
+        assert_eq!(
+            segments
+                .iter()
+                .map(|m| m.http_request_time.count())
+                .sum::<usize>(),
+            in_segments
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_connection_probe_stops_fail_fast_run() {
+        // Nothing listens on port 1, so the connection is refused.
+        let config = VirtualUserConfig::new("http://127.0.0.1:1")
+            .record_connection_probe(true)
+            .fail_fast(true);
+        let mut manager = VirtualUserManager::new(config);
+        let started = Instant::now();
+        let result = manager.run_constant(2, Duration::from_secs(5)).await;
+
+        assert!(started.elapsed() < Duration::from_secs(1));
+        match result {
+            Err(RunError::FailFast(failure)) => {
+                assert!(failure.contains("connection probe failed"), "{failure}")
+            }
+            other => panic!("expected fail-fast stop, got {other:?}"),
+        }
+        let metrics = manager.get_overall_metrics();
+        assert!(metrics.failed_connection_probes >= 1);
+        assert_eq!(metrics.failed_connection_probes, metrics.connection_probes);
+        assert_eq!(metrics.total_requests(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_report_interval_calls_back_during_run() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(5)))
+            .mount(&mock_server)
+            .await;
+
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = reports.clone();
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .report_interval(Duration::from_millis(200), move |metrics| {
+                recorded.lock().unwrap().push(metrics.total_requests())
+            });
+        let mut manager = VirtualUserManager::new(config);
+        manager
+            .run_constant(2, Duration::from_secs(1))
+            .await
+            .unwrap();
+
+        let reports = reports.lock().unwrap();
+        assert!((4..=5).contains(&reports.len()), "{reports:?}");
+        assert!(
+            reports.windows(2).all(|pair| pair[0] <= pair[1]),
+            "{reports:?}"
+        );
+        assert!(reports[0] > 0);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_snapshots_end_with_overall_metrics() {
+        use futures_core::Stream;
+        use std::pin::Pin;
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(5)))
+            .mount(&mock_server)
+            .await;
+
+        let mut manager = VirtualUserManager::new(VirtualUserConfig::new(&mock_server.uri()));
+        let mut stream = manager.metrics_snapshots(Duration::from_millis(100));
+        let collector = tokio::spawn(async move {
+            let mut samples = Vec::new();
+            while let Some(sample) =
+                std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await
+            {
+                samples.push(sample);
+            }
+            samples
+        });
+        manager
+            .run_constant(2, Duration::from_millis(500))
+            .await
+            .unwrap();
+
+        let samples = collector.await.unwrap();
+        let requests: Vec<_> = samples
+            .iter()
+            .map(|sample| sample.metrics.total_requests())
+            .collect();
+        assert!((4..=7).contains(&samples.len()), "{requests:?}");
+        assert!(
+            requests.windows(2).all(|pair| pair[0] <= pair[1]),
+            "{requests:?}"
+        );
+        let elapsed: Vec<_> = samples.iter().map(|sample| sample.elapsed).collect();
+        assert!(
+            elapsed[0] >= Duration::from_millis(100) && elapsed[0] < Duration::from_millis(250),
+            "{elapsed:?}"
+        );
+        assert!(
+            samples
+                .windows(2)
+                .all(|pair| pair[0].elapsed < pair[1].elapsed
+                    && pair[0].taken_at <= pair[1].taken_at),
+            "{elapsed:?}"
+        );
+        let (last, overall) = (
+            &samples.last().unwrap().metrics,
+            manager.get_overall_metrics(),
+        );
+        assert!(samples.last().unwrap().elapsed >= overall.run_duration.unwrap());
+        assert_eq!(last.total_requests(), overall.total_requests());
+        assert_eq!(
+            last.http_request_time.count(),
+            overall.http_request_time.count()
+        );
+        assert_eq!(last.status_code_counts, overall.status_code_counts);
+        assert_eq!(last.run_duration, overall.run_duration);
+    }
+
+    #[tokio::test]
+    async fn test_vu_rate_plan_ramps_rps_at_fixed_vus() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let mut manager = VirtualUserManager::new(VirtualUserConfig::new(&mock_server.uri()));
+        manager.add_vu_rate_plan(Duration::from_secs(1), 10.0);
+        manager.add_vu_rate_plan(Duration::from_secs(1), 50.0);
+        manager.add_vu_rate_plan(Duration::from_secs(1), 50.0);
+        manager.run_vu_rate(2).await.unwrap();
+
+        assert_eq!((manager.spawned_vus, manager.peak_vus), (2, 2));
+        // Per 1s window and 2 VUs: 10 rps each, then a ramp averaging 30, then 50.
+        let counts = manager
+            .get_overall_metrics()
+            .rps_summary
+            .window_counts()
+            .to_vec();
+        assert!(counts.len() >= 3, "{counts:?}");
+        assert!((15..=25).contains(&counts[0]), "{counts:?}");
+        assert!((45..=75).contains(&counts[1]), "{counts:?}");
+        assert!((85..=105).contains(&counts[2]), "{counts:?}");
+    }
+
+    #[tokio::test]
+    async fn test_run_result_counts_successes_and_failures() {
+        let mock_server = MockServer::start().await;
+        Mock::given(path("/missing"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/broken"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/cached"))
+            .respond_with(ResponseTemplate::new(304))
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/embedded-error"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("error"))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let endpoint = |name: &str| RequestSpec::new(&format!("{}{}", mock_server.uri(), name));
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .success_predicate(|_, body| body != b"error")
+            .endpoint(endpoint("/ok"), 2)
+            .endpoint(endpoint("/cached"), 1)
+            .endpoint(endpoint("/embedded-error"), 1)
+            .endpoint(endpoint("/missing"), 1)
+            .endpoint(endpoint("/broken"), 1)
+            .endpoint(RequestSpec::new("http://127.0.0.1:1/refused"), 1);
+        let mut manager = VirtualUserManager::new(config);
+        let result = manager
+            .run_constant(2, Duration::from_millis(300))
+            .await
+            .unwrap();
+
+        let metrics = manager.get_overall_metrics();
+        assert_eq!(result.total_requests, metrics.total_requests());
+        assert_eq!(
+            result.successful_requests + result.failed_requests,
+            result.total_requests
+        );
+        // The predicate only rejects the embedded errors; 304s are successes.
+        let (not_modified, assertion_failures) = (
+            metrics.status_code_counts[&304],
+            metrics.assertion_failures(),
+        );
+        assert!(not_modified > 0 && assertion_failures > 0);
+        assert_eq!(
+            result.successful_requests,
+            metrics.status_code_counts[&200] - assertion_failures + not_modified
+        );
+        let failed_responses = metrics.status_code_counts[&404] + metrics.status_code_counts[&503];
+        assert!(failed_responses > 0 && metrics.total_errors() > 0);
+        assert_eq!(
+            result.failed_requests,
+            failed_responses + assertion_failures + metrics.total_errors()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_run_returns_partial_metrics() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(10)))
+            .mount(&mock_server)
+            .await;
+
+        let mut manager = VirtualUserManager::new(VirtualUserConfig::new(&mock_server.uri()));
+        manager.add_plan(Duration::ZERO, 2);
+        manager.add_plan(Duration::from_secs(10), 2);
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            sleep(Duration::from_millis(300)).await;
+            canceller.cancel();
+        });
+        let started = Instant::now();
+        let result = manager.run_cancellable(token).await.unwrap();
+
+        assert!(
+            started.elapsed() < Duration::from_secs(1),
+            "{:?}",
+            started.elapsed()
+        );
+        assert!(result.aborted);
+        assert_eq!(result.exit_code(), 2);
+        let metrics = manager.get_overall_metrics();
+        assert!(metrics.total_requests() > 0);
+        assert!(metrics.run_duration.unwrap() < Duration::from_secs(1));
+
+        // The token only applies to the run it was passed to.
+        manager.plans = vec![PlanSegment::new(Duration::from_millis(100), 1)];
+        assert!(!manager.run().await.unwrap().aborted);
+    }
+
+    #[tokio::test]
+    async fn test_aborted_run_still_closes_metrics_snapshots() {
+        use futures_core::Stream;
+        use std::pin::Pin;
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(500).set_delay(Duration::from_millis(150)))
+            .mount(&mock_server)
+            .await;
+
+        let path = std::env::temp_dir().join(format!("rperf-aborted-{}.json", std::process::id()));
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .fail_fast(true)
+            .output_file(&path);
+        let mut manager = VirtualUserManager::new(config);
+        let mut stream = manager.metrics_snapshots(Duration::from_millis(50));
+        let collector = tokio::spawn(async move {
+            let mut samples = Vec::new();
+            while let Some(sample) =
+                std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await
+            {
+                samples.push(sample);
+            }
+            samples
+        });
+        let result = manager.run_constant(2, Duration::from_secs(5)).await;
+        assert!(matches!(result, Err(RunError::FailFast(_))), "{result:?}");
+
+        let samples = tokio::time::timeout(Duration::from_secs(1), collector)
+            .await
+            .expect("snapshot stream should close once the run aborts")
+            .unwrap();
+        // The periodic snapshots came before the first response; the final one has it.
+        assert!(samples.len() >= 2, "{}", samples.len());
+        let (last, overall) = (
+            &samples.last().unwrap().metrics,
+            manager.get_overall_metrics(),
+        );
+        assert!(last.total_requests() >= 1);
+        assert_eq!(last.total_requests(), overall.total_requests());
+        assert_eq!(last.status_code_counts, overall.status_code_counts);
+        assert!(last.run_duration.is_some());
+
+        let summary: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(summary["requests"], overall.total_requests());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn test_plan_transitions_are_logged() {
+        #[derive(Clone, Default)]
+        struct Capture(Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Capture {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let mut manager = VirtualUserManager::new(VirtualUserConfig::new(&mock_server.uri()));
+        manager.add_plan(Duration::from_millis(100), 2);
+        manager.add_plan(Duration::from_millis(100), 2);
+        manager.add_plan(Duration::from_millis(100), 0);
+        manager.run().await.unwrap();
+
+        let output = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        let transitions: Vec<_> = output
+            .lines()
+            .filter(|line| line.contains("plan transition"))
+            .collect();
+        assert_eq!(transitions.len(), 3, "{output}");
+        for (line, (segment, target, direction)) in
+            transitions
+                .iter()
+                .zip([(0, 2, "RampUp"), (1, 2, "Hold"), (2, 0, "RampDown")])
+        {
+            assert!(line.contains(&format!("segment={segment} ")), "{line}");
+            assert!(line.contains(&format!("target={target} ")), "{line}");
+            assert!(line.contains(&format!("direction={direction} ")), "{line}");
+            assert!(line.contains("elapsed="), "{line}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connect_and_request_failures_are_counted_apart() {
+        use tokio::io::AsyncReadExt;
+        use tokio::net::TcpListener;
+
+        // Accepts the connection, reads the request, then resets instead of answering.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let _ = stream.read(&mut [0u8; 1024]).await;
+                let _ = stream.set_linger(Some(Duration::ZERO));
+            }
+        });
+
+        let mut counts = Vec::new();
+        for url in [
+            format!("http://127.0.0.1:{port}"),
+            "http://127.0.0.1:1".to_string(),
+        ] {
+            let mut manager = VirtualUserManager::new(VirtualUserConfig::new(&url));
+            manager
+                .run_constant(1, Duration::from_millis(100))
+                .await
+                .unwrap();
+            let metrics = manager.get_overall_metrics();
+            assert!(metrics.total_requests() > 0);
+            assert_eq!(metrics.total_errors(), metrics.total_requests());
+            counts.push((metrics.connect_failures, metrics.request_failures));
+        }
+
+        let [(reset_connect, reset_request), (refused_connect, refused_request)] = counts[..]
+        else {
+            unreachable!()
+        };
+        assert_eq!(reset_connect, 0);
+        assert!(reset_request > 0);
+        assert!(refused_connect > 0);
+        assert_eq!(refused_request, 0);
+    }
+
+    #[tokio::test]
+    async fn test_progress_rises_toward_one_during_run() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let mut manager = VirtualUserManager::new(VirtualUserConfig::new(&mock_server.uri()));
+        manager.add_plan(Duration::from_millis(250), 2);
+        manager.add_plan(Duration::from_millis(250), 2);
+        assert_eq!(manager.progress(), 0.0);
+        let progress = manager.progress_handle();
+        let poller = tokio::spawn(async move {
+            let mut samples = Vec::new();
+            for _ in 0..8 {
+                sleep(Duration::from_millis(50)).await;
+                samples.push(progress.fraction());
+            }
+            samples
+        });
+        manager.run().await.unwrap();
+
+        let samples = poller.await.unwrap();
+        assert!(
+            samples.windows(2).all(|pair| pair[0] < pair[1]),
+            "{samples:?}"
+        );
+        assert!(samples[0] > 0.0 && samples[7] < 1.0, "{samples:?}");
+        assert_eq!(manager.progress(), 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_fake_data_fills_each_request_body() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri())
+            .method(Method::POST)
+            .body(r#"{"email": "{random_email}", "age": {random_int:18:65}}"#)
+            .fake_data(true);
+        let mut manager = VirtualUserManager::new(config);
+        manager
+            .run_constant(2, Duration::from_millis(200))
+            .await
+            .unwrap();
+
+        let bodies: Vec<serde_json::Value> = mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|request| request.method == Method::POST)
+            .map(|request| serde_json::from_slice(&request.body).unwrap())
+            .collect();
+        assert!(bodies.len() >= 20, "only {} requests", bodies.len());
+        let mut emails = std::collections::HashSet::new();
+        for body in &bodies {
+            let email = body["email"].as_str().unwrap();
+            let (local, domain) = email.split_once('@').unwrap();
+            assert_eq!(domain, "example.com");
+            assert!(!local.is_empty(), "{email}");
+            assert!(
+                local
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.'),
+                "{email}"
+            );
+            emails.insert(email.to_string());
+            let age = body["age"].as_i64().unwrap();
+            assert!((18..=65).contains(&age), "{age}");
+        }
+        assert!(emails.len() > 1);
+    }
+
+    #[tokio::test]
+    async fn test_raw_samples_stay_within_capacity() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let config = VirtualUserConfig::new(&mock_server.uri()).track_raw_samples(5);
+        let mut manager = VirtualUserManager::new(config);
+        manager
+            .run_constant(3, Duration::from_millis(300))
+            .await
+            .unwrap();
+
+        let metrics = manager.get_overall_metrics();
+        assert!(metrics.total_requests() > 5);
+        let raw = metrics.raw_latencies();
+        assert_eq!(raw.len(), 5);
+        assert!(raw
+            .iter()
+            .all(|latency| *latency >= metrics.total_latency.min
+                && *latency <= metrics.total_latency.max));
+    }
+
+    #[tokio::test]
+    async fn test_run_waits_for_healthy_target() {
+        let mock_server = MockServer::start().await;
+        Mock::given(path("/health"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/health"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/down"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let healthy = SuccessPredicate::new(|status, body| status == 200 && body == b"ok");
+        let config = VirtualUserConfig::new(&mock_server.uri());
+        let mut manager = VirtualUserManager::new(config);
+        let start = Instant::now();
+        manager
+            .wait_for_healthy(
+                &format!("{}/health", mock_server.uri()),
+                healthy.clone(),
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+        assert!(start.elapsed() >= HEALTH_CHECK_INTERVAL * 2);
+        let checks = mock_server.received_requests().await.unwrap().len();
+        assert_eq!(checks, 3);
+
+        manager
+            .run_constant(1, Duration::from_millis(100))
+            .await
+            .unwrap();
+        assert!(manager.get_overall_metrics().total_requests() > 0);
+
+        let down = format!("{}/down", mock_server.uri());
+        let err = manager
+            .wait_for_healthy(&down, healthy, Duration::from_millis(600))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RunError::Unhealthy { url, .. } if url == down));
+    }
+}
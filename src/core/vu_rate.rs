@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// The request rate each VU holds itself to, shared by every VU in a run so the manager can
+// ramp it while they are running. Stored as `f64` bits, since there is no atomic float.
+#[derive(Debug)]
+pub struct VuRate(AtomicU64);
+
+impl VuRate {
+    pub fn new(rps_per_vu: f64) -> Self {
+        Self(AtomicU64::new(rps_per_vu.to_bits()))
+    }
+
+    pub fn set(&self, rps_per_vu: f64) {
+        self.0.store(rps_per_vu.to_bits(), Ordering::Release);
+    }
+
+    pub fn rps_per_vu(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Acquire))
+    }
+
+    // When a VU whose last request started at `started_at` may send the next one.
+    pub fn next_start(&self, started_at: Instant) -> Instant {
+        started_at + Duration::from_secs_f64(1.0 / self.rps_per_vu())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_start_follows_rate_changes() {
+        let rate = VuRate::new(10.0);
+        let started_at = Instant::now();
+        assert_eq!(
+            rate.next_start(started_at),
+            started_at + Duration::from_millis(100)
+        );
+        rate.set(f64::INFINITY);
+        assert_eq!(rate.next_start(started_at), started_at);
+    }
+}
@@ -0,0 +1,80 @@
+use rand::Rng;
+
+// Cumulative weights searched with a binary search; zero-weight entries are never picked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WeightedSelector {
+    cumulative: Vec<u64>,
+}
+
+impl WeightedSelector {
+    pub fn new(weights: impl IntoIterator<Item = u32>) -> Option<Self> {
+        let mut total = 0u64;
+        let cumulative: Vec<u64> = weights
+            .into_iter()
+            .map(|weight| {
+                total += u64::from(weight);
+                total
+            })
+            .collect();
+        (total > 0).then_some(Self { cumulative })
+    }
+
+    pub fn len(&self) -> usize {
+        self.cumulative.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cumulative.is_empty()
+    }
+
+    pub fn total_weight(&self) -> u64 {
+        self.cumulative.last().copied().unwrap_or(0)
+    }
+
+    pub fn pick<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        let target = rng.gen_range(0..self.total_weight());
+        self.cumulative.partition_point(|&bound| bound <= target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_frequencies_match_weights() {
+        let weights = [1, 0, 2, 3, 4];
+        let selector = WeightedSelector::new(weights).unwrap();
+        let mut rng = StdRng::seed_from_u64(152);
+        let draws = 200_000;
+        let mut counts = [0usize; 5];
+        for _ in 0..draws {
+            counts[selector.pick(&mut rng)] += 1;
+        }
+
+        assert_eq!(counts[1], 0);
+        for (count, weight) in counts.iter().zip(weights) {
+            let expected = weight as f64 / 10.0;
+            let observed = *count as f64 / draws as f64;
+            assert!(
+                (observed - expected).abs() < 0.005,
+                "weight {weight}: {observed}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_same_seed_gives_same_order() {
+        let selector = WeightedSelector::new([5, 1, 1]).unwrap();
+        let order = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..64).map(|_| selector.pick(&mut rng)).collect::<Vec<_>>()
+        };
+        assert_eq!(order(7), order(7));
+        assert_ne!(order(7), order(8));
+        assert!(WeightedSelector::new([0, 0]).is_none());
+        assert!(WeightedSelector::new(Vec::new()).is_none());
+    }
+}
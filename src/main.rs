@@ -1,4 +1,4 @@
-use rperf::core::virtual_user_manager::{VirtualUserConfig, VirtualUserManager};
+use rperf::core::virtual_user_manager::{RunError, VirtualUserConfig, VirtualUserManager};
 
 #[tokio::main]
 async fn main() {
@@ -8,9 +8,19 @@ async fn main() {
     let mut virtual_user_manager = VirtualUserManager::new(config);
     virtual_user_manager.add_plan(std::time::Duration::from_secs(10), 120);
     
-    virtual_user_manager.run().await;
+    let result = virtual_user_manager.run().await;
 
-    let metrics = virtual_user_manager.get_overall_metrics();
-    println!("{:#?}", metrics);
-    println!("ave total_latency: {}", metrics.total_latency.average().unwrap());
+    // Config and client errors stop the run before any request is sent.
+    if !matches!(result, Err(RunError::Config(_) | RunError::Client(_))) {
+        let metrics = virtual_user_manager.get_overall_metrics();
+        print!("{}", metrics.text_report());
+    }
+
+    match result {
+        Ok(result) => std::process::exit(result.exit_code()),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
 }
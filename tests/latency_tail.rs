@@ -0,0 +1,29 @@
+mod test_support;
+
+use std::time::Duration;
+
+use rperf::core::virtual_user_manager::{VirtualUserConfig, VirtualUserManager};
+use test_support::{latency_backend, LatencyProfile};
+
+#[tokio::test]
+async fn test_measured_p99_reflects_injected_tail() {
+    let tail = Duration::from_millis(200);
+    let server =
+        latency_backend(LatencyProfile::new(Duration::from_millis(10)).tail(0.02, tail)).await;
+
+    let mut manager = VirtualUserManager::new(VirtualUserConfig::new(&server.uri()));
+    manager.run_iterations(1000, 10).await.unwrap();
+
+    let metrics = manager.get_overall_metrics();
+    assert_eq!(metrics.total_requests(), 1000);
+    let p50 = metrics.latency_percentile(0.5).unwrap();
+    assert!((0.01..0.05).contains(&p50), "p50 {p50}");
+    // 2% of requests land in the tail, so p99 falls inside it. The histogram is exact to
+    // within its 2% bucket width; the rest of the allowance is client overhead.
+    let p99 = metrics.latency_percentile(0.99).unwrap();
+    assert!(
+        (tail.as_secs_f64() * 0.98..tail.as_secs_f64() * 1.1).contains(&p99),
+        "p99 {p99}"
+    );
+    assert_eq!(metrics.json_report()["latency"]["p99"], p99);
+}
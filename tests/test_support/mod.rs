@@ -0,0 +1,65 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use wiremock::matchers::any;
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+// Response delays for a mock backend: `base` for most requests, `delay` for a `fraction` of
+// them. Tail responses are spread evenly rather than at random, so any n requests in a row
+// include `n * fraction` of them, give or take one.
+#[derive(Debug, Clone, Copy)]
+pub struct LatencyProfile {
+    pub base: Duration,
+    pub tail: Option<(f64, Duration)>,
+}
+
+impl LatencyProfile {
+    pub fn new(base: Duration) -> Self {
+        Self { base, tail: None }
+    }
+
+    pub fn tail(mut self, fraction: f64, delay: Duration) -> Self {
+        if !(0.0..=1.0).contains(&fraction) {
+            panic!("tail fraction must be in [0, 1], got {fraction}");
+        }
+
+        self.tail = Some((fraction, delay));
+        self
+    }
+
+    fn delay(&self, index: usize) -> Duration {
+        match self.tail {
+            Some((fraction, delay))
+                if ((index + 1) as f64 * fraction).floor() > (index as f64 * fraction).floor() =>
+            {
+                delay
+            }
+            _ => self.base,
+        }
+    }
+}
+
+struct ProfiledDelay {
+    profile: LatencyProfile,
+    served: AtomicUsize,
+}
+
+impl Respond for ProfiledDelay {
+    fn respond(&self, _: &Request) -> ResponseTemplate {
+        let index = self.served.fetch_add(1, Ordering::Relaxed);
+        ResponseTemplate::new(200).set_delay(self.profile.delay(index))
+    }
+}
+
+// A mock server answering every request with a 200 after a delay drawn from `profile`.
+pub async fn latency_backend(profile: LatencyProfile) -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(any())
+        .respond_with(ProfiledDelay {
+            profile,
+            served: AtomicUsize::new(0),
+        })
+        .mount(&server)
+        .await;
+    server
+}